@@ -0,0 +1,40 @@
+// computes the SHA-256 of the web assets embedded by the internal feature
+// so that the binary can verify them at startup, and records the build time
+// shown in the startup banner (and rejects builds with both internal and internal-dynamic)
+
+use ring::digest::{digest, SHA256};
+use std::{env, fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+const ASSETS: &[&str] = &[
+    "index.html",
+    "game.js",
+    "game.css",
+    "pkg/ai_wargame_web.js",
+    "pkg/ai_wargame_web_bg.wasm",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // rebuilt sources get a new timestamp (SOURCE_DATE_EPOCH for reproducible builds)
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let build_time = env::var("SOURCE_DATE_EPOCH").ok().and_then(|secs| secs.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}",build_time);
+    let embedded = env::var_os("CARGO_FEATURE_INTERNAL").is_some();
+    if embedded && env::var_os("CARGO_FEATURE_INTERNAL_DYNAMIC").is_some() {
+        panic!("the internal and internal-dynamic features are mutually exclusive (build with --no-default-features --features internal-dynamic)");
+    }
+    if !embedded {
+        return;
+    }
+    let web_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("../ai_wargame_web");
+    for asset in ASSETS {
+        let path = web_dir.join(asset);
+        println!("cargo:rerun-if-changed={}",path.display());
+        let bytes = fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {e}",path.display()));
+        let hash: String = digest(&SHA256, &bytes).as_ref().iter().map(|b| format!("{:02x}",b)).collect();
+        let name: String = asset.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+        println!("cargo:rustc-env=ASSET_{}_HASH={}",name,hash);
+    }
+}
@@ -0,0 +1,479 @@
+// certificates from an ACME CA (Let's Encrypt by default) validated with the HTTP-01
+// challenge (RFC 8555): with tls.acme_domain, the certificate and its key are kept in
+// tls.acme_cache instead of tls.cert and tls.key, obtained at startup when missing and
+// renewed by the cleaner when they expire soon
+
+use axum::{Router, extract::{Path, State}, response::{IntoResponse, Response}, routing::get};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL};
+use hyper::{Body, HeaderMap, Method, Request, StatusCode, header};
+use ring::{digest, rand::SystemRandom, signature::{self, EcdsaKeyPair, KeyPair}};
+use serde_json::{json, Value};
+use tokio::{io::{AsyncRead, AsyncWrite}, net::TcpStream, sync::oneshot, time::sleep};
+use tokio_rustls::{TlsConnector, rustls::{ClientConfig, RootCertStore, ServerName}};
+use tracing::{info, warn, error};
+use std::{collections::HashMap, fmt, net::SocketAddr, path::{Path as FilePath, PathBuf}, sync::{Arc, RwLock, atomic::{AtomicBool, Ordering}}, time::Duration};
+use crate::{ConfigTLS, certcheck, der::{der, pem, DER_BIT_STRING, DER_INTEGER, DER_OCTET_STRING, DER_OID, DER_SEQUENCE, DER_SET, DER_UTF8_STRING}, mtls::read_certs, sni::{SniResolver, FALLBACK_NAME}, snapshot::iso8601, unix_time_secs};
+
+pub const DEFAULT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+pub const DEFAULT_CACHE: &str = "./acme-cache/";
+pub const DEFAULT_CA_FILE: &str = "/etc/ssl/certs/ca-certificates.crt";
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/:token";
+// renewal check when the cleaner is not running
+pub const RENEWAL_CHECK_SECS: u64 = 12 * 60 * 60;
+// Let's Encrypt certificates are valid for 90 days
+const RENEW_BEFORE_SECS: u64 = 30 * 86400;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+
+const DER_CONTEXT_0: u8 = 0xa0;
+const DER_DNS_NAME: u8 = 0x82;
+// 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+// 1.2.840.10045.2.1 with the curve 1.2.840.10045.3.1.7
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+// 1.2.840.10045.4.3.2
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+// 1.2.840.113549.1.9.14
+const OID_EXTENSION_REQUEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+// 2.5.29.17
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+// key authorizations of the pending challenges, keyed by token
+#[derive(Default,Debug)]
+pub struct Challenges(RwLock<HashMap<String,String>>);
+
+impl Challenges {
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.0.read().ok()?.get(token).cloned()
+    }
+    fn insert(&self, token: &str, key_authorization: String) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).insert(token.to_string(), key_authorization);
+    }
+    fn remove(&self, token: &str) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).remove(token);
+    }
+}
+
+// fetched by the CA without authentication
+pub async fn http01_challenge(
+    Path(token): Path<String>,
+    State(challenges): State<Arc<Challenges>>,
+) -> Response {
+    match challenges.key_authorization(&token) {
+        Some(key_authorization) => ([(header::CONTENT_TYPE, "application/octet-stream")], key_authorization).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub struct AcmeClient {
+    domain: String,
+    contact: Option<String>,
+    directory: String,
+    cache: PathBuf,
+    // None when the directory is plain http (test CAs)
+    tls: Option<TlsConnector>,
+    challenges: Arc<Challenges>,
+    // the cleaner does not start a renewal while another one is running
+    renewing: AtomicBool,
+}
+
+impl fmt::Debug for AcmeClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcmeClient").field("domain", &self.domain).field("directory", &self.directory).finish()
+    }
+}
+
+impl AcmeClient {
+    pub fn new(config: &ConfigTLS, challenges: Arc<Challenges>) -> Result<Self,String> {
+        let domain = config.acme_domain.clone().unwrap_or_default().to_ascii_lowercase();
+        if domain.is_empty() || !domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+            return Err(format!("tls.acme_domain {domain:?} is not a valid domain name"));
+        }
+        let directory = config.acme_directory.clone().unwrap_or_else(|| String::from(DEFAULT_DIRECTORY));
+        let tls = match directory.starts_with("https://") {
+            true => {
+                let ca_file = PathBuf::from(config.acme_ca_file.as_deref().unwrap_or(DEFAULT_CA_FILE));
+                let certs: Vec<Vec<u8>> = read_certs(&ca_file).map_err(|e| e.to_string())?.into_iter().map(|cert| cert.0).collect();
+                let mut roots = RootCertStore::empty();
+                if roots.add_parsable_certificates(&certs).0 == 0 {
+                    return Err(format!("no CA certificate found in {:?} (tls.acme_ca_file)",ca_file));
+                }
+                let config = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
+                Some(TlsConnector::from(Arc::new(config)))
+            },
+            false => None,
+        };
+        let cache = PathBuf::from(config.acme_cache.as_deref().unwrap_or(DEFAULT_CACHE));
+        std::fs::create_dir_all(&cache).map_err(|e| format!("cannot create {:?}: {e}",cache))?;
+        Ok(AcmeClient {
+            domain,
+            contact: config.acme_contact.clone(),
+            directory,
+            cache,
+            tls,
+            challenges,
+            renewing: AtomicBool::new(false),
+        })
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn cert_path(&self) -> PathBuf {
+        self.cache.join(format!("{}.crt",self.domain))
+    }
+
+    pub fn key_path(&self) -> PathBuf {
+        self.cache.join(format!("{}.key",self.domain))
+    }
+
+    // missing, expiring within RENEW_BEFORE_SECS or not the pair of the key (ex: left by a
+    // crash while they were replaced, the SNI resolver would refuse to load them)
+    pub fn needs_renewal(&self) -> bool {
+        !self.key_path().exists()
+            || certcheck::cert_matches_key(&self.cert_path(), &self.key_path()).is_err()
+            || certcheck::cert_not_after(&self.cert_path())
+                .map_or(true, |not_after| not_after < unix_time_secs().saturating_add(RENEW_BEFORE_SECS))
+    }
+
+    // PKCS#8 account key, created on first use
+    fn account_key(&self) -> Result<Vec<u8>,String> {
+        let path = self.cache.join("account.key");
+        if path.exists() {
+            return read_private_key(&path);
+        }
+        let key = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+            .map_err(|_| String::from("cannot generate the ACME account key"))?;
+        write_private_key(&path, key.as_ref())?;
+        Ok(key.as_ref().to_vec())
+    }
+
+    // places an order for the domain and replaces the certificate and key in the cache
+    pub async fn obtain(&self) -> Result<(),String> {
+        info!("requesting a certificate for {} from {}",self.domain,self.directory);
+        let mut session = Session::start(self).await?;
+        let new_order = session.new_order.clone();
+        let created = session.post(&new_order, Some(json!({ "identifiers": [{ "type": "dns", "value": self.domain }] }))).await?;
+        let order_url = created.location.clone().ok_or("no order URL in the ACME reply")?;
+        let order = created.json();
+        for authorization in order["authorizations"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            session.authorize(authorization).await?;
+        }
+        let (key, csr) = certificate_request(&self.domain)?;
+        let finalize = order["finalize"].as_str().ok_or("no finalize URL in the ACME order")?;
+        session.post(finalize, Some(json!({ "csr": BASE64URL.encode(csr) }))).await?;
+        let order = session.poll(&order_url).await?;
+        if order["status"] != "valid" {
+            return Err(format!("order {order_url} is {}: {}",order["status"],order["error"]["detail"]));
+        }
+        let certificate = order["certificate"].as_str().ok_or("no certificate URL in the ACME order")?;
+        let chain = session.post(certificate, None).await?.body;
+        // both are written before either replaces the current pair (a failed write leaves it as is)
+        let key_tmp = write_tmp_file(&self.key_path(), pem("PRIVATE KEY", &key).as_bytes(), true)?;
+        let cert_tmp = write_tmp_file(&self.cert_path(), &chain, false)?;
+        rename_file(&key_tmp, &self.key_path())?;
+        rename_file(&cert_tmp, &self.cert_path())?;
+        match certcheck::cert_not_after(&self.cert_path()) {
+            Ok(not_after) => info!("obtained a certificate for {} valid until {}",self.domain,iso8601(not_after)),
+            Err(e) => warn!("obtained a certificate for {} that cannot be read: {e}",self.domain),
+        }
+        Ok(())
+    }
+
+    // obtain with a temporary plain http server for the challenges (nothing else is served until it is done)
+    pub async fn obtain_serving_challenges(&self, addr: SocketAddr) -> Result<(),String> {
+        let app = Router::new()
+            .route(CHALLENGE_PATH, get(http01_challenge))
+            .with_state(self.challenges.clone());
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = axum::Server::try_bind(&addr).map_err(|e| format!("cannot listen on {addr} for the ACME challenges: {e}"))?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async { shutdown_rx.await.ok(); });
+        warn!("answering ACME challenges on http://{addr} until the certificate of {} is obtained",self.domain);
+        let server = tokio::spawn(server);
+        let result = self.obtain().await;
+        let _ = shutdown_tx.send(());
+        let _ = server.await;
+        result
+    }
+
+    async fn send(&self, method: Method, url: &str, body: Option<Value>) -> Result<(StatusCode,HeaderMap,Vec<u8>),String> {
+        let uri: hyper::Uri = url.parse().map_err(|_| format!("invalid URL {url:?}"))?;
+        let (Some(host), Some(authority)) = (uri.host(), uri.authority()) else {
+            return Err(format!("invalid URL {url:?}"));
+        };
+        let https = uri.scheme_str() == Some("https");
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri.path_and_query().map_or("/", |path| path.as_str()))
+            .header(header::HOST, authority.as_str())
+            .header(header::USER_AGENT, concat!("ai_wargame_broker/", env!("CARGO_PKG_VERSION")));
+        let request = match body {
+            Some(body) => builder.header(header::CONTENT_TYPE, "application/jose+json").body(Body::from(body.to_string())),
+            None => builder.body(Body::empty()),
+        }.map_err(|e| e.to_string())?;
+        let exchange = async {
+            let tcp = TcpStream::connect((host, port)).await.map_err(|e| e.to_string())?;
+            if !https {
+                return send_request(tcp, request).await;
+            }
+            let tls = self.tls.as_ref().ok_or("https URL from a plain http ACME directory")?;
+            let server_name = ServerName::try_from(host).map_err(|e| e.to_string())?;
+            send_request(tls.connect(server_name, tcp).await.map_err(|e| e.to_string())?, request).await
+        };
+        tokio::time::timeout(REQUEST_TIMEOUT, exchange).await
+            .map_err(|_| format!("{url}: request timed out"))?
+            .map_err(|e| format!("{url}: {e}"))
+    }
+}
+
+// one HTTP/1.1 request per connection (a few requests per certificate)
+pub async fn send_request<T>(io: T, request: Request<Body>) -> Result<(StatusCode,HeaderMap,Vec<u8>),String>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(io).await.map_err(|e| e.to_string())?;
+    tokio::spawn(async move { let _ = connection.await; });
+    let response = sender.send_request(request).await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body()).await.map_err(|e| e.to_string())?;
+    Ok((status, headers, body.to_vec()))
+}
+
+struct AcmeResponse {
+    location: Option<String>,
+    body: Vec<u8>,
+}
+
+impl AcmeResponse {
+    fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+// requests signed with the account key (JWS with ES256)
+struct Session<'a> {
+    client: &'a AcmeClient,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    new_nonce: String,
+    new_order: String,
+    // account URL, sent instead of the public key once the account exists
+    kid: Option<String>,
+    // returned by the previous request
+    nonce: Option<String>,
+}
+
+impl<'a> Session<'a> {
+    // the account is created on first use (or found again from the key)
+    async fn start(client: &'a AcmeClient) -> Result<Session<'a>,String> {
+        let (status, _, body) = client.send(Method::GET, &client.directory, None).await?;
+        if !status.is_success() {
+            return Err(format!("{}: {status}",client.directory));
+        }
+        let directory: Value = serde_json::from_slice(&body).map_err(|e| format!("invalid ACME directory: {e}"))?;
+        let url = |name: &str| directory[name].as_str().map(String::from).ok_or_else(|| format!("no {name} in the ACME directory"));
+        let key = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &client.account_key()?)
+            .map_err(|_| String::from("invalid ACME account key"))?;
+        let mut session = Session {
+            client,
+            key,
+            rng: SystemRandom::new(),
+            new_nonce: url("newNonce")?,
+            new_order: url("newOrder")?,
+            kid: None,
+            nonce: None,
+        };
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(contact) = client.contact.as_deref() {
+            account["contact"] = json!([format!("mailto:{contact}")]);
+        }
+        let response = session.post(&url("newAccount")?, Some(account)).await?;
+        session.kid = Some(response.location.ok_or("no account URL in the ACME reply")?);
+        Ok(session)
+    }
+
+    // members in lexicographic order as required for the thumbprint (RFC 7638)
+    fn jwk(&self) -> Value {
+        let point = self.key.public_key().as_ref();
+        json!({ "crv": "P-256", "kty": "EC", "x": BASE64URL.encode(&point[1..33]), "y": BASE64URL.encode(&point[33..]) })
+    }
+
+    fn thumbprint(&self) -> String {
+        BASE64URL.encode(digest::digest(&digest::SHA256, self.jwk().to_string().as_bytes()))
+    }
+
+    // payload None for POST-as-GET
+    fn sign(&self, url: &str, payload: Option<&Value>, nonce: &str) -> Result<Value,String> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match self.kid.as_deref() {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = BASE64URL.encode(protected.to_string());
+        let payload = payload.map(|payload| BASE64URL.encode(payload.to_string())).unwrap_or_default();
+        let signature = self.key.sign(&self.rng, format!("{protected}.{payload}").as_bytes())
+            .map_err(|_| String::from("cannot sign the ACME request"))?;
+        Ok(json!({ "protected": protected, "payload": payload, "signature": BASE64URL.encode(signature.as_ref()) }))
+    }
+
+    async fn post(&mut self, url: &str, payload: Option<Value>) -> Result<AcmeResponse,String> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.fetch_nonce().await?,
+            };
+            let body = self.sign(url, payload.as_ref(), &nonce)?;
+            let (status, headers, body) = self.client.send(Method::POST, url, Some(body)).await?;
+            let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(String::from);
+            self.nonce = header("replay-nonce");
+            if status.is_success() {
+                return Ok(AcmeResponse { location: header(header::LOCATION.as_str()), body });
+            }
+            let problem: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+            // the CA can reject a nonce at any time, the new one it returned is tried once
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(format!("{url}: {status} {}",problem["detail"].as_str().unwrap_or_default()));
+        }
+    }
+
+    async fn fetch_nonce(&self) -> Result<String,String> {
+        let (_, headers, _) = self.client.send(Method::HEAD, &self.new_nonce, None).await?;
+        headers.get("replay-nonce").and_then(|nonce| nonce.to_str().ok()).map(String::from)
+            .ok_or_else(|| format!("no nonce from {}",self.new_nonce))
+    }
+
+    // POST-as-GET until the object is no longer pending or processing
+    async fn poll(&mut self, url: &str) -> Result<Value,String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let object = self.post(url, None).await?.json();
+            if !(object["status"] == "pending" || object["status"] == "processing") {
+                return Ok(object);
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Err(format!("{url} still pending after {}s",POLL_INTERVAL.as_secs() * POLL_ATTEMPTS as u64))
+    }
+
+    // answers the http-01 challenge of the authorization and waits until the CA has checked it
+    async fn authorize(&mut self, url: &str) -> Result<(),String> {
+        let authorization = self.post(url, None).await?.json();
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+        let challenge = authorization["challenges"].as_array().into_iter().flatten()
+            .find(|challenge| challenge["type"] == "http-01")
+            .ok_or_else(|| format!("{url} has no http-01 challenge"))?;
+        let (Some(token), Some(challenge_url)) = (challenge["token"].as_str(), challenge["url"].as_str()) else {
+            return Err(format!("invalid http-01 challenge in {url}"));
+        };
+        let challenges = self.client.challenges.clone();
+        challenges.insert(token, format!("{token}.{}",self.thumbprint()));
+        let result = async {
+            self.post(challenge_url, Some(json!({}))).await?;
+            self.poll(url).await
+        }.await;
+        challenges.remove(token);
+        let authorization = result?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+        let error = authorization["challenges"].as_array().into_iter().flatten()
+            .find(|challenge| challenge["type"] == "http-01")
+            .and_then(|challenge| challenge["error"]["detail"].as_str())
+            .unwrap_or_default();
+        Err(format!("authorization of {} is {}: {error}",self.client.domain,authorization["status"]))
+    }
+}
+
+// new P-256 key (PKCS#8) and the DER certificate signing request for the domain
+fn certificate_request(domain: &str) -> Result<(Vec<u8>,Vec<u8>),String> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| String::from("cannot generate the certificate key"))?;
+    let key = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+        .map_err(|_| String::from("cannot generate the certificate key"))?;
+    // the common name is limited to 64 characters (the CA only uses the subjectAltName)
+    let subject = match domain.len() <= 64 {
+        true => der(DER_SET, &der(DER_SEQUENCE, &[der(DER_OID, OID_COMMON_NAME), der(DER_UTF8_STRING, domain.as_bytes())].concat())),
+        false => vec![],
+    };
+    let algorithm = der(DER_SEQUENCE, &[der(DER_OID, OID_EC_PUBLIC_KEY), der(DER_OID, OID_P256)].concat());
+    let public_key = der(DER_BIT_STRING, &[&[0], key.public_key().as_ref()].concat());
+    let alt_names = der(DER_SEQUENCE, &der(DER_DNS_NAME, domain.as_bytes()));
+    let extensions = der(DER_SEQUENCE, &der(DER_SEQUENCE, &[der(DER_OID, OID_SUBJECT_ALT_NAME), der(DER_OCTET_STRING, &alt_names)].concat()));
+    let info = der(DER_SEQUENCE, &[
+        der(DER_INTEGER, &[0]),
+        der(DER_SEQUENCE, &subject),
+        der(DER_SEQUENCE, &[algorithm, public_key].concat()),
+        der(DER_CONTEXT_0, &der(DER_SEQUENCE, &[der(DER_OID, OID_EXTENSION_REQUEST), der(DER_SET, &extensions)].concat())),
+    ].concat());
+    let signature = key.sign(&rng, &info).map_err(|_| String::from("cannot sign the certificate request"))?;
+    let csr = der(DER_SEQUENCE, &[
+        info,
+        der(DER_SEQUENCE, &der(DER_OID, OID_ECDSA_SHA256)),
+        der(DER_BIT_STRING, &[&[0], signature.as_ref()].concat()),
+    ].concat());
+    Ok((pkcs8.as_ref().to_vec(), csr))
+}
+
+fn read_private_key(path: &FilePath) -> Result<Vec<u8>,String> {
+    let pem = std::fs::read(path).map_err(|e| format!("cannot read {:?}: {e}",path))?;
+    match rustls_pemfile::read_one(&mut pem.as_slice()) {
+        Ok(Some(rustls_pemfile::Item::PKCS8Key(key))) => Ok(key),
+        _ => Err(format!("no PKCS#8 private key in {:?}",path)),
+    }
+}
+
+// written next to the target then renamed (a crash never leaves a truncated file)
+fn write_file(path: &FilePath, contents: &[u8], private: bool) -> Result<(),String> {
+    rename_file(&write_tmp_file(path, contents, private)?, path)
+}
+
+// path of the written temporary file, to be renamed to path
+fn write_tmp_file(path: &FilePath, contents: &[u8], private: bool) -> Result<PathBuf,String> {
+    // name.crt.tmp and name.key.tmp (both exist at the same time in obtain)
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, contents).map_err(|e| format!("cannot write {:?}: {e}",tmp_path))?;
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600)).map_err(|e| format!("cannot restrict {:?}: {e}",tmp_path))?;
+    }
+    Ok(tmp_path)
+}
+
+fn rename_file(tmp_path: &FilePath, path: &FilePath) -> Result<(),String> {
+    std::fs::rename(tmp_path, path).map_err(|e| format!("cannot rename {:?} to {:?}: {e}",tmp_path,path))
+}
+
+fn write_private_key(path: &FilePath, pkcs8: &[u8]) -> Result<(),String> {
+    write_file(path, pem("PRIVATE KEY", pkcs8).as_bytes(), true)
+}
+
+// in the background so that the caller (the cleaner) is not delayed, failures are retried on the next call
+pub fn renew_if_needed(client: Arc<AcmeClient>, resolver: Option<Arc<SniResolver>>) {
+    if !client.needs_renewal() || client.renewing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async move {
+        let renewed = client.obtain().await
+            .and_then(|()| resolver.map_or(Ok(vec![]), |resolver| resolver.reload(Some(FALLBACK_NAME))));
+        match renewed {
+            Ok(_) => warn!("certificate of {} renewed",client.domain),
+            Err(e) => error!("cannot renew the certificate of {}: {e} (retrying on the next cleaner cycle)",client.domain),
+        }
+        client.renewing.store(false, Ordering::SeqCst);
+    });
+}
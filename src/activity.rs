@@ -0,0 +1,99 @@
+// recent events (turns posted, games created) counted by time bucket
+
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex, time::{Duration, Instant}};
+
+// events older than this are dropped (longer buckets are rejected)
+pub const DEFAULT_RETENTION_SECS: u64 = 60 * 60;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ActivityEvent {
+    TurnPosted,
+    GameCreated,
+}
+
+#[derive(Serialize,Default,Debug,Clone,PartialEq)]
+pub struct ActivityCounts {
+    pub turns_posted: usize,
+    pub games_created: usize,
+}
+
+#[derive(Debug)]
+pub struct ActivityLog {
+    // oldest first
+    events: Mutex<VecDeque<(Instant,ActivityEvent)>>,
+    retention: Duration,
+}
+
+impl Default for ActivityLog {
+    fn default() -> Self {
+        ActivityLog::new(Duration::from_secs(DEFAULT_RETENTION_SECS))
+    }
+}
+
+impl ActivityLog {
+    pub fn new(retention: Duration) -> Self {
+        ActivityLog { events: Mutex::new(VecDeque::new()), retention }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    // old events are also dropped here since the cleaner is optional
+    pub fn record(&self, event: ActivityEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            self.trim_events(&mut events);
+            events.push_back((Instant::now(), event));
+        }
+    }
+
+    fn trim_events(&self, events: &mut VecDeque<(Instant,ActivityEvent)>) {
+        while events.front().is_some_and(|(time,_)| time.elapsed() > self.retention) {
+            events.pop_front();
+        }
+    }
+
+    // number of events dropped
+    pub fn clear(&self) -> usize {
+        self.events.lock().map(|mut events| events.drain(..).count()).unwrap_or(0)
+    }
+
+    pub fn trim(&self) {
+        if let Ok(mut events) = self.events.lock() {
+            self.trim_events(&mut events);
+        }
+    }
+
+    // events of the last `window`
+    pub fn counts(&self, window: Duration) -> ActivityCounts {
+        self.trim();
+        let mut counts = ActivityCounts::default();
+        let Ok(events) = self.events.lock() else { return counts };
+        for (_,event) in events.iter().rev().take_while(|(time,_)| time.elapsed() <= window) {
+            match event {
+                ActivityEvent::TurnPosted => counts.turns_posted += 1,
+                ActivityEvent::GameCreated => counts.games_created += 1,
+            }
+        }
+        counts
+    }
+}
+
+// "30s", "5m" or "1h"
+pub fn parse_bucket(bucket: &str) -> Option<Duration> {
+    let unit_secs = match bucket.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        _ => return None,
+    };
+    let digits = &bucket[..bucket.len() - 1];
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match digits.parse::<u64>().ok()?.checked_mul(unit_secs)? {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
@@ -0,0 +1,98 @@
+// audit log of the API requests: one line per audited request (who, what, result)
+// at info level under AUDIT_TARGET, selected by the [audit] section
+
+use axum::{extract::{ConnectInfo, State}, http::{Method, Request}, middleware::Next, response::Response};
+use serde::Deserialize;
+use tracing::info;
+use std::{net::SocketAddr, sync::Arc};
+use crate::UserName;
+
+pub const AUDIT_TARGET: &str = "ai_wargame_broker::audit";
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditLevel {
+    // every request
+    All,
+    // POST, PUT, PATCH and DELETE on any path
+    WritesOnly,
+    // every request under /admin
+    AdminOnly,
+    // POST, PUT, PATCH and DELETE under /admin
+    #[default]
+    AdminWrites,
+    Off,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+pub struct ConfigAudit {
+    pub level: AuditLevel,
+    // paths (and the paths below them) that are never audited, ex: "/admin/state"
+    pub exclude_paths: Vec<String>,
+    // methods that are never audited, ex: "GET"
+    pub exclude_methods: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct AuditFilter {
+    level: AuditLevel,
+    exclude_paths: Vec<String>,
+    exclude_methods: Vec<Method>,
+}
+
+impl AuditFilter {
+    pub fn new(config: &ConfigAudit) -> Result<Self,String> {
+        let exclude_methods = config.exclude_methods.iter()
+            .map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes()).map_err(|_| format!("audit.exclude_methods: invalid method {method:?}")))
+            .collect::<Result<_,_>>()?;
+        let exclude_paths = config.exclude_paths.iter()
+            .map(|path| match path.starts_with('/') {
+                true => Ok(path.trim_end_matches('/').to_string()),
+                false => Err(format!("audit.exclude_paths: {path:?} does not start with /")),
+            })
+            .collect::<Result<_,_>>()?;
+        Ok(AuditFilter { level: config.level, exclude_paths, exclude_methods })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.level != AuditLevel::Off
+    }
+
+    pub fn audits(&self, method: &Method, path: &str) -> bool {
+        let write = [Method::POST, Method::PUT, Method::PATCH, Method::DELETE].contains(method);
+        let admin = path == "/admin" || path.starts_with("/admin/");
+        let selected = match self.level {
+            AuditLevel::All => true,
+            AuditLevel::WritesOnly => write,
+            AuditLevel::AdminOnly => admin,
+            AuditLevel::AdminWrites => admin && write,
+            AuditLevel::Off => false,
+        };
+        let path = path.trim_end_matches('/');
+        let excluded = self.exclude_methods.contains(method) || self.exclude_paths.iter()
+            .any(|excluded| path == excluded || path.strip_prefix(excluded.as_str()).is_some_and(|rest| rest.starts_with('/')));
+        selected && !excluded
+    }
+}
+
+// after authentication (the user name is known) and after the handler (the status is known)
+pub async fn record<B>(
+    State(filter): State<Arc<AuditFilter>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !filter.audits(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let user = request.extensions().get::<UserName>().and_then(|UserName(name)| name.clone());
+    let addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+    let response = next.run(request).await;
+    info!(target: AUDIT_TARGET, "{method} {path} by {} from {}: {}",
+        user.as_deref().unwrap_or("(anonymous)"),
+        addr.map(|addr| addr.to_string()).unwrap_or_default(),
+        response.status());
+    response
+}
@@ -0,0 +1,269 @@
+// command-line client of the broker API, also meant as a short example of each call:
+//   broker-cli [--json] game new
+//   broker-cli [--json] game get GAME_ID
+//   broker-cli [--json] game post GAME_ID --from A0 --to B1 --turn 1
+//   broker-cli [--json] game delete GAME_ID
+//   broker-cli [--json] admin state
+//   broker-cli [--json] admin clear
+//   broker-cli [--json] verify --sig SIGNATURE [--pubkey PUBKEY.pem] EXPORT.json
+// verify checks a file saved from /admin/export?signed=true against its X-Broker-Signature (hex or base64),
+// with the public key of the broker (GET /admin/export/pubkey) unless --pubkey is given
+// the broker URL and the credentials are read from ~/.broker-cli.toml (url, username and password)
+// and can be overridden with BROKER_URL, BROKER_USERNAME and BROKER_PASSWORD
+
+#[allow(dead_code)]
+#[path = "../testutil.rs"]
+mod testutil;
+#[path = "../verify.rs"]
+mod verify;
+
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use std::{path::{Path, PathBuf}, process::ExitCode};
+use testutil::{TestClient, TestResponse};
+
+const DEFAULT_URL: &str = "http://127.0.0.1:8000";
+const USAGE: &str = "usage: broker-cli [--json] (game new | game get GAME_ID | game post GAME_ID --from A0 --to B1 --turn N | game delete GAME_ID | admin state | admin clear | verify --sig SIGNATURE [--pubkey PUBKEY.pem] EXPORT.json)";
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct CliConfig {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl CliConfig {
+    fn load() -> Result<Self,String> {
+        let path = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".broker-cli.toml"));
+        let mut config = match path.filter(|path| path.exists()) {
+            Some(path) => {
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("cannot read {path:?}: {e}"))?;
+                toml::from_str(&text).map_err(|e| format!("invalid {path:?}: {e}"))?
+            },
+            None => CliConfig::default(),
+        };
+        let env = |name: &str| std::env::var(name).ok();
+        config.url = env("BROKER_URL").or(config.url);
+        config.username = env("BROKER_USERNAME").or(config.username);
+        config.password = env("BROKER_PASSWORD").or(config.password);
+        Ok(config)
+    }
+
+    fn client(&self) -> TestClient {
+        let client = TestClient::new(self.url.as_deref().unwrap_or(DEFAULT_URL));
+        match (self.username.as_deref(), self.password.as_deref()) {
+            (Some(username), Some(password)) => client.with_auth(username, password),
+            _ => client,
+        }
+    }
+}
+
+enum Command {
+    GameNew,
+    GameGet(String),
+    GamePost { gameid: String, from: Value, to: Value, turn: u16 },
+    GameDelete(String),
+    AdminState,
+    AdminClear,
+    Verify { sig: Vec<u8>, pubkey: Option<PathBuf>, export: PathBuf },
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Result<Self,String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["game", "new"] => Ok(Command::GameNew),
+            ["game", "get", gameid] => Ok(Command::GameGet(gameid.to_string())),
+            ["game", "delete", gameid] => Ok(Command::GameDelete(gameid.to_string())),
+            ["game", "post", gameid, options @ ..] => {
+                let (mut from, mut to, mut turn) = (None, None, None);
+                for option in options.chunks(2) {
+                    match option {
+                        ["--from", coord] => from = Some(parse_coord(coord)?),
+                        ["--to", coord] => to = Some(parse_coord(coord)?),
+                        ["--turn", number] => turn = Some(number.parse().map_err(|_| format!("invalid turn number {number:?}"))?),
+                        _ => return Err(format!("unexpected option {:?}",option.join(" "))),
+                    }
+                }
+                match (from, to, turn) {
+                    (Some(from), Some(to), Some(turn)) => Ok(Command::GamePost { gameid: gameid.to_string(), from, to, turn }),
+                    _ => Err(String::from("game post requires --from, --to and --turn")),
+                }
+            },
+            ["admin", "state"] => Ok(Command::AdminState),
+            ["admin", "clear"] => Ok(Command::AdminClear),
+            ["verify", options @ .., export] => {
+                let (mut sig, mut pubkey) = (None, None);
+                for option in options.chunks(2) {
+                    match option {
+                        ["--sig", sig_text] => sig = Some(parse_signature(sig_text)?),
+                        ["--pubkey", path] => pubkey = Some(PathBuf::from(path)),
+                        _ => return Err(format!("unexpected option {:?}",option.join(" "))),
+                    }
+                }
+                let sig = sig.ok_or_else(|| String::from("verify requires --sig"))?;
+                Ok(Command::Verify { sig, pubkey, export: PathBuf::from(export) })
+            },
+            _ => Err(String::from(USAGE)),
+        }
+    }
+}
+
+// hex as printed by most tools, or base64 as sent in X-Broker-Signature
+fn parse_signature(text: &str) -> Result<Vec<u8>,String> {
+    let hex = |pair: &[u8]| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok());
+    if text.len().is_multiple_of(2) && text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return text.as_bytes().chunks(2).map(hex).collect::<Option<_>>().ok_or_else(|| format!("invalid signature {text:?}"));
+    }
+    BASE64.decode(text).map_err(|_| format!("invalid signature {text:?} (expected hex or base64)"))
+}
+
+async fn verify(client: &TestClient, sig: &[u8], pubkey: Option<&Path>, export: &Path, json_output: bool) -> Result<(),String> {
+    let export = std::fs::read(export).map_err(|e| format!("cannot read {export:?}: {e}"))?;
+    let pubkey = match pubkey {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("cannot read {path:?}: {e}"))?,
+        None => {
+            let response = client.get("/admin/export/pubkey").await?;
+            if response.status != StatusCode::OK {
+                return Err(format!("{}: cannot get the public key of the broker (signing not enabled?)",response.status));
+            }
+            response.body
+        },
+    };
+    let result = verify::verify(&pubkey, &export, sig);
+    match (&result, json_output) {
+        (_, true) => println!("{}",json!({ "valid": result.is_ok(), "error": result.as_ref().err() })),
+        (Ok(()), false) => println!("signature OK"),
+        (Err(_), false) => (),
+    }
+    result
+}
+
+// letter-number coordinates as shown by the broker: A0 is row 0 column 0, rows a to z follow Z
+fn parse_coord(coord: &str) -> Result<Value,String> {
+    let invalid = || format!("invalid coordinate {coord:?} (expected a row letter and a column number, ex: B3)");
+    let mut chars = coord.chars();
+    let row = match chars.next().ok_or_else(invalid)? {
+        c @ 'A'..='Z' => c as u8 - b'A',
+        c @ 'a'..='z' => c as u8 - b'a' + 26,
+        _ => return Err(invalid()),
+    };
+    let col: u8 = chars.as_str().parse().map_err(|_| invalid())?;
+    Ok(json!({ "row": row, "col": col }))
+}
+
+fn format_coord(coord: &Value) -> String {
+    let (Some(row), Some(col)) = (coord["row"].as_u64(), coord["col"].as_u64()) else { return String::new() };
+    match row {
+        0..=25 => format!("{}{col}",(b'A' + row as u8) as char),
+        26..=51 => format!("{}{col}",(b'a' + row as u8 - 26) as char),
+        _ => format!("({row},{col})"),
+    }
+}
+
+// columns padded to their widest cell
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let header: Vec<String> = header.iter().map(|cell| cell.to_string()).collect();
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String]| cells.iter().zip(&widths)
+        .map(|(cell, width)| format!("{cell:<width$}"))
+        .collect::<Vec<_>>().join("  ").trim_end().to_string();
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    println!("{}",line(&header));
+    println!("{}",line(&separator));
+    for row in rows {
+        println!("{}",line(row));
+    }
+}
+
+fn turn_row(gameid: Option<&str>, turn: &Value) -> Vec<String> {
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    let mut row: Vec<String> = gameid.map(String::from).into_iter().collect();
+    row.extend([text(&turn["turn"]), format_coord(&turn["from"]), format_coord(&turn["to"]), text(&turn["move_type"])]);
+    row
+}
+
+fn print_turn(turn: &Value) {
+    if turn.is_null() {
+        println!("no turn played");
+    } else {
+        print_table(&["Turn", "From", "To", "Type"], &[turn_row(None, turn)]);
+    }
+}
+
+// JSON replies are printed as is with --json, other replies (plain text) are printed as is in both modes
+fn print_reply(response: &TestResponse, json_output: bool, human: impl FnOnce(&Value)) {
+    let value = response.json();
+    if value.is_null() {
+        print!("{}",response.body);
+    } else if json_output {
+        println!("{}",serde_json::to_string_pretty(&value).unwrap_or_default());
+    } else {
+        human(&value);
+    }
+}
+
+async fn run(command: Command, client: &TestClient, json_output: bool) -> Result<(),String> {
+    let response = match &command {
+        Command::Verify { sig, pubkey, export } => return verify(client, sig, pubkey.as_deref(), export, json_output).await,
+        Command::GameNew => client.get("/game").await?,
+        Command::GameGet(gameid) => client.get(&format!("/game/{gameid}?envelope=true")).await?,
+        Command::GamePost { gameid, from, to, turn } => {
+            client.post(&format!("/game/{gameid}?envelope=true"), json!({ "from": from, "to": to, "turn": turn })).await?
+        },
+        Command::GameDelete(gameid) => client.delete(&format!("/game/{gameid}")).await?,
+        Command::AdminState => client.get("/games").await?,
+        Command::AdminClear => client.delete("/admin/clear").await?,
+    };
+    if response.status != StatusCode::OK {
+        let error = response.json()["error"].as_str().map(String::from).unwrap_or_else(|| response.body.trim().to_string());
+        return Err(format!("{}: {error}",response.status));
+    }
+    match command {
+        Command::GameNew if json_output => println!("{}",json!({ "id": response.body.trim() })),
+        Command::GameNew => println!("{}",response.body.trim()),
+        Command::GameGet(_) | Command::GamePost { .. } | Command::GameDelete(_) => {
+            print_reply(&response, json_output, |reply| print_turn(&reply["data"]));
+        },
+        Command::AdminState => print_reply(&response, json_output, |games| {
+            let rows: Vec<Vec<String>> = games.as_object().into_iter().flatten()
+                .map(|(gameid, turn)| turn_row(Some(gameid), turn))
+                .collect();
+            print_table(&["Game ID", "Turn", "From", "To", "Type"], &rows);
+        }),
+        Command::AdminClear if json_output => println!("{}",json!({ "message": response.body.trim() })),
+        Command::AdminClear => print!("{}",response.body),
+        Command::Verify { .. } => (),
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json_output = args.iter().any(|arg| arg == "--json");
+    args.retain(|arg| arg != "--json");
+    let result = match (Command::parse(&args), CliConfig::load()) {
+        (Ok(command), Ok(config)) => run(command, &config.client(), json_output).await,
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("broker-cli: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,209 @@
+// startup checks of the TLS certificate and key: a mismatched pair or an
+// expired certificate is reported clearly before anything is bound
+// (instead of a cryptic rustls error or failed handshakes)
+
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair};
+use tracing::{info, warn};
+use std::{fmt, path::Path};
+use crate::{mtls::{der_next, read_certs}, snapshot::iso8601, unix_time_secs};
+
+// a warning is logged when the certificate expires sooner than this
+const EXPIRY_WARNING_SECS: u64 = 30 * 86400;
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+const DER_UTC_TIME: u8 = 0x17;
+const DER_GENERALIZED_TIME: u8 = 0x18;
+// 1.2.840.113549.1.1.1
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+// 1.2.840.10045.2.1 with the curves 1.2.840.10045.3.1.7 (P-256) and 1.3.132.0.34 (P-384)
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+// 1.3.101.112
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum KeyType {
+    Rsa(usize),
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+    Unknown,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Rsa(bits) => write!(f, "RSA-{bits}"),
+            KeyType::EcdsaP256 => write!(f, "ECDSA-P256"),
+            KeyType::EcdsaP384 => write!(f, "ECDSA-P384"),
+            KeyType::Ed25519 => write!(f, "Ed25519"),
+            KeyType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// public key as encoded in the subjectPublicKey of a certificate
+struct PublicKey {
+    key_type: KeyType,
+    bytes: Vec<u8>,
+}
+
+struct CertInfo {
+    public_key: PublicKey,
+    // unix timestamps (seconds)
+    not_before: u64,
+    not_after: u64,
+}
+
+// size in bits of the modulus of a DER encoded RSAPublicKey
+fn rsa_bits(key: &[u8]) -> Option<usize> {
+    let (DER_SEQUENCE, key, _) = der_next(key)? else { return None };
+    let (_, modulus, _) = der_next(key)?;
+    let modulus: Vec<u8> = modulus.iter().copied().skip_while(|b| *b == 0).collect();
+    let first = *modulus.first()?;
+    Some(modulus.len() * 8 - first.leading_zeros() as usize)
+}
+
+fn parse_public_key(spki: &[u8]) -> Option<PublicKey> {
+    let (DER_SEQUENCE, algorithm, rest) = der_next(spki)? else { return None };
+    let (DER_BIT_STRING, bits, _) = der_next(rest)? else { return None };
+    // the first byte of a bit string is the number of unused bits (always 0 for keys)
+    let bytes = bits.get(1..)?.to_vec();
+    let (DER_OID, oid, parameters) = der_next(algorithm)? else { return None };
+    let curve = der_next(parameters).map(|(_, curve, _)| curve);
+    let key_type = match oid {
+        OID_RSA_ENCRYPTION => KeyType::Rsa(rsa_bits(&bytes)?),
+        OID_EC_PUBLIC_KEY if curve == Some(OID_P256) => KeyType::EcdsaP256,
+        OID_EC_PUBLIC_KEY if curve == Some(OID_P384) => KeyType::EcdsaP384,
+        OID_ED25519 => KeyType::Ed25519,
+        _ => KeyType::Unknown,
+    };
+    Some(PublicKey { key_type, bytes })
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // inverse of the civil date computation in snapshot::iso8601
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// UTCTime (YYMMDDHHMMSSZ) or GeneralizedTime (YYYYMMDDHHMMSSZ) as a unix timestamp
+pub fn parse_der_time(tag: u8, value: &[u8]) -> Option<u64> {
+    let value = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        DER_UTC_TIME if value.len() == 12 => {
+            let year: i64 = value[..2].parse().ok()?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &value[2..])
+        },
+        DER_GENERALIZED_TIME if value.len() == 14 => (value[..4].parse().ok()?, &value[4..]),
+        _ => return None,
+    };
+    if !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| rest[i..i+2].parse::<i64>().ok();
+    let days = days_from_civil(year, field(0)?, field(2)?);
+    let secs = days * 86400 + field(4)? * 3600 + field(6)? * 60 + field(8)?;
+    u64::try_from(secs).ok()
+}
+
+fn parse_cert(cert: &[u8]) -> Option<CertInfo> {
+    let (DER_SEQUENCE, cert, _) = der_next(cert)? else { return None };
+    let (DER_SEQUENCE, mut fields, _) = der_next(cert)? else { return None };
+    // skip the optional version ([0]) then serial, signature algorithm and issuer
+    if let Some((0xa0, _, rest)) = der_next(fields) {
+        fields = rest;
+    }
+    for _ in 0..3 {
+        fields = der_next(fields)?.2;
+    }
+    let (DER_SEQUENCE, validity, rest) = der_next(fields)? else { return None };
+    let (tag, not_before, validity) = der_next(validity)?;
+    let not_before = parse_der_time(tag, not_before)?;
+    let (tag, not_after, _) = der_next(validity)?;
+    let not_after = parse_der_time(tag, not_after)?;
+    // skip the subject
+    let (DER_SEQUENCE, spki, _) = der_next(der_next(rest)?.2)? else { return None };
+    Some(CertInfo { public_key: parse_public_key(spki)?, not_before, not_after })
+}
+
+// None if the key cannot be parsed by ring (ex: SEC1 "EC PRIVATE KEY" files)
+fn private_key_public_key(path: &Path) -> Result<Option<PublicKey>,String> {
+    use rustls_pemfile::Item;
+    let pem = std::fs::read(path).map_err(|e| format!("cannot read {:?}: {e}",path))?;
+    let item = rustls_pemfile::read_one(&mut pem.as_slice()).map_err(|e| format!("cannot read {:?}: {e}",path))?;
+    let rsa = |key_pair: RsaKeyPair| PublicKey {
+        key_type: KeyType::Rsa(rsa_bits(key_pair.public_key().as_ref()).unwrap_or(0)),
+        bytes: key_pair.public_key().as_ref().to_vec(),
+    };
+    Ok(match item {
+        Some(Item::RSAKey(der)) => RsaKeyPair::from_der(&der).ok().map(rsa),
+        Some(Item::PKCS8Key(der)) => RsaKeyPair::from_pkcs8(&der).ok().map(rsa)
+            .or_else(|| EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, &der).ok()
+                .map(|key_pair| PublicKey { key_type: KeyType::EcdsaP256, bytes: key_pair.public_key().as_ref().to_vec() }))
+            .or_else(|| EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P384_SHA384_ASN1_SIGNING, &der).ok()
+                .map(|key_pair| PublicKey { key_type: KeyType::EcdsaP384, bytes: key_pair.public_key().as_ref().to_vec() }))
+            .or_else(|| Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der).ok()
+                .map(|key_pair| PublicKey { key_type: KeyType::Ed25519, bytes: key_pair.public_key().as_ref().to_vec() })),
+        Some(_) => None,
+        None => return Err(format!("no private key found in {:?}",path)),
+    })
+}
+
+// first certificate of the file
+fn read_cert(cert_path: &Path) -> Result<CertInfo,String> {
+    let certs = read_certs(cert_path).map_err(|e| e.to_string())?;
+    let cert = certs.first().ok_or_else(|| format!("no certificate found in {:?}",cert_path))?;
+    parse_cert(&cert.0).ok_or_else(|| format!("cannot parse the certificate in {:?}",cert_path))
+}
+
+// unix timestamp after which the (first) certificate of the file is no longer valid
+pub fn cert_not_after(cert_path: &Path) -> Result<u64,String> {
+    read_cert(cert_path).map(|cert| cert.not_after)
+}
+
+// errors if the key does not belong to the certificate, false if the key format cannot be checked
+fn check_key(cert: &CertInfo, key_path: &Path) -> Result<bool,String> {
+    match private_key_public_key(key_path)? {
+        Some(key) if key.bytes == cert.public_key.bytes => Ok(true),
+        Some(key) if key.key_type != cert.public_key.key_type => Err(format!(
+            "TLS cert and key do not match: cert has {} key, private key is {}",cert.public_key.key_type,key.key_type)),
+        Some(key) => Err(format!(
+            "TLS cert and key do not match: both are {} but the private key is not the one of the certificate",key.key_type)),
+        None => Ok(false),
+    }
+}
+
+// same key check as check_cert_and_key without the dates and without logging (ACME renewal checks)
+pub fn cert_matches_key(cert_path: &Path, key_path: &Path) -> Result<bool,String> {
+    check_key(&read_cert(cert_path)?, key_path)
+}
+
+// errors if the key does not belong to the (first) certificate or if the certificate is not valid yet or has expired
+pub fn check_cert_and_key(cert_path: &Path, key_path: &Path) -> Result<(),String> {
+    let cert = read_cert(cert_path)?;
+    if !check_key(&cert, key_path)? {
+        warn!("cannot check that the private key in {:?} matches the certificate (unsupported key format)",key_path);
+    }
+
+    let now = unix_time_secs();
+    if cert.not_before > now {
+        return Err(format!("TLS cert {:?} is not valid before {}",cert_path,iso8601(cert.not_before)));
+    }
+    if cert.not_after <= now {
+        return Err(format!("TLS cert {:?} expired on {}",cert_path,iso8601(cert.not_after)));
+    }
+    if cert.not_after - now < EXPIRY_WARNING_SECS {
+        warn!("TLS cert {:?} expires soon: {}",cert_path,iso8601(cert.not_after));
+    } else {
+        info!("TLS cert {:?} ({} key) is valid until {}",cert_path,cert.public_key.key_type,iso8601(cert.not_after));
+    }
+    Ok(())
+}
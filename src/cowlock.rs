@@ -0,0 +1,30 @@
+// copy-on-write lock: the value is kept in an Arc so that a reader can take a snapshot (one
+// Arc clone under the read lock) and work on it without holding the lock; a writer copies
+// the value only if a snapshot of it is still alive (Arc::make_mut)
+
+use tokio::sync::{RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::Arc;
+
+#[derive(Default,Debug)]
+pub struct CowLock<T>(RwLock<Arc<T>>);
+
+impl<T> From<T> for CowLock<T> {
+    fn from(value: T) -> Self {
+        CowLock(RwLock::new(Arc::new(value)))
+    }
+}
+
+impl<T: Clone> CowLock<T> {
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        RwLockReadGuard::map(self.0.read().await, |value| value.as_ref())
+    }
+
+    pub async fn write(&self) -> RwLockMappedWriteGuard<'_, T> {
+        RwLockWriteGuard::map(self.0.write().await, Arc::make_mut)
+    }
+
+    // the current value, not affected by later writes
+    pub async fn snapshot(&self) -> Arc<T> {
+        self.0.read().await.clone()
+    }
+}
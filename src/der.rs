@@ -0,0 +1,35 @@
+// DER and PEM encoding of the keys, certificate requests and certificates built by the broker
+// (ACME and export signatures)
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+pub const DER_INTEGER: u8 = 0x02;
+pub const DER_BIT_STRING: u8 = 0x03;
+pub const DER_OCTET_STRING: u8 = 0x04;
+pub const DER_NULL: u8 = 0x05;
+pub const DER_OID: u8 = 0x06;
+pub const DER_UTF8_STRING: u8 = 0x0c;
+pub const DER_SEQUENCE: u8 = 0x30;
+pub const DER_SET: u8 = 0x31;
+
+// element with a definite length (short form below 128 bytes, long form otherwise)
+pub fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+// base64 in lines of 64 characters between the BEGIN and END lines of the label
+pub fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let lines: Vec<&str> = encoded.as_bytes().chunks(64).map(|line| std::str::from_utf8(line).unwrap_or_default()).collect();
+    format!("-----BEGIN {label}-----\n{}\n-----END {label}-----\n",lines.join("\n"))
+}
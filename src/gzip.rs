@@ -0,0 +1,79 @@
+// gzip (RFC 1952) decoding of request bodies sent with Content-Encoding: gzip
+
+use miniz_oxide::inflate::{TINFLStatus, decompress_to_vec_with_limit};
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+#[derive(Debug,PartialEq)]
+pub enum GzipError {
+    // the decompressed data is larger than the limit
+    TooLarge,
+    Invalid(String),
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn invalid(msg: &str) -> GzipError {
+    GzipError::Invalid(msg.to_string())
+}
+
+// length of the header (including the optional fields)
+fn header_len(data: &[u8]) -> Result<usize,GzipError> {
+    if data.len() < 10 || data[0] != 0x1f || data[1] != 0x8b {
+        return Err(invalid("not gzip data"));
+    }
+    if data[2] != 8 {
+        return Err(invalid("unsupported gzip compression method"));
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FLAG_EXTRA != 0 {
+        let len = data.get(pos..pos+2).ok_or_else(|| invalid("truncated gzip header"))?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let end = data.get(pos..).and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| invalid("truncated gzip header"))?;
+            pos += end + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+    if pos > data.len() {
+        return Err(invalid("truncated gzip header"));
+    }
+    Ok(pos)
+}
+
+// a single gzip member, checked against its CRC and size trailer
+pub fn decompress(data: &[u8], max_len: usize) -> Result<Vec<u8>,GzipError> {
+    let start = header_len(data)?;
+    if data.len() < start + 8 {
+        return Err(invalid("truncated gzip data"));
+    }
+    let (deflated, trailer) = data[start..].split_at(data.len() - start - 8);
+    let output = decompress_to_vec_with_limit(deflated, max_len).map_err(|e| match e.status {
+        TINFLStatus::HasMoreOutput => GzipError::TooLarge,
+        _ => GzipError::Invalid(format!("invalid gzip data: {e}")),
+    })?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc != crc32(&output) || len != output.len() as u32 {
+        return Err(invalid("gzip checksum mismatch"));
+    }
+    Ok(output)
+}
@@ -0,0 +1,50 @@
+// timeout of the TLS handshake (and of the protocol detection in both mode)
+// so that clients cannot hold connections open without ever completing it
+
+use axum_server::accept::Accept;
+use hyper::server::conn::AddrStream;
+use tracing::warn;
+use std::{future::Future, io, pin::Pin, time::Duration};
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Clone)]
+pub struct TimeoutAcceptor<A> {
+    inner: A,
+    timeout: Duration,
+}
+
+impl<A> TimeoutAcceptor<A> {
+    pub fn new(inner: A, timeout: Duration) -> Self {
+        TimeoutAcceptor { inner, timeout }
+    }
+}
+
+impl<A, S> Accept<AddrStream, S> for TimeoutAcceptor<A>
+where
+    A: Accept<AddrStream, S>,
+    A::Future: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: AddrStream, service: S) -> Self::Future {
+        let peer = stream.remote_addr();
+        let timeout = self.timeout;
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            // the rustls acceptor has its own timeout which can expire first
+            let result = match tokio::time::timeout(timeout, accept).await {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+            };
+            if let Err(e) = &result {
+                if e.kind() == io::ErrorKind::TimedOut {
+                    warn!("dropping connection from {peer}: TLS handshake not completed within {}s",timeout.as_secs());
+                }
+            }
+            result
+        })
+    }
+}
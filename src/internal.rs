@@ -0,0 +1,126 @@
+// web frontend for AI Wargame embedded in the binary at compile time
+// (the expected SHA-256 of each asset is computed by build.rs)
+
+use axum::{
+    routing::get,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    Json, Router,
+    extract::{ConnectInfo, State}, Extension};
+use ring::digest::{digest, SHA256};
+use serde::Serialize;
+use tracing::{info, error, debug};
+use std::net::SocketAddr;
+use crate::{ConfigUserRole, SharedState, authenticate};
+
+pub struct Asset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub bytes: &'static [u8],
+    pub expected_hash: &'static str,
+}
+
+macro_rules! asset {
+    ($path:literal,$ctype:literal,$hash:literal) => {
+        Asset {
+            path: $path,
+            content_type: $ctype,
+            bytes: include_bytes!(concat!("../../ai_wargame_web/",$path)),
+            expected_hash: env!($hash),
+        }
+    };
+}
+
+pub static ASSETS: &[Asset] = &[
+    asset!("index.html","text/html; charset=utf-8","ASSET_INDEX_HTML_HASH"),
+    asset!("game.js","text/javascript","ASSET_GAME_JS_HASH"),
+    asset!("game.css","text/css","ASSET_GAME_CSS_HASH"),
+    asset!("pkg/ai_wargame_web.js","text/javascript","ASSET_PKG_AI_WARGAME_WEB_JS_HASH"),
+    asset!("pkg/ai_wargame_web_bg.wasm","application/wasm","ASSET_PKG_AI_WARGAME_WEB_BG_WASM_HASH"),
+];
+
+// request for the scripts and styles of the page before the browser parses it
+// (HTTP/2 servers that support push turn these Link headers into pushes)
+fn preload_links() -> String {
+    ASSETS.iter()
+        .filter_map(|asset| match asset.content_type {
+            "text/javascript" => Some(format!("<{}>; rel=preload; as=script",asset.path)),
+            "text/css" => Some(format!("<{}>; rel=preload; as=style",asset.path)),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// with etag, the assets are sent with their SHA-256 as ETag (internal.headers ETag = "auto")
+pub fn router(preload: bool, etag: bool) -> Router {
+    let mut router = Router::new();
+    let links = preload_links();
+    let etag_of = move |asset: &Asset| etag.then(|| [(header::ETAG, format!("\"{}\"",asset.expected_hash))]);
+    for asset in ASSETS {
+        let handler = get(move || async move {
+            ([(header::CONTENT_TYPE, asset.content_type)], etag_of(asset), asset.bytes)
+        });
+        if asset.path == "index.html" && preload {
+            // a client that sends a cache digest already has (some of) the assets
+            let links = links.clone();
+            router = router.route("/", get(move |headers: HeaderMap| async move {
+                let links = (!headers.contains_key("cache-digest")).then_some([(header::LINK, links)]);
+                ([(header::CONTENT_TYPE, asset.content_type)], etag_of(asset), links, asset.bytes)
+            }));
+        } else if asset.path == "index.html" {
+            router = router.route("/", handler);
+        } else {
+            router = router.route(&format!("/{}",asset.path), handler);
+        }
+    }
+    // client side routes of the web UI get the page (like try_files $uri /index.html)
+    if let Some(index) = ASSETS.iter().find(|asset| asset.path == "index.html") {
+        router = router.fallback(move || async move {
+            ([(header::CONTENT_TYPE, index.content_type)], etag_of(index), index.bytes)
+        });
+    }
+    router
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    digest(&SHA256, bytes).as_ref().iter().map(|b| format!("{:02x}",b)).collect()
+}
+
+#[derive(Serialize,Debug)]
+pub struct AssetIntegrity {
+    path: &'static str,
+    expected: &'static str,
+    actual: String,
+    valid: bool,
+}
+
+pub fn asset_integrity() -> Vec<AssetIntegrity> {
+    ASSETS.iter().map(|asset| {
+        let actual = sha256_hex(asset.bytes);
+        AssetIntegrity { path: asset.path, expected: asset.expected_hash, valid: actual == asset.expected_hash, actual }
+    }).collect()
+}
+
+// panics if an embedded asset does not match the hash computed at build time
+pub fn verify_asset_integrity() {
+    for check in asset_integrity() {
+        if !check.valid {
+            panic!("embedded asset {} is corrupted: expected sha256 {}, got {}",check.path,check.expected,check.actual);
+        }
+    }
+    info!("verified integrity of {} embedded assets",ASSETS.len());
+}
+
+pub async fn admin_asset_integrity(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    Json(asset_integrity()).into_response()
+}
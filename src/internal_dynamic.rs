@@ -0,0 +1,91 @@
+// web frontend for AI Wargame read from disk at request time (internal-dynamic feature),
+// so that game.js and game.css can be changed without rebuilding the broker: a file is
+// served from memory for CACHE_SECS after it was checked, then read again if its
+// modification time changed
+
+use axum::{
+    routing::get,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    extract::State, Router};
+use ring::digest::{digest, SHA256};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+use std::{collections::HashMap, path::{Component, Path, PathBuf}, sync::Arc, time::{Duration, Instant, SystemTime}};
+use crate::static_mime_type;
+
+// default of general.internal_assets_path (the web UI is built next to the broker)
+pub const DEFAULT_ASSETS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../ai_wargame_web");
+const CACHE_SECS: u64 = 5;
+
+struct CachedFile {
+    bytes: Arc<Vec<u8>>,
+    modified: SystemTime,
+    checked_at: Instant,
+    // SHA-256 (hex) for internal.headers ETag = "auto"
+    hash: String,
+}
+
+struct Assets {
+    root: PathBuf,
+    etag: bool,
+    cache: RwLock<HashMap<PathBuf,CachedFile>>,
+}
+
+impl Assets {
+    // None if there is no such file
+    async fn read(&self, path: &Path) -> Option<(Arc<Vec<u8>>, String)> {
+        if let Some(cached) = self.cache.read().await.get(path) {
+            if cached.checked_at.elapsed() < Duration::from_secs(CACHE_SECS) {
+                return Some((cached.bytes.clone(), cached.hash.clone()));
+            }
+        }
+        let full_path = self.root.join(path);
+        let modified = tokio::fs::metadata(&full_path).await.ok().filter(|metadata| metadata.is_file())?.modified().ok()?;
+        let mut cache = self.cache.write().await;
+        if let Some(cached) = cache.get_mut(path).filter(|cached| cached.modified == modified) {
+            cached.checked_at = Instant::now();
+            return Some((cached.bytes.clone(), cached.hash.clone()));
+        }
+        let bytes = match tokio::fs::read(&full_path).await {
+            Ok(bytes) => Arc::new(bytes),
+            Err(e) => {
+                warn!("cannot read internal asset {:?}: {e}",full_path);
+                return None;
+            }
+        };
+        debug!("internal asset {:?} read from disk",full_path);
+        let hash: String = digest(&SHA256, &bytes).as_ref().iter().map(|b| format!("{:02x}",b)).collect();
+        cache.insert(path.to_path_buf(), CachedFile { bytes: bytes.clone(), modified, checked_at: Instant::now(), hash: hash.clone() });
+        Some((bytes, hash))
+    }
+}
+
+async fn asset(State(assets): State<Arc<Assets>>, uri: Uri) -> Response {
+    let decoded = percent_encoding::percent_decode_str(uri.path().trim_start_matches('/')).decode_utf8_lossy();
+    let path = match decoded.as_ref() {
+        "" => PathBuf::from("index.html"),
+        path => PathBuf::from(path),
+    };
+    // never outside of the root
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some((bytes, hash)) = assets.read(&path).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut content_type = static_mime_type(&path.to_string_lossy());
+    if content_type == "text/html" {
+        content_type.push_str("; charset=utf-8");
+    }
+    let etag = assets.etag.then(|| [(header::ETAG, format!("\"{hash}\""))]);
+    ([(header::CONTENT_TYPE, content_type)], etag, bytes.as_ref().clone()).into_response()
+}
+
+pub fn router(root: PathBuf, etag: bool) -> Router {
+    let assets = Arc::new(Assets { root, etag, cache: RwLock::new(HashMap::new()) });
+    Router::new()
+        .route("/", get(asset))
+        .fallback(asset)
+        .with_state(assets)
+}
@@ -0,0 +1,109 @@
+// in-memory copy of the most recent log lines (served by /admin/logs)
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::{filter::{LevelFilter, Targets}, fmt::MakeWriter};
+use std::{collections::VecDeque, io, sync::{Mutex, atomic::{AtomicUsize, Ordering}}};
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+// target of the request body logs (never copied to the buffer so that they cannot be read through the API)
+pub const REQUEST_BODY_TARGET: &str = "ai_wargame_broker::request_body";
+
+// console output: info and above, plus the request bodies (debug level, only logged with
+// general.debug_log_request_bodies)
+pub fn console_filter() -> Targets {
+    Targets::new().with_default(LevelFilter::INFO).with_target(REQUEST_BODY_TARGET, LevelFilter::DEBUG)
+}
+
+// buffer: info and above, never the request bodies
+pub fn buffer_filter() -> Targets {
+    Targets::new().with_default(LevelFilter::INFO).with_target(REQUEST_BODY_TARGET, LevelFilter::OFF)
+}
+
+// the subscriber is global (and set up before the config is read) so the buffer is too
+pub static LOG_BUFFER: LogBuffer = LogBuffer::new(DEFAULT_CAPACITY);
+
+pub struct LogBuffer {
+    // oldest first
+    lines: Mutex<VecDeque<(Level,String)>>,
+    // 0 = disabled
+    capacity: AtomicUsize,
+}
+
+impl LogBuffer {
+    pub const fn new(capacity: usize) -> Self {
+        LogBuffer { lines: Mutex::new(VecDeque::new()), capacity: AtomicUsize::new(capacity) }
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        if let Ok(mut lines) = self.lines.lock() {
+            while lines.len() > capacity {
+                lines.pop_front();
+            }
+        }
+    }
+
+    pub fn push(&self, level: Level, line: String) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        if let Ok(mut lines) = self.lines.lock() {
+            while lines.len() >= capacity {
+                lines.pop_front();
+            }
+            lines.push_back((level, line));
+        }
+    }
+
+    // the most recent lines at min_level or more severe (oldest first)
+    pub fn lines(&self, min_level: Level, limit: Option<usize>) -> Vec<String> {
+        let Ok(lines) = self.lines.lock() else { return vec![] };
+        // in tracing, more verbose levels compare greater
+        let mut selected: Vec<String> = lines.iter().rev()
+            .filter(|(level,_)| *level <= min_level)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(_,line)| line.clone())
+            .collect();
+        selected.reverse();
+        selected
+    }
+}
+
+// the fmt layer writes each event to a new writer, which is added to the buffer when dropped
+pub struct LogLineWriter {
+    level: Level,
+    line: Vec<u8>,
+}
+
+impl io::Write for LogLineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogLineWriter {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).trim_end().to_string();
+        if !line.is_empty() {
+            LOG_BUFFER.push(self.level, line);
+        }
+    }
+}
+
+pub struct MakeLogBufferWriter;
+
+impl<'a> MakeWriter<'a> for MakeLogBufferWriter {
+    type Writer = LogLineWriter;
+    fn make_writer(&'a self) -> Self::Writer {
+        LogLineWriter { level: Level::INFO, line: vec![] }
+    }
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        LogLineWriter { level: *meta.level(), line: vec![] }
+    }
+}
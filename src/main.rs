@@ -1,48 +1,846 @@
 use axum::{
-    routing::{get, delete},
-    http::{StatusCode, Uri, header, Request, HeaderValue},
-    response::{IntoResponse, Redirect},
+    body::Bytes,
+    routing::{get, delete, patch, post},
+    http::{StatusCode, Uri, header, Request, HeaderMap, HeaderValue},
+    response::{IntoResponse, Redirect, Response},
     Json, Router,
-    extract::{Path, State, Query, ConnectInfo, Host}, TypedHeader, headers::{Authorization, authorization::Basic}, middleware::{Next, self}, Extension, error_handling::HandleErrorLayer, BoxError};
-use axum_server::tls_rustls::RustlsConfig;
-use tokio::{sync::RwLock, time::sleep};
-use tower::{ServiceBuilder, timeout::TimeoutLayer};
-use tower_http::{services::ServeDir, trace::{TraceLayer, self}};
+    extract::{Path, State, Query, ConnectInfo, Host, MatchedPath, DefaultBodyLimit}, TypedHeader, headers::{Authorization, HeaderMapExt, authorization::{Basic, Bearer}}, middleware::{Next, self}, Extension, error_handling::HandleErrorLayer, BoxError};
+use tokio::{sync::{RwLock, watch}, time::sleep};
+use tower::{ServiceBuilder, ServiceExt, timeout::TimeoutLayer};
+use tower_http::{services::{ServeDir, ServeFile}, set_header::SetResponseHeaderLayer, trace::{TraceLayer, self}};
+use axum_server::{HttpConfig, tls_rustls::RustlsAcceptor};
 use tracing::{info, debug, warn, error};
-use std::{net::SocketAddr, sync::Arc, collections::HashMap, fs::read_to_string, str::FromStr, path::PathBuf, time::{Duration, SystemTime}};
+use std::{net::{IpAddr, SocketAddr}, sync::Arc, collections::HashMap, fs::read_to_string, str::FromStr, path::PathBuf, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 use serde::{Deserialize, Serialize};
 use askama::Template;
 use nanoid::nanoid;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+mod acme;
+mod activity;
+mod audit;
+mod certcheck;
+mod cowlock;
+mod der;
+mod gzip;
+mod handshake;
+mod logbuf;
+mod memguard;
+#[cfg(feature = "internal")]
+mod internal;
+#[cfg(feature = "internal-dynamic")]
+mod internal_dynamic;
+mod mtls;
+mod perf;
+mod protocol;
+#[cfg(feature = "qrcode")]
+mod qrpng;
+mod rate;
+mod ratelimit;
+mod rating;
+mod selftest;
+#[cfg(feature = "simulate")]
+mod simulate;
+mod signing;
+mod sni;
+mod snapshot;
+mod testutil;
+mod token;
+mod validator;
+#[cfg(test)]
+mod verify;
+#[cfg(feature = "vault")]
+mod vault;
+#[cfg(test)]
+mod tests;
+
+use rating::UserRating;
+use perf::LatencyHistogram;
+use activity::ActivityEvent;
 
 type SharedState = Arc<SharedData>;
-type GameData = HashMap<String,GameTurn>;
+type GameData = HashMap<String,GameEntry>;
+
+// point-in-time view of the game data for the long reads (admin page, export, stats), taken
+// without holding the lock while it is used
+#[derive(Debug,Clone)]
+struct GameDataSnapshot(Arc<GameData>);
+
+impl std::ops::Deref for GameDataSnapshot {
+    type Target = GameData;
+    fn deref(&self) -> &GameData {
+        &self.0
+    }
+}
 
 #[derive(Default,Debug)]
 struct SharedData {
-    game_data: RwLock<GameData>,
-    users: Vec<ConfigUser>,
+    // written in place unless a snapshot is in use (the map is copied then)
+    game_data: cowlock::CowLock<GameData>,
+    // games closed by /admin/games/archive (only read by /admin/archives/:gameid, not saved in snapshots)
+    archived_games: RwLock<GameData>,
+    // the cleaner removes archives not written for longer than this (kept if None)
+    archived_retention_secs: Option<u64>,
+    users: RwLock<Vec<ConfigUser>>,
     unauthenticated_role: ConfigUserRole,
+    // server-side move validation (game.move_rules)
+    move_rules: MoveRules,
+    // None means any coordinate is accepted
+    bounds: Option<GameBounds>,
+    validator: Option<Arc<validator::Validator>>,
+    ratings: RwLock<HashMap<String,UserRating>>,
+    elo_k_factor: f64,
+    // expiry of games without turns and of finished games (derived from expires if None)
+    created_expires_secs: Option<u64>,
+    finished_expires_secs: Option<u64>,
+    // upper bound of the ttl_secs of POST /game/batch (created_expires_secs or derived from expires,
+    // None if games never expire)
+    max_batch_ttl_secs: Option<u64>,
+    reserved_expires_secs: u64,
+    hard_max_age_secs: Option<u64>,
+    hard_max_games: Option<usize>,
+    // 0 = unlimited
+    max_turns_per_game: u16,
+    max_annotations_per_game: usize,
+    // 0 = unlimited
+    max_history_per_game: usize,
+    game_id_alphabet: Vec<char>,
+    game_id_case: ConfigGameIdCase,
+    // game_generate gives up after this many ids already taken (or reserved), waiting between attempts
+    id_gen_max_attempts: u32,
+    max_batch_generate: usize,
+    id_gen_retry_delay_ms: u64,
+    // lowercase (compared case-insensitively)
+    reserved_game_ids: Vec<String>,
+    // generated from the config at startup (security.txt is None without a [security] section)
+    robots_txt: Arc<String>,
+    security_txt: Option<Arc<String>>,
+    // realm of the basic auth challenge
+    auth_realm: String,
+    // indent all JSON responses (except game_post)
+    pretty_json: bool,
+    // auto-refresh of /admin/state without ?refresh (0 = off)
+    default_refresh_secs: usize,
+    // game_get and game_post reply without the GameReply envelope unless ?envelope=true
+    omit_envelope: bool,
+    // same from and to as the previous turn (stuck AI): logged, or rejected with 409
+    warn_duplicate_moves: bool,
+    reject_duplicate_moves: bool,
+    // identical game_post bodies from the same user within this time return the stored turn (0 = off)
+    dedup_window_secs: u64,
+    // game_get replies are cached once a game was read more than this many times (0 = off)
+    cache_min_reads: u64,
+    // estimated size over which the admin page and the export are answered with 507 (None = no limit)
+    max_response_memory_bytes: Option<usize>,
+    reply_cache_hits: std::sync::atomic::AtomicU64,
+    reply_cache_misses: std::sync::atomic::AtomicU64,
+    cleaner_status: RwLock<CleanerStatus>,
+    // recent turns and game creations for /admin/activity
+    activity: activity::ActivityLog,
+    // keyed by "METHOD /matched/path"
+    latencies: std::sync::Mutex<HashMap<String,LatencyHistogram>>,
+    // HMAC key of the game tokens (random unless configured)
+    token_secret: Vec<u8>,
+    token_ttl_secs: u64,
+    // invites already used, with their expiry (forgotten once expired)
+    consumed_invites: RwLock<HashMap<String,u64>>,
+    // URLs given to clients use https (see canonical_url)
+    tls_enabled: bool,
+    // addresses whose X-Forwarded-Proto is trusted (network.trusted_proxies)
+    trusted_proxies: Vec<IpAddr>,
+    // snapshot of the game data (crash recovery)
+    state_file: Option<PathBuf>,
+    access: ConfigAccess,
+    // requests rejected by the [access] checks since startup (shown by /admin/state)
+    blocked_requests: std::sync::atomic::AtomicU64,
+    // signs /admin/export?signed=true (general.enable_signing)
+    export_signer: Option<Arc<signing::ExportSigner>>,
+    // noop: games are neither stored nor read (load tests)
+    storage: ConfigStorageBackend,
+    // names of the [[tls.certs]] entries (admin views on these hosts only show their own games)
+    sni_hosts: Vec<String>,
+    // certificates by SNI name (set at startup when TLS is enabled)
+    tls_certs: std::sync::OnceLock<Arc<sni::SniResolver>>,
+    // set at startup with tls.acme_domain (the challenges are served by every listener)
+    acme: std::sync::OnceLock<Arc<acme::AcmeClient>>,
+    acme_challenges: Arc<acme::Challenges>,
+    // global limit (users can have their own)
+    rate_limit: ConfigRateLimit,
+    rate_limiter: ratelimit::RateLimiter,
+}
+
+impl SharedData {
+    async fn snapshot(&self) -> GameDataSnapshot {
+        GameDataSnapshot(self.game_data.snapshot().await)
+    }
+    // counts of every status (0 for the missing ones) in a single snapshot
+    async fn game_count_by_status(&self) -> HashMap<GameStatus,usize> {
+        let mut counts: HashMap<GameStatus,usize> = [GameStatus::Created, GameStatus::Active, GameStatus::Finished]
+            .into_iter().map(|status| (status, 0)).collect();
+        for entry in self.snapshot().await.values() {
+            *counts.entry(entry.status()).or_default() += 1;
+        }
+        counts
+    }
+    async fn total_game_count(&self) -> usize {
+        self.game_data.read().await.len()
+    }
 }
 
 #[derive(Serialize,Default,Debug,Clone)]
+struct CleanerStatus {
+    interval_secs: u64,
+    // unix timestamps (seconds)
+    last_run: Option<u64>,
+    next_run: Option<u64>,
+    removed_last_cycle: usize,
+    expired: usize,
+    orphaned: usize,
+    finished: usize,
+    // over general.hard_max_age_secs or general.hard_max_games
+    evicted: usize,
+    // archives older than general.archived_retention_secs
+    archives_removed: usize,
+}
+
+#[derive(Serialize,Debug)]
+struct CleanerStatusReply {
+    #[serde(flatten)]
+    status: CleanerStatus,
+    // games currently stored, by status
+    games: HashMap<GameStatus,usize>,
+}
+
+#[derive(Serialize,Debug,Clone)]
 struct GameReply {
     success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
     data: Option<GameTurn>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<GameMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<GameResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    turns_remaining: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forfeit_at: Option<u16>,
+    // related URLs, only when requested with ?links=true
+    #[serde(rename = "_links", skip_serializing_if = "Option::is_none")]
+    links: Option<GameLinks>,
+}
+
+impl GameReply {
+    const EMPTY: GameReply = GameReply {
+        success: false,
+        error: None,
+        data: None,
+        meta: None,
+        result: None,
+        turns_remaining: None,
+        forfeit_at: None,
+        links: None,
+    };
+    fn error(msg: impl Into<String>) -> Self {
+        GameReply { error: Some(msg.into()), ..GameReply::EMPTY }
+    }
+    const fn success(data: GameTurn) -> Self {
+        GameReply { success: true, data: Some(data), ..GameReply::EMPTY }
+    }
+    const fn empty_success() -> Self {
+        GameReply { success: true, ..GameReply::EMPTY }
+    }
+    fn with_status(self, status: StatusCode) -> (StatusCode, Json<GameReply>) {
+        (status, Json(self))
+    }
+}
+
+// a failed reply with no data: use GameReply::success(), empty_success() or error() to build meaningful replies
+impl Default for GameReply {
+    fn default() -> Self {
+        GameReply::EMPTY
+    }
+}
+
+#[derive(Serialize,Debug,Clone)]
+struct GameLinks {
+    #[serde(rename = "self")]
+    game: String,
+    history: String,
+    diff: String,
+    annotations: String,
+}
+
+impl GameLinks {
+    fn new(gameid: &str) -> Self {
+        let game = format!("/game/{gameid}");
+        GameLinks {
+            history: format!("{game}/history"),
+            diff: format!("{game}/diff"),
+            annotations: format!("{game}/annotations"),
+            game,
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+struct GameEntry {
+    turn: Option<GameTurn>,
+    // every turn posted so far (including the current one)
+    history: Vec<GameTurn>,
+    meta: GameMeta,
+    creator: Option<String>,
+    result: Option<GameResult>,
+    created_at: SystemTime,
+    // pre-created with a chosen id by an admin (ex: tournament brackets)
+    reserved: bool,
+    // overrides the global limit (0 = unlimited)
+    max_turns: Option<u16>,
+    annotations: Vec<Annotation>,
+    turn_limit: Option<TurnLimit>,
+    // turn number posted by the timeout enforcer
+    forfeit_at: Option<u16>,
+    // pending forfeit (aborted when a turn is posted)
+    turn_timer: Option<Arc<TurnTimer>>,
+    // incremented on every write (long-polling clients watch it)
+    version: u64,
+    last_write: SystemTime,
+    version_tx: Arc<watch::Sender<u64>>,
+    // shown first on the admin page and kept by age-based bulk deletions
+    pinned: bool,
+    // SNI name of the [[tls.certs]] entry the game was created on
+    host: Option<String>,
+    // user who posted the current turn (None if unknown, ex: anonymous posts)
+    posted_by: Option<String>,
+    // users who posted turns in the game (the only players rated by game_result_post)
+    players: Vec<String>,
+    // replaces general.created_expires_secs while the game was never played (POST /game/batch)
+    created_ttl_secs: Option<u64>,
+    // body of the last turn written, for general.dedup_window_secs (not saved in snapshots)
+    last_body: Option<LastBody>,
+    // serialized game_get reply, for general.cache_min_reads (not saved in snapshots)
+    reply_cache: ReplyCache,
+}
+
+// pending forfeit task of an entry: the id tells the task if it is still the one scheduled
+// for the entry (an entry with the same game id may have replaced it since)
+#[derive(Debug)]
+struct TurnTimer {
+    id: u64,
+    handle: tokio::task::AbortHandle,
+    deadline: Instant,
+}
+
+// posted_by of the turns forfeited by the broker
+const TIMEOUT_ENFORCER: &str = "timeout_enforcer";
+
+static NEXT_TURN_TIMER_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// sha-256 of the raw body of a game_post request (set by body_hash)
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct BodyHash([u8; 32]);
+
+#[derive(Debug,Clone)]
+struct LastBody {
+    hash: BodyHash,
+    posted_by: Option<String>,
+    at: Instant,
+}
+
+// game_get reply of the entry (with the envelope, without links) serialized once and returned
+// as is while the version of the entry is the same; game_get only holds the read lock, hence
+// the interior mutability; the copies of the entry (copy-on-write, snapshots) share the cache,
+// which is safe because the cached reply is only returned for its version
+#[derive(Debug,Default,Clone)]
+struct ReplyCache(Arc<ReplyCacheInner>);
+
+#[derive(Debug,Default)]
+struct ReplyCacheInner {
+    reads: std::sync::atomic::AtomicU64,
+    cached_json: std::sync::Mutex<Option<(u64, Bytes)>>,
+}
+
+impl ReplyCache {
+    fn get(&self, version: u64) -> Option<Bytes> {
+        self.0.cached_json.lock().unwrap().as_ref()
+            .filter(|(cached_version, _)| *cached_version == version)
+            .map(|(_, json)| json.clone())
+    }
+    // number of reads so far, including this one
+    fn count_read(&self) -> u64 {
+        self.0.reads.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1
+    }
+    fn store(&self, version: u64, json: Bytes) {
+        *self.0.cached_json.lock().unwrap() = Some((version, json));
+    }
+    fn is_filled(&self) -> bool {
+        self.0.cached_json.lock().unwrap().is_some()
+    }
+    fn clear(&self) {
+        *self.0.cached_json.lock().unwrap() = None;
+    }
+}
+
+impl Default for GameEntry {
+    fn default() -> Self {
+        GameEntry {
+            turn: None,
+            history: vec![],
+            meta: GameMeta::default(),
+            creator: None,
+            result: None,
+            created_at: SystemTime::now(),
+            reserved: false,
+            max_turns: None,
+            annotations: vec![],
+            turn_limit: None,
+            forfeit_at: None,
+            turn_timer: None,
+            version: 0,
+            last_write: SystemTime::now(),
+            version_tx: Arc::new(watch::channel(0).0),
+            pinned: false,
+            host: None,
+            posted_by: None,
+            players: vec![],
+            created_ttl_secs: None,
+            last_body: None,
+            reply_cache: ReplyCache::default(),
+        }
+    }
+}
+
+impl GameEntry {
+    fn new(creator: Option<String>) -> Self {
+        GameEntry { creator, ..Default::default() }
+    }
+    fn status(&self) -> GameStatus {
+        if self.result.is_some() { GameStatus::Finished }
+        else if self.turn.is_some() { GameStatus::Active }
+        else { GameStatus::Created }
+    }
+    fn max_turns(&self, default_max_turns: u16) -> Option<u16> {
+        match self.max_turns.unwrap_or(default_max_turns) {
+            0 => None,
+            max_turns => Some(max_turns),
+        }
+    }
+    // turn numbers still accepted after the current one
+    fn turns_remaining(&self, default_max_turns: u16) -> Option<u16> {
+        let max_turns = self.max_turns(default_max_turns)?;
+        Some(match self.turn {
+            Some(turn) => max_turns.saturating_sub(turn.turn.saturating_add(1)),
+            None => max_turns,
+        })
+    }
+    // appends to the history, dropping the oldest turns beyond max_history (0 = unlimited)
+    fn push_history(&mut self, turn: GameTurn, max_history: usize) {
+        self.history.push(turn);
+        if max_history == 0 { return; }
+        let excess = self.history.len().saturating_sub(max_history);
+        self.history.drain(..excess);
+    }
+    // most recent turn posted with the given turn number
+    fn turn_in_history(&self, turn: u16) -> Option<&GameTurn> {
+        self.history.iter().rev().find(|t| t.turn == turn)
+    }
+    // time of the last turn or creation time if no turn was played
+    fn last_activity(&self) -> SystemTime {
+        self.turn.and_then(|turn| turn.updated).unwrap_or(self.created_at)
+    }
+    // the creator and the players named in the metadata
+    fn is_participant(&self, username: &str) -> bool {
+        [&self.creator, &self.meta.player_white, &self.meta.player_black].iter().any(|name| name.as_deref() == Some(username))
+    }
+    // true if the creator (or an admin) is making the request
+    fn can_be_managed_by(&self, role: ConfigUserRole, username: &Option<String>) -> bool {
+        role >= ConfigUserRole::Admin || (username.is_some() && &self.creator == username)
+    }
+    // upper bound of the size of the entry in /admin/export (see memguard)
+    fn estimated_export_bytes(&self) -> usize {
+        (self.history.len() + self.annotations.len()).saturating_mul(memguard::BYTES_PER_TURN).saturating_add(memguard::BYTES_PER_GAME)
+    }
+    // to be called after every change to the entry
+    fn touch(&mut self) {
+        self.reply_cache.clear();
+        self.version += 1;
+        self.last_write = SystemTime::now();
+        self.version_tx.send_replace(self.version);
+    }
+    fn version_headers(&self) -> [(header::HeaderName, String); 2] {
+        let age_ms = self.last_write.elapsed().map(|age| age.as_millis()).unwrap_or(0);
+        [
+            (header::HeaderName::from_static("x-game-version"), self.version.to_string()),
+            (header::HeaderName::from_static("x-game-age-ms"), age_ms.to_string()),
+        ]
+    }
+    fn cancel_turn_timer(&mut self) {
+        if let Some(timer) = self.turn_timer.take() {
+            timer.handle.abort();
+        }
+    }
+    // (re)starts the countdown for the next turn if the game has a turn limit
+    fn schedule_turn_timer(&mut self, gameid: &str, state: &SharedState) {
+        let Some(turn_limit) = self.turn_limit else { return self.cancel_turn_timer() };
+        self.schedule_turn_timer_at(gameid, state, Instant::now() + Duration::from_secs(turn_limit.secs_per_turn));
+    }
+    // restarts the pending countdown (if any) under another game id without extending it
+    fn reschedule_turn_timer(&mut self, gameid: &str, state: &SharedState) {
+        match self.turn_timer.as_ref().filter(|timer| !timer.handle.is_finished()) {
+            Some(timer) => {
+                let deadline = timer.deadline;
+                self.schedule_turn_timer_at(gameid, state, deadline);
+            },
+            None => self.cancel_turn_timer(),
+        }
+    }
+    fn schedule_turn_timer_at(&mut self, gameid: &str, state: &SharedState, deadline: Instant) {
+        self.cancel_turn_timer();
+        let Some(turn_limit) = self.turn_limit else { return };
+        if self.result.is_some() || self.forfeit_at.is_some() { return; }
+        let expected_turn = self.turn.map_or(1, |turn| turn.turn.saturating_add(1));
+        let id = NEXT_TURN_TIMER_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let gameid = gameid.to_string();
+        let state = state.clone();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            let mut dict = state.game_data.write().await;
+            let Some(entry) = dict.get_mut(&gameid) else { return };
+            // the entry was cleared, replaced or rescheduled while waiting for the lock
+            if entry.turn_timer.as_ref().map(|timer| timer.id) != Some(id) { return; }
+            // a turn was posted while waiting for the lock
+            if entry.turn.map_or(1, |turn| turn.turn.saturating_add(1)) != expected_turn { return; }
+            let mut forfeit = GameTurn {
+                from: turn_limit.forfeit_move.from,
+                to: turn_limit.forfeit_move.to,
+                turn: expected_turn,
+                forfeited: true,
+                updated: Some(SystemTime::now()),
+                ..Default::default()
+            };
+            forfeit.fingerprint = Some(Fingerprint::of(&forfeit));
+            warn!("game {} turn {:03} forfeited by {TIMEOUT_ENFORCER} after {}s",gameid,expected_turn,turn_limit.secs_per_turn);
+            entry.turn = Some(forfeit);
+            entry.push_history(forfeit, state.max_history_per_game);
+            entry.forfeit_at = Some(expected_turn);
+            entry.posted_by = Some(String::from(TIMEOUT_ENFORCER));
+            entry.turn_timer = None;
+            entry.touch();
+        });
+        self.turn_timer = Some(Arc::new(TurnTimer { id, handle: timer.abort_handle(), deadline }));
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Copy,Clone)]
+struct TurnLimit {
+    secs_per_turn: u64,
+    forfeit_move: ForfeitMove,
+}
+
+#[derive(Serialize,Deserialize,Debug,Copy,Clone)]
+struct ForfeitMove {
+    from: GameCoord,
+    to: GameCoord,
+}
+
+#[derive(Serialize,Deserialize,Debug,Copy,Clone,PartialEq,Eq,Hash)]
+#[serde(rename_all = "lowercase")]
+enum GameStatus {
+    Created,
+    Active,
+    Finished,
+}
+
+#[derive(Serialize,Deserialize,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum GameWinner {
+    White,
+    Black,
+    Draw,
+}
+
+#[derive(Serialize,Deserialize,Default,Debug,Clone)]
+struct GameResult {
+    // None when the game was concluded without a winner (ex: aborted)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    winner: Option<GameWinner>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+struct Annotation {
+    turn_num: u16,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    // unix timestamp (seconds)
+    created_at: u64,
+    // authenticated user who posted the annotation (only they can delete it)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    posted_by: Option<String>,
+}
+
+#[derive(Deserialize,Debug)]
+struct AnnotationRequest {
+    text: String,
+    author: Option<String>,
+}
+
+const MAX_NOTES_LEN: usize = 500;
+const MAX_PLAYER_NAME_LEN: usize = 64;
+const MAX_ANNOTATION_LEN: usize = 1000;
+const DEFAULT_MAX_ANNOTATIONS_PER_GAME: usize = 50;
+const DEFAULT_MAX_HISTORY_PER_GAME: usize = 1000;
+const MAX_GAME_ID_LEN: usize = 64;
+const DEFAULT_RESERVED_EXPIRES_SECS: u64 = 24 * 60 * 60;
+
+// same characters as the generated (nanoid) ids
+fn validate_game_id(gameid: &str) -> Result<(),String> {
+    if gameid.is_empty() || gameid.len() > MAX_GAME_ID_LEN {
+        return Err(format!("game ID must have between 1 and {MAX_GAME_ID_LEN} characters"));
+    }
+    if !gameid.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("game ID '{gameid}' can only contain letters, digits, '_' and '-'"));
+    }
+    Ok(())
+}
+
+// IDs that could be confused with API paths (general.reserved_game_ids)
+fn check_game_id_not_reserved(state: &SharedData, gameid: &str) -> Result<(),String> {
+    if state.reserved_game_ids.contains(&gameid.to_lowercase()) {
+        return Err(format!("game ID '{gameid}' is reserved"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize,Deserialize,Default,Debug,Clone)]
+struct GameMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    player_white: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    player_black: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+}
+
+impl GameMeta {
+    // only the fields present in the update are changed
+    fn merge(&mut self, update: GameMeta) {
+        if update.player_white.is_some() { self.player_white = update.player_white; }
+        if update.player_black.is_some() { self.player_black = update.player_black; }
+        if update.description.is_some() { self.description = update.description; }
+        if update.notes.is_some() { self.notes = update.notes; }
+    }
+    // the players must be configured users (they are rated when the result is recorded)
+    async fn check_players(&self, state: &SharedState) -> Result<(), String> {
+        let users = state.users.read().await;
+        for name in [&self.player_white, &self.player_black].into_iter().flatten() {
+            if name.chars().count() > MAX_PLAYER_NAME_LEN {
+                return Err(format!("player names are limited to {MAX_PLAYER_NAME_LEN} characters"));
+            }
+            if !users.iter().any(|user| &user.name == name) {
+                return Err(format!("unknown player {name}"));
+            }
+        }
+        Ok(())
+    }
+    fn tooltip(&self) -> String {
+        [self.description.as_deref(), self.notes.as_deref()]
+            .into_iter().flatten().collect::<Vec<_>>().join("\n")
+    }
 }
 
 #[derive(Serialize,Deserialize,Default,Debug,Clone,Copy)]
 struct GameTurn {
+    // the aliases are the names of early versions of the API (see general.strict_field_names)
+    #[serde(alias = "source")]
     from : GameCoord,
+    #[serde(alias = "destination")]
     to : GameCoord,
+    #[serde(alias = "turn_number")]
     turn: u16,
+    // reported by the AI for information only (0.0 to 1.0 and unbounded score)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eval: Option<f64>,
+    // intent of the move as reported by the AI (never validated)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    move_type: Option<MoveType>,
+    // posted by the broker because the player ran out of time
+    #[serde(skip_deserializing, skip_serializing_if = "std::ops::Not::not")]
+    forfeited: bool,
+    // set by the broker (a value sent by the client is replaced)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<Fingerprint>,
     #[serde(skip_deserializing)]
     #[serde(skip_serializing)]
     updated: Option<SystemTime>,
+    // set by game_post: submitted_at_ms and submitted_at_iso in the JSON (absent when None)
+    #[serde(flatten, skip_deserializing)]
+    submitted_at: Option<SubmittedAt>,
 }
 
-#[derive(Serialize,Deserialize,Default,Debug,Clone,Copy)]
+// reasons for rejecting a posted turn (GameTurn::validate)
+#[derive(Debug,Clone,PartialEq)]
+enum BrokerError {
+    // from and to are the same cell for a type of move that must leave it (a self-destruct stays in place)
+    SelfMove { at: GameCoord, move_type: MoveType },
+    OutOfBounds { coord: GameCoord, bounds: GameBounds },
+    NotFinite(&'static str),
+}
+
+impl std::fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrokerError::SelfMove { at, move_type } => write!(f, "a {move_type} cannot go from {at} to the same cell"),
+            BrokerError::OutOfBounds { coord, bounds } => write!(f, "{coord} is outside of the board {bounds}"),
+            BrokerError::NotFinite(field) => write!(f, "{field} must be a finite number"),
+        }
+    }
+}
+
+impl GameTurn {
+    // all the rules that only depend on the turn itself (the turn number is at most u16::MAX
+    // since it is parsed as a u16), every error is returned
+    fn validate(&self, bounds: Option<&GameBounds>) -> Result<(),Vec<BrokerError>> {
+        let mut errors = Vec::new();
+        if let Some(move_type) = self.move_type.filter(|move_type| matches!(move_type, MoveType::Move | MoveType::Attack | MoveType::Repair)) {
+            if self.from == self.to {
+                errors.push(BrokerError::SelfMove { at: self.from, move_type });
+            }
+        }
+        if let Some(bounds) = bounds {
+            for coord in [self.from, self.to] {
+                if !coord.is_in_bounds(bounds) && !errors.contains(&BrokerError::OutOfBounds { coord, bounds: *bounds }) {
+                    errors.push(BrokerError::OutOfBounds { coord, bounds: *bounds });
+                }
+            }
+        }
+        for (field, value) in [("confidence", self.confidence), ("eval", self.eval)] {
+            if value.is_some_and(|value| !value.is_finite()) {
+                errors.push(BrokerError::NotFinite(field));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+// time at which the broker received a turn, serialized both as unix milliseconds
+// (new Date(submitted_at_ms) in JavaScript) and as RFC 3339 (UTC)
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct SubmittedAt(u64);
+
+impl SubmittedAt {
+    fn now() -> Self {
+        SubmittedAt(unix_time_millis(SystemTime::now()) as u64)
+    }
+
+    fn iso(&self) -> String {
+        let secs = snapshot::iso8601(self.0 / 1000);
+        format!("{}.{:03}Z",secs.trim_end_matches('Z'),self.0 % 1000)
+    }
+}
+
+impl Serialize for SubmittedAt {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("submitted_at_ms", &self.0)?;
+        map.serialize_entry("submitted_at_iso", &self.iso())?;
+        map.end()
+    }
+}
+
+// sha-256 of the fields posted by the client, serialized as a hex string
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(try_from = "String", into = "String")]
+struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    fn of(t: &GameTurn) -> Self {
+        let canonical = format!("turn:{},from:{},to:{}",t.turn,t.from.to_tuple_string(),t.to.to_tuple_string());
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, canonical.as_bytes()).as_ref());
+        Fingerprint(bytes)
+    }
+}
+
+impl std::fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}",b))
+    }
+}
+
+impl From<Fingerprint> for String {
+    fn from(fingerprint: Fingerprint) -> Self {
+        fingerprint.to_string()
+    }
+}
+
+impl TryFrom<String> for Fingerprint {
+    type Error = String;
+    fn try_from(hex: String) -> Result<Self,Self::Error> {
+        let mut bytes = [0; 32];
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(format!("invalid fingerprint {hex:?}"));
+        }
+        for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap_or_default(), 16)
+                .map_err(|_| format!("invalid fingerprint {hex:?}"))?;
+        }
+        Ok(Fingerprint(bytes))
+    }
+}
+
+// hex sha-256 of "turn:N,from:(r,c),to:(r,c)" which clients can compute from the turn they posted
+fn compute_fingerprint(t: &GameTurn) -> String {
+    Fingerprint::of(t).to_string()
+}
+
+#[derive(Serialize,Deserialize,Default,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum MoveType {
+    Move,
+    Attack,
+    Repair,
+    SelfDestruct,
+    // types added by newer clients are accepted
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl std::fmt::Display for MoveType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MoveType::Move => "move",
+            MoveType::Attack => "attack",
+            MoveType::Repair => "repair",
+            MoveType::SelfDestruct => "selfdestruct",
+            MoveType::Unknown => "unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl GameTurn {
+    // red (low confidence) to green (high confidence) background for the admin page
+    fn confidence_style(&self) -> String {
+        match self.confidence {
+            Some(confidence) => format!("background-color: hsl({:.0}, 70%, 80%)",confidence.clamp(0.0, 1.0) * 120.0),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Default,Debug,Clone,Copy,PartialEq)]
 struct GameCoord {
     row: u8,
     col: u8,
@@ -63,6 +861,60 @@ impl GameCoord {
     pub fn to_tuple_string(self) -> String {
         format!("({},{})", self.row, self.col)
     }
+    // signed (row,col) difference to go from self to other
+    pub fn delta(self, other: GameCoord) -> (i16, i16) {
+        (other.row as i16 - self.row as i16, other.col as i16 - self.col as i16)
+    }
+    pub fn is_in_bounds(self, bounds: &GameBounds) -> bool {
+        bounds.contains(self)
+    }
+}
+
+// (row,col) offsets of the adjacent cells
+const ORTHOGONAL_NEIGHBORS: [(i8,i8); 4] = [(-1,0),(1,0),(0,-1),(0,1)];
+
+// adjacent cells for the standard move rules (no allocation, cells that would
+// not fit in a u8 are skipped)
+impl GameCoord {
+    fn offset(self, delta_row: i8, delta_col: i8) -> Option<GameCoord> {
+        Some(GameCoord { row: self.row.checked_add_signed(delta_row)?, col: self.col.checked_add_signed(delta_col)? })
+    }
+    // up, down, left and right
+    pub fn neighbors(self) -> impl Iterator<Item = GameCoord> {
+        ORTHOGONAL_NEIGHBORS.into_iter().filter_map(move |(row,col)| self.offset(row, col))
+    }
+}
+
+// inclusive limits of the coordinates on the board
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+struct GameBounds {
+    min_row: u8,
+    max_row: u8,
+    min_col: u8,
+    max_col: u8,
+}
+
+impl GameBounds {
+    // size x size board (size > 0)
+    pub fn standard(size: u8) -> Self {
+        GameBounds::rectangle(size, size)
+    }
+    // rows x cols board (rows > 0 and cols > 0)
+    pub fn rectangle(rows: u8, cols: u8) -> Self {
+        GameBounds { min_row: 0, max_row: rows.saturating_sub(1), min_col: 0, max_col: cols.saturating_sub(1) }
+    }
+    pub fn contains(&self, coord: GameCoord) -> bool {
+        (self.min_row..=self.max_row).contains(&coord.row) && (self.min_col..=self.max_col).contains(&coord.col)
+    }
+}
+
+// ex: [A0..E4] for a 5x5 board
+impl std::fmt::Display for GameBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let first = GameCoord { row: self.min_row, col: self.min_col };
+        let last = GameCoord { row: self.max_row, col: self.max_col };
+        write!(f, "[{first}..{last}]")
+    }
 }
 
 impl std::fmt::Display for GameCoord {
@@ -77,58 +929,691 @@ struct Config {
     network: ConfigNetwork,
     tls: ConfigTLS,
     statics: Vec<ConfigStatic>,
+    internal: ConfigInternal,
     general: ConfigGeneral,
     users: Vec<ConfigUser>,
+    game: ConfigGame,
+    auth: ConfigAuth,
+    security: Option<ConfigSecurity>,
+    storage: ConfigStorage,
+    rate_limit: ConfigRateLimit,
+    latency: ConfigLatency,
+    audit: audit::ConfigAudit,
+    access: ConfigAccess,
 }
 
+// client filtering before authentication
 #[derive(Deserialize,Default,Debug,Clone)]
 #[serde(default)]
-struct ConfigGeneral {
-    internal: Option<String>,
-    expires: Option<u64>,
-    cleanup: Option<u64>,
-    #[serde(default = "ConfigUserRole::default_unauthenticated")]
-    unauthenticated: ConfigUserRole,
+struct ConfigAccess {
+    // requests whose User-Agent contains one of these strings are rejected
+    blocked_user_agents: Vec<String>,
+    // reject requests without a User-Agent (or with an empty one)
+    require_user_agent: bool,
 }
 
-#[derive(Deserialize,Default,Debug,Clone)]
-struct ConfigUser {
-    name: String,
-    #[serde(default = "ConfigUserRole::default_user")]
-    role: ConfigUserRole,
-    password: String,
+impl ConfigAccess {
+    fn validate(&self) -> Result<(),String> {
+        match self.blocked_user_agents.iter().any(|agent| agent.trim().is_empty()) {
+            true => Err(String::from("access.blocked_user_agents cannot contain an empty string (it would block every client)")),
+            false => Ok(()),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.require_user_agent || !self.blocked_user_agents.is_empty()
+    }
+
+    // reason of the rejection (logged), None if the client is allowed
+    fn rejects(&self, user_agent: Option<&str>) -> Option<String> {
+        match user_agent.map(str::trim).filter(|agent| !agent.is_empty()) {
+            None if self.require_user_agent => Some(String::from("no User-Agent")),
+            None => None,
+            Some(agent) => self.blocked_user_agents.iter()
+                .find(|blocked| agent.contains(blocked.as_str()))
+                .map(|blocked| format!("User-Agent {agent:?} matches {blocked:?}")),
+        }
+    }
 }
 
-#[derive(Deserialize,Default,Debug,Clone)]
+// requests per second of each user (or address without authentication), no limit if None
+#[derive(Deserialize,Serialize,Default,Debug,Copy,Clone,PartialEq)]
 #[serde(default)]
-struct ConfigStatic {
-    uri: String,
-    path: String,
+struct ConfigRateLimit {
+    reads_per_second: Option<f64>,
+    writes_per_second: Option<f64>,
+    // defaults to one second of requests
+    burst: Option<u32>,
+    // only for a rate_limit_override: no limit for this user
+    unlimited: bool,
 }
 
-#[derive(Deserialize,Default,Debug,Clone)]
+impl ConfigRateLimit {
+    fn validate(&self) -> Result<(),String> {
+        for rate in [self.reads_per_second, self.writes_per_second].into_iter().flatten() {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(format!("rate limits must be positive numbers, not {rate}"));
+            }
+        }
+        if self.burst == Some(0) {
+            return Err(String::from("rate limit burst cannot be 0"));
+        }
+        Ok(())
+    }
+    // the fields missing from an override are taken from the global limit
+    fn or(&self, global: &ConfigRateLimit) -> ConfigRateLimit {
+        ConfigRateLimit {
+            reads_per_second: self.reads_per_second.or(global.reads_per_second),
+            writes_per_second: self.writes_per_second.or(global.writes_per_second),
+            burst: self.burst.or(global.burst),
+            unlimited: self.unlimited,
+        }
+    }
+    fn limit(&self, write: bool) -> Option<ratelimit::Limit> {
+        if self.unlimited {
+            return None;
+        }
+        let per_second = if write { self.writes_per_second } else { self.reads_per_second }?;
+        Some(ratelimit::Limit { per_second, burst: self.burst.unwrap_or(per_second.ceil() as u32) })
+    }
+}
+
+// delays and failures injected in the API requests to test the timeouts and
+// retries of clients (simulate feature only, never in production)
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
 #[serde(default)]
-struct ConfigTLS {
-    cert: String,
-    key: String,
-    enabled: ConfigTLSType,
+struct ConfigLatency {
+    distribution: ConfigLatencyDistribution,
+    min_ms: u64,
+    max_ms: u64,
+    // fraction of the requests answered with 503 (0.0 to 1.0)
+    failure_rate: f64,
 }
 
 #[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
 #[serde(rename_all = "lowercase")]
-enum ConfigTLSType {
+enum ConfigLatencyDistribution {
     #[default]
-    Http,
-    Https,
-    Both,
+    Uniform,
+    // centered between min_ms and max_ms, which are 3 standard deviations away
+    Normal,
 }
 
-#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq,PartialOrd)]
-#[serde(rename_all = "lowercase")]
-// the order of the roles is important for authentication (admin > user > guest)
-enum ConfigUserRole {
-    #[default]
-    Guest,
+impl ConfigLatency {
+    fn validate(&self) -> Result<(),String> {
+        if self.min_ms > self.max_ms {
+            return Err(format!("latency.min_ms ({}) is above latency.max_ms ({})",self.min_ms,self.max_ms));
+        }
+        if !(0.0..=1.0).contains(&self.failure_rate) {
+            return Err(format!("latency.failure_rate must be between 0.0 and 1.0, not {}",self.failure_rate));
+        }
+        Ok(())
+    }
+    fn is_enabled(&self) -> bool {
+        self.max_ms > 0 || self.failure_rate > 0.0
+    }
+}
+
+// [latency] if set, otherwise the fixed general.simulate_latency_ms (None if disabled)
+fn latency_injection(config: &Config) -> Option<ConfigLatency> {
+    config.latency.validate().expect("latency checked by Config::validate");
+    if config.latency.is_enabled() {
+        return Some(config.latency);
+    }
+    let delay_ms = config.general.simulate_latency_ms;
+    (delay_ms > 0).then_some(ConfigLatency { min_ms: delay_ms, max_ms: delay_ms, ..Default::default() })
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigStorage {
+    backend: ConfigStorageBackend,
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConfigStorageBackend {
+    #[default]
+    Memory,
+    // posted turns are discarded and games always read as empty (load tests, benchmarks)
+    Noop,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigSecurity {
+    // ex: "mailto:security@example.com"
+    contact: String,
+    // ex: "2025-12-31T00:00:00Z"
+    expires: Option<String>,
+}
+
+impl ConfigSecurity {
+    fn security_txt(&self) -> String {
+        let mut txt = format!("Contact: {}\n",self.contact);
+        if let Some(expires) = self.expires.as_deref() {
+            txt.push_str(&format!("Expires: {expires}\n"));
+        }
+        txt
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigAuth {
+    // separate file with a [[users]] array (supports $VAR and ${VAR})
+    users_file: Option<String>,
+    // key used to sign game tokens (random at each startup if not set)
+    token_secret: Option<String>,
+    // lifetime of game tokens (defaults to one hour)
+    token_ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigUsersFile {
+    users: Vec<ConfigUser>,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigGame {
+    move_rules: ConfigMoveRules,
+    valid_moves: Vec<ConfigMoveDelta>,
+    // coordinates are not checked unless the board size is set (square or rows x cols)
+    board_size: Option<u8>,
+    board_rows: Option<u8>,
+    board_cols: Option<u8>,
+    // external check of every posted move
+    validator: Option<validator::ConfigValidator>,
+    validator_timeout_secs: Option<u64>,
+}
+
+impl ConfigGame {
+    fn bounds(&self) -> Result<Option<GameBounds>,String> {
+        let (rows, cols) = match (self.board_size, self.board_rows, self.board_cols) {
+            (None, None, None) => return Ok(None),
+            (Some(size), None, None) => (size, size),
+            (None, Some(rows), Some(cols)) => (rows, cols),
+            _ => return Err(String::from("set either game.board_size or both game.board_rows and game.board_cols")),
+        };
+        if rows == 0 || cols == 0 {
+            return Err(format!("the board cannot have {rows} rows and {cols} columns"));
+        }
+        Ok(Some(if rows == cols { GameBounds::standard(rows) } else { GameBounds::rectangle(rows, cols) }))
+    }
+    fn move_rules(&self) -> MoveRules {
+        match self.move_rules {
+            ConfigMoveRules::None => MoveRules::None,
+            ConfigMoveRules::Standard => MoveRules::Standard,
+            ConfigMoveRules::Custom => MoveRules::Deltas(self.valid_moves.iter()
+                .map(|m| (m.delta_row as i16, m.delta_col as i16))
+                .collect()),
+        }
+    }
+}
+
+#[derive(Default,Debug,Clone,PartialEq)]
+enum MoveRules {
+    // any move is accepted
+    #[default]
+    None,
+    // single step in any orthogonal direction or in place (self-destruct)
+    Standard,
+    // allowed (row,col) move deltas
+    Deltas(Vec<(i16,i16)>),
+}
+
+impl MoveRules {
+    fn allows(&self, from: GameCoord, to: GameCoord) -> bool {
+        match self {
+            MoveRules::None => true,
+            MoveRules::Standard => from == to || from.neighbors().any(|neighbor| neighbor == to),
+            MoveRules::Deltas(deltas) => deltas.contains(&from.delta(to)),
+        }
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConfigMoveRules {
+    #[default]
+    None,
+    Standard,
+    Custom,
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone)]
+struct ConfigMoveDelta {
+    delta_row: i8,
+    delta_col: i8,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigGeneral {
+    internal: Option<String>,
+    expires: Option<ConfigDuration>,
+    cleanup: Option<ConfigDuration>,
+    #[serde(default = "ConfigUserRole::default_unauthenticated")]
+    unauthenticated: ConfigUserRole,
+    selftest_on_startup: bool,
+    // no startup banner (the warnings are still logged)
+    quiet_startup: bool,
+    elo_k_factor: Option<f64>,
+    // defaults to expires / 10
+    created_expires_secs: Option<u64>,
+    // defaults to expires * 10
+    finished_expires_secs: Option<u64>,
+    // expiry of reserved games that were never played (defaults to one day)
+    reserved_expires_secs: Option<u64>,
+    // games created longer ago are removed by the cleaner whatever their status
+    hard_max_age_secs: Option<u64>,
+    // the cleaner removes the least recently active games above this number
+    hard_max_games: Option<usize>,
+    // archived games are removed by the cleaner after this delay (kept until restart if not set)
+    archived_retention_secs: Option<u64>,
+    // 0 = unlimited
+    max_turns_per_game: u16,
+    // log the raw bodies of game_post and game_generate (privacy risk in production)
+    debug_log_request_bodies: bool,
+    // reject unknown fields in posted turns and metadata updates (they are ignored otherwise)
+    strict_json: bool,
+    // reject posted turns using the legacy field names (LEGACY_TURN_FIELDS) instead of accepting them
+    strict_field_names: bool,
+    // accept request bodies sent with Content-Encoding: gzip
+    decompress_requests: bool,
+    // limit of the request bodies after decompression (defaults to 2 MiB)
+    max_request_body_bytes: Option<usize>,
+    // the admin page and the export are answered with 507 if estimated larger than this (defaults to 256, 0 = no limit)
+    max_response_memory_mb: Option<usize>,
+    // indent all JSON responses, also requested per call with ?pretty=true or Accept: application/json; indent=2
+    pretty_json: bool,
+    // auto-refresh interval of /admin/state when the page is opened without ?refresh (0 = off)
+    default_refresh_secs: usize,
+    // wrap the turn of game_get and game_post in {"success":...,"data":...} (defaults to true, also set per call with ?envelope=)
+    default_envelope: Option<bool>,
+    // defaults to 50
+    max_annotations_per_game: Option<usize>,
+    // turns kept in the history of a game, the oldest are dropped (defaults to DEFAULT_MAX_HISTORY_PER_GAME, 0 = unlimited)
+    max_history_per_game: Option<usize>,
+    // game data is restored from this file at startup and saved to it periodically
+    state_file: Option<String>,
+    snapshot_interval_secs: Option<u64>,
+    // PEM private key (Ed25519 or RSA) of the signed exports, required by enable_signing
+    signing_key: Option<String>,
+    enable_signing: bool,
+    // number of recent log lines kept for /admin/logs (defaults to 1000, 0 = disabled)
+    log_buffer_size: Option<usize>,
+    // how long the events shown by /admin/activity are kept (defaults to 1h)
+    activity_retention: Option<ConfigDuration>,
+    // reload the users when the config file changes (for containers where SIGHUP is unreliable)
+    watch_config: bool,
+    // wait until the file has not changed for this long before reloading (defaults to 2)
+    watch_config_debounce_secs: Option<u64>,
+    game_id_alphabet: ConfigGameIdAlphabet,
+    // used when game_id_alphabet = "custom"
+    game_id_alphabet_chars: Option<String>,
+    game_id_case: ConfigGameIdCase,
+    // attempts to find a free id in game_generate (defaults to 100) and pause between them (defaults to 0)
+    id_gen_max_attempts: Option<u32>,
+    // maximum count of POST /game/batch (defaults to DEFAULT_MAX_BATCH_GENERATE)
+    max_batch_generate: Option<usize>,
+    id_gen_retry_delay_ms: u64,
+    // game IDs that are never generated nor accepted, case-insensitive (defaults to DEFAULT_RESERVED_GAME_IDS)
+    reserved_game_ids: Option<Vec<String>>,
+    // paths listed in robots.txt (defaults to /admin/ and /game)
+    robots_disallow: Option<Vec<String>>,
+    // realm shown by browsers when asking for credentials (defaults to "game broker")
+    auth_realm: Option<String>,
+    // POST /admin/reset clears all the runtime state (for test suites, never in production)
+    allow_reset: bool,
+    // directory of the internal web UI with the internal-dynamic feature (defaults to ../ai_wargame_web next to Cargo.toml)
+    internal_assets_path: Option<String>,
+    // Link preload headers for the assets of the internal web UI (https only)
+    http2_push: bool,
+    // MIME types served by the [[statics]] that have no allowed_types of their own (defaults to all types)
+    default_allowed_types: Option<Vec<String>>,
+    // fixed delay added to the API requests (simulate feature only, [latency] for more options)
+    simulate_latency_ms: u64,
+    // log a warning when a turn repeats the coordinates of the previous one
+    warn_duplicate_moves: bool,
+    // reject such turns with 409 (implies warn_duplicate_moves)
+    reject_duplicate_moves: bool,
+    // a turn with the same body as the previous one from the same user within this time is not
+    // written again (defaults to DEFAULT_DEDUP_WINDOW_SECS, 0 = off)
+    dedup_window_secs: Option<u64>,
+    // the reply of game_get is serialized once per version of a game read more than this many
+    // times (defaults to DEFAULT_CACHE_MIN_READS, 0 = off)
+    cache_min_reads: Option<u64>,
+}
+
+const DEFAULT_ROBOTS_DISALLOW: &[&str] = &["/admin/", "/game"];
+
+// Crockford Base32 (no I, L, O or U)
+const UNAMBIGUOUS_ALPHABET: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const GAME_ID_LEN: usize = 8;
+const DEFAULT_ID_GEN_MAX_ATTEMPTS: u32 = 100;
+const DEFAULT_MAX_BATCH_GENERATE: usize = 50;
+const DEFAULT_DEDUP_WINDOW_SECS: u64 = 5;
+const DEFAULT_CACHE_MIN_READS: u64 = 3;
+const DEFAULT_RESERVED_GAME_IDS: &[&str] = &["admin", "health", "ready", "state", "batch", "history"];
+const DEFAULT_AUTH_REALM: &str = "game broker";
+
+impl ConfigGeneral {
+    // the cleaner runs for these even when games never expire
+    fn has_cleanup_limits(&self) -> bool {
+        self.hard_max_age_secs.is_some() || self.hard_max_games.is_some() || self.archived_retention_secs.is_some()
+    }
+    fn export_signer(&self) -> Result<Option<Arc<signing::ExportSigner>>,String> {
+        if !self.enable_signing {
+            return Ok(None);
+        }
+        let Some(path) = self.signing_key.as_deref() else {
+            return Err(String::from("general.enable_signing requires general.signing_key"));
+        };
+        signing::ExportSigner::load(std::path::Path::new(path)).map(|signer| Some(Arc::new(signer)))
+    }
+    fn game_id_alphabet(&self) -> Result<Vec<char>,String> {
+        let alphabet: Vec<char> = match self.game_id_alphabet {
+            ConfigGameIdAlphabet::Default => return Ok(nanoid::alphabet::SAFE.to_vec()),
+            ConfigGameIdAlphabet::Unambiguous => UNAMBIGUOUS_ALPHABET.chars().collect(),
+            ConfigGameIdAlphabet::Custom => self.game_id_alphabet_chars.as_deref()
+                .ok_or("game_id_alphabet = \"custom\" requires game_id_alphabet_chars")?
+                .chars().collect(),
+        };
+        if let Some(c) = alphabet.iter().find(|c| ['/','\\','?','#',' '].contains(c)) {
+            return Err(format!("game ID alphabet cannot contain {c:?}"));
+        }
+        let mut distinct = alphabet.clone();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() < 8 || distinct.len() > 256 {
+            return Err(format!("game ID alphabet must have between 8 and 256 distinct characters, not {}",distinct.len()));
+        }
+        Ok(distinct)
+    }
+    fn reserved_game_ids(&self) -> Vec<String> {
+        match self.reserved_game_ids.as_ref() {
+            Some(ids) => ids.iter().map(|id| id.to_lowercase()).collect(),
+            None => DEFAULT_RESERVED_GAME_IDS.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+    fn auth_realm(&self) -> Result<String,String> {
+        let realm = self.auth_realm.as_deref().unwrap_or(DEFAULT_AUTH_REALM);
+        if let Some(c) = realm.chars().find(|c| !(' '..='~').contains(c) || *c == '"' || *c == '\\') {
+            return Err(format!("auth_realm cannot contain {c:?}"));
+        }
+        Ok(realm.to_string())
+    }
+    fn robots_txt(&self) -> String {
+        let disallow = match self.robots_disallow.as_ref() {
+            Some(paths) => paths.clone(),
+            None => DEFAULT_ROBOTS_DISALLOW.iter().map(|path| path.to_string()).collect(),
+        };
+        let mut txt = String::from("User-agent: *\n");
+        for path in disallow {
+            txt.push_str(&format!("Disallow: {path}\n"));
+        }
+        txt
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConfigGameIdAlphabet {
+    // nanoid (letters, digits, '_' and '-')
+    #[default]
+    Default,
+    Unambiguous,
+    Custom,
+}
+
+// case of the game ids used as keys (ids in the wrong case are converted, not rejected)
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConfigGameIdCase {
+    Lower,
+    Upper,
+    #[default]
+    #[serde(rename = "as-is")]
+    AsIs,
+}
+
+impl ConfigGameIdCase {
+    fn apply(self, gameid: &str) -> String {
+        match self {
+            ConfigGameIdCase::Lower => gameid.to_lowercase(),
+            ConfigGameIdCase::Upper => gameid.to_uppercase(),
+            ConfigGameIdCase::AsIs => gameid.to_string(),
+        }
+    }
+}
+
+// seconds (integer) or a string such as "90s", "30m", "2h", "1d" or "1h30m"
+#[derive(Deserialize,Debug,Copy,Clone,PartialEq)]
+#[serde(try_from = "ConfigDurationValue")]
+struct ConfigDuration(Duration);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigDurationValue {
+    Secs(u64),
+    Text(String),
+}
+
+impl TryFrom<ConfigDurationValue> for ConfigDuration {
+    type Error = String;
+    fn try_from(value: ConfigDurationValue) -> Result<Self,Self::Error> {
+        match value {
+            ConfigDurationValue::Secs(secs) => Ok(ConfigDuration(Duration::from_secs(secs))),
+            ConfigDurationValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for ConfigDuration {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Self,Self::Err> {
+        let invalid = || format!("invalid duration {text:?} (expected seconds or a value like \"30m\", \"2h\" or \"1d\")");
+        let mut secs: u64 = 0;
+        let mut rest = text.trim();
+        if rest.is_empty() { return Err(invalid()); }
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+            let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+            let unit = match rest[..unit_len].trim() {
+                "s" | "" => 1,
+                "m" => 60,
+                "h" => 60 * 60,
+                "d" => 24 * 60 * 60,
+                _ => return Err(invalid()),
+            };
+            rest = &rest[unit_len..];
+            secs = value.checked_mul(unit).and_then(|value| secs.checked_add(value)).ok_or_else(invalid)?;
+        }
+        Ok(ConfigDuration(Duration::from_secs(secs)))
+    }
+}
+
+impl std::fmt::Display for ConfigDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut secs = self.0.as_secs();
+        if secs == 0 { return write!(f, "0s"); }
+        for (unit,unit_secs) in [("d",24 * 60 * 60),("h",60 * 60),("m",60),("s",1)] {
+            if secs >= unit_secs {
+                write!(f, "{}{}", secs / unit_secs, unit)?;
+                secs %= unit_secs;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConfigDuration {
+    fn as_secs(&self) -> u64 {
+        self.0.as_secs()
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+struct ConfigUser {
+    name: String,
+    #[serde(default = "ConfigUserRole::default_user")]
+    role: ConfigUserRole,
+    // empty when read from Vault
+    #[serde(default)]
+    password: String,
+    // KV secret holding the password (vault feature), exclusive with password
+    vault_path: Option<String>,
+    // field of the secret (default "password")
+    vault_key: Option<String>,
+    // authenticates the user with a TLS client certificate having this CN
+    certificate_cn: Option<String>,
+    // replaces the global [rate_limit] for this user (admins are unlimited otherwise)
+    rate_limit_override: Option<ConfigRateLimit>,
+}
+
+#[derive(Serialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RateLimitSource {
+    Global,
+    Override,
+    Admin,
+}
+
+impl ConfigUser {
+    // basic auth and ?password: an empty password never matches (users with only a
+    // certificate_cn authenticate with their client certificate)
+    fn accepts_password(&self, password: &str) -> bool {
+        !self.password.is_empty() && self.password == password
+    }
+    fn rate_limit(&self, global: &ConfigRateLimit) -> (RateLimitSource, ConfigRateLimit) {
+        match self.rate_limit_override.as_ref() {
+            Some(limit) => (RateLimitSource::Override, limit.or(global)),
+            None if self.role >= ConfigUserRole::Admin => (RateLimitSource::Admin, ConfigRateLimit { unlimited: true, ..Default::default() }),
+            None => (RateLimitSource::Global, *global),
+        }
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigStatic {
+    uri: String,
+    path: String,
+    // other files are rejected with 403 (defaults to general.default_allowed_types)
+    allowed_types: Option<Vec<String>>,
+    // serve all types even if general.default_allowed_types is set
+    all_types: bool,
+    // missing files are answered with index.html (client side routing of single-page applications)
+    spa_fallback: bool,
+    // added to every response of the route (ex: Surrogate-Control for a CDN)
+    extra_headers: Vec<ConfigHeader>,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigHeader {
+    name: String,
+    // "auto" for ETag: computed from the modification time of the file
+    value: String,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigInternal {
+    // added to every response of the web UI (ETag = "auto": SHA-256 of the asset)
+    headers: std::collections::BTreeMap<String,String>,
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigTLS {
+    cert: String,
+    key: String,
+    enabled: ConfigTLSType,
+    // plain http listener that redirects everything to https (https mode only)
+    http_redirect_port: Option<u32>,
+    // public https port used in redirects (defaults to network.port)
+    https_port: Option<u32>,
+    // mutual TLS: clients must present a certificate signed by client_ca_cert (https mode only)
+    require_client_cert: bool,
+    client_ca_cert: Option<String>,
+    // certificates selected by the SNI hostname (cert and key above are the fallback)
+    certs: Vec<ConfigTLSCert>,
+    // connections that do not complete the TLS handshake in time are dropped
+    handshake_timeout_secs: Option<u64>,
+    // both mode: plain http on this port and https only on network.port (instead of both on network.port)
+    http_port: Option<u32>,
+    // both mode with http_port: redirect the plain http requests to https instead of serving them
+    http_redirect_only: bool,
+    // certificate obtained from an ACME CA with the HTTP-01 challenge (instead of cert and key)
+    acme_domain: Option<String>,
+    // account key and certificates (defaults to ./acme-cache/)
+    acme_cache: Option<String>,
+    // email address given to the CA (expiry notices)
+    acme_contact: Option<String>,
+    // directory URL of the CA (defaults to Let's Encrypt)
+    acme_directory: Option<String>,
+    // roots used to connect to the CA (defaults to /etc/ssl/certs/ca-certificates.crt)
+    acme_ca_file: Option<String>,
+    // max-age of the Strict-Transport-Security header of the https responses
+    hsts_max_age_secs: Option<u64>,
+    // adds the preload directive (see https://hstspreload.org for its requirements)
+    hsts_preload: bool,
+    // both mode: plain http requests are always redirected to https (rejected in http mode)
+    enforce_https: bool,
+}
+
+const DEFAULT_HSTS_MAX_AGE_SECS: u64 = 63072000;
+
+impl ConfigTLS {
+    fn hsts(&self) -> String {
+        let max_age = self.hsts_max_age_secs.unwrap_or(DEFAULT_HSTS_MAX_AGE_SECS);
+        match self.hsts_preload {
+            true => format!("max-age={max_age}; includeSubDomains; preload"),
+            false => format!("max-age={max_age}; includeSubDomains"),
+        }
+    }
+    fn handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.handshake_timeout_secs.unwrap_or(handshake::DEFAULT_TIMEOUT_SECS))
+    }
+    // plain http port on which the CA fetches the HTTP-01 challenges (port 80 of acme_domain from the outside)
+    fn acme_challenge_port(&self, network_port: u32) -> Option<u32> {
+        match self.enabled {
+            ConfigTLSType::Http => None,
+            ConfigTLSType::Https => self.http_redirect_port,
+            ConfigTLSType::Both => Some(self.http_port.unwrap_or(network_port)),
+        }
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone)]
+#[serde(default)]
+struct ConfigTLSCert {
+    sni: String,
+    cert: String,
+    key: String,
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ConfigTLSType {
+    #[default]
+    Http,
+    Https,
+    Both,
+}
+
+#[derive(Deserialize,Default,Debug,Copy,Clone,PartialEq,PartialOrd)]
+#[serde(rename_all = "lowercase")]
+// the order of the roles is important for authentication (admin > user > guest)
+enum ConfigUserRole {
+    #[default]
+    Guest,
     User,
     Admin,
 }
@@ -138,11 +1623,56 @@ impl ConfigUserRole {
     fn default_user() -> Self { Self::User }
 }
 
+// same names as in the config files (lowercase)
+impl std::fmt::Display for ConfigUserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ConfigUserRole::Guest => "guest",
+            ConfigUserRole::User => "user",
+            ConfigUserRole::Admin => "admin",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// for roles given on the command line or in environment variables (case insensitive)
+impl FromStr for ConfigUserRole {
+    type Err = UnknownRoleError;
+    fn from_str(text: &str) -> Result<Self,Self::Err> {
+        [ConfigUserRole::Guest, ConfigUserRole::User, ConfigUserRole::Admin].into_iter()
+            .find(|role| text.trim().eq_ignore_ascii_case(&role.to_string()))
+            .ok_or_else(|| UnknownRoleError(text.to_string()))
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+struct UnknownRoleError(String);
+
+impl std::fmt::Display for UnknownRoleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown role {:?} (expected guest, user or admin)", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRoleError {}
+
 #[derive(Deserialize,Debug,Clone)]
 #[serde(default)] 
 struct ConfigNetwork {
     ip: String,
     port: u32,
+    // plain http connections that do not send the request headers in time are closed
+    accept_timeout_secs: u64,
+    // reverse proxies (IP addresses) whose X-Forwarded-Proto header is used in the URLs given to clients
+    trusted_proxies: Vec<String>,
+}
+
+impl ConfigNetwork {
+    fn trusted_proxies(&self) -> Result<Vec<IpAddr>,String> {
+        self.trusted_proxies.iter()
+            .map(|proxy| proxy.parse().map_err(|_| format!("network.trusted_proxies: {proxy:?} is not an IP address")))
+            .collect()
+    }
 }
 
 impl Default for ConfigNetwork {
@@ -150,6 +1680,8 @@ impl Default for ConfigNetwork {
         ConfigNetwork { 
             ip: "127.0.0.1".to_string(), 
             port: 8000,
+            accept_timeout_secs: 10,
+            trusted_proxies: Vec::new(),
         }
     }
 }
@@ -160,198 +1692,3093 @@ impl From<ConfigNetwork> for SocketAddr {
     }
 }
 
+// name of the authenticated user (None for unauthenticated requests)
+#[derive(Default,Debug,Clone)]
+struct UserName(Option<String>);
+
 #[derive(Deserialize,Default,Debug,Clone)]
 struct RequestParams {
     refresh: Option<usize>,
+    from_turn: Option<u16>,
+    to_turn: Option<u16>,
     username: Option<String>,
     password: Option<String>,
+    include_token: Option<bool>,
+    // add the _links of the game to the reply
+    links: Option<bool>,
+    // false: game_get and game_post reply with the turn only (defaults to general.default_envelope)
+    envelope: Option<bool>,
+    // indented JSON response
+    pretty: Option<bool>,
+    // long polling: wait up to N seconds for a version newer than since_version
+    wait: Option<u64>,
+    since_version: Option<u64>,
+    // game_post: the current turn posted again is not rewritten (409 if its move differs)
+    idempotent_by_turn: Option<bool>,
 }
 
 #[derive(Template)]
 #[template(path = "hello.html")]
 struct GameTemplate<'a> {
     refresh: Option<usize>,
-    game_data: &'a GameData,
+    // pinned games first, then by game id
+    game_data: Vec<(&'a String, &'a GameEntry)>,
+    // QR code column (qrcode feature)
+    qrcode: bool,
+    // requests rejected by the [access] checks (None when there are none configured)
+    blocked_requests: Option<u64>,
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct GameGenerateRequest {
+    // overrides general.max_turns_per_game (0 = unlimited)
+    max_turns: Option<u16>,
+}
+
+#[derive(Serialize,Debug)]
+struct GameTokenReply {
+    id: String,
+    token: String,
+    // unix timestamp (seconds)
+    expires: u64,
 }
 
 async fn game_generate(
-    Query(_params): Query<RequestParams>,
+    Query(params): Query<RequestParams>,
     Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
     State(state): State<SharedState>, 
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    host: Option<Host>,
+    request: Option<Json<GameGenerateRequest>>,
 ) -> impl IntoResponse {
     debug!("Role: {:?}",role);
     if role < ConfigUserRole::User {
         debug!("failed auth from {addr}");
-        return authenticate().into_response();
+        return authenticate(&state).into_response();
     }
-    let mut gameid;
-    loop {
-        gameid = nanoid!(8);
-        let dict = state.game_data.read().await;
-        if dict.get(&gameid).is_none() { break; }
+    let Json(request) = request.unwrap_or_default();
+    let gameid = if state.storage == ConfigStorageBackend::Noop {
+        // nothing is stored so there is nothing to collide with
+        let gameid = state.game_id_case.apply(&nanoid!(GAME_ID_LEN, &state.game_id_alphabet));
+        debug!(gameid = %gameid, "game not stored (noop storage)");
+        gameid
+    } else {
+        // the lock is only held to check and insert each candidate
+        let host = host.and_then(|Host(hostname)| sni_scope(&state, &hostname));
+        let mut attempt = 1;
+        loop {
+            let gameid = state.game_id_case.apply(&nanoid!(GAME_ID_LEN, &state.game_id_alphabet));
+            if check_game_id_not_reserved(&state, &gameid).is_ok() {
+                let mut dict = state.game_data.write().await;
+                if !dict.contains_key(&gameid) {
+                    dict.insert(gameid.clone(), GameEntry { max_turns: request.max_turns, host, ..GameEntry::new(username.clone()) });
+                    break gameid;
+                }
+            }
+            if attempt >= state.id_gen_max_attempts {
+                error!("no free game id after {attempt} attempts (request from {addr})");
+                return (StatusCode::SERVICE_UNAVAILABLE, "cannot generate a free game id, try again\n").into_response();
+            }
+            attempt += 1;
+            if state.id_gen_retry_delay_ms > 0 {
+                sleep(Duration::from_millis(state.id_gen_retry_delay_ms)).await;
+            }
+        }
+    };
+    state.activity.record(ActivityEvent::GameCreated);
+    if params.include_token.unwrap_or(false) {
+        let Some(username) = username else {
+            return (StatusCode::BAD_REQUEST, "game tokens require an authenticated user\n").into_response();
+        };
+        let claims = token::GameClaims::new(&username, &gameid, state.token_ttl_secs);
+        let token = token::issue(&state.token_secret, &claims);
+        return Json(GameTokenReply { id: gameid, token, expires: claims.exp }).into_response();
     }
     (StatusCode::OK, format!("{}\n",gameid)).into_response()
 }
 
-async fn game_get(
-    Path(gameid): Path<String>,
-    Query(_params): Query<RequestParams>,
+#[derive(Deserialize,Debug)]
+struct GameBatchRequest {
+    count: i64,
+    // expiry of the games while they are not played (defaults to general.created_expires_secs, which
+    // is also the maximum)
+    ttl_secs: Option<u64>,
+    // overrides general.max_turns_per_game (0 = unlimited)
+    max_turns: Option<u16>,
+    // metadata of every game
+    meta: Option<GameMeta>,
+}
+
+#[derive(Serialize,Debug)]
+struct GameBatchReply {
+    ids: Vec<String>,
+}
+
+// all the ids are generated and inserted while holding the lock, so that the batch is
+// created entirely or not at all
+async fn game_batch(
     Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
     State(state): State<SharedState>, 
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> (StatusCode, Json<GameReply>) {
+    host: Option<Host>,
+    Json(request): Json<GameBatchRequest>,
+) -> impl IntoResponse {
     debug!("Role: {:?}",role);
-    let mut reply = GameReply::default();
     if role < ConfigUserRole::User {
         debug!("failed auth from {addr}");
-        reply.success = false;
-        reply.error = Some(String::from("invalid client auth"));
-        return (StatusCode::UNAUTHORIZED, Json(reply));
+        return authenticate(&state).into_response();
     }
-    reply.success = true;
-    let dict = state.game_data.read().await;
-    reply.data = dict.get(&gameid).map(Clone::clone);
-    if let Some(payload) = reply.data.as_ref() {
-        debug!("game {} turn {:03} move {} -> {} read from {addr}",gameid,payload.turn,payload.from,payload.to);
+    let count = match usize::try_from(request.count) {
+        Ok(count) if (1..=state.max_batch_generate).contains(&count) => count,
+        _ => return (StatusCode::UNPROCESSABLE_ENTITY, format!("count must be between 1 and {}\n",state.max_batch_generate)).into_response(),
+    };
+    if let (Some(ttl_secs), Some(max_ttl_secs)) = (request.ttl_secs, state.max_batch_ttl_secs) {
+        if ttl_secs > max_ttl_secs {
+            return (StatusCode::UNPROCESSABLE_ENTITY, format!("ttl_secs is limited to {max_ttl_secs}\n")).into_response();
+        }
+    }
+    if request.meta.as_ref().and_then(|meta| meta.notes.as_ref()).is_some_and(|notes| notes.chars().count() > MAX_NOTES_LEN) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("notes are limited to {MAX_NOTES_LEN} characters\n")).into_response();
+    }
+    if let Some(meta) = request.meta.as_ref() {
+        if let Err(e) = meta.check_players(&state).await {
+            return (StatusCode::UNPROCESSABLE_ENTITY, format!("{e}\n")).into_response();
+        }
+    }
+    let new_id = || state.game_id_case.apply(&nanoid!(GAME_ID_LEN, &state.game_id_alphabet));
+    let mut ids: Vec<String> = Vec::with_capacity(count);
+    if state.storage == ConfigStorageBackend::Noop {
+        // nothing is stored so there is nothing to collide with
+        ids.extend((0..count).map(|_| new_id()));
+    } else {
+        let host = host.and_then(|Host(hostname)| sni_scope(&state, &hostname));
+        let mut dict = state.game_data.write().await;
+        for _ in 0..count {
+            let gameid = (0..state.id_gen_max_attempts).map(|_| new_id())
+                .find(|gameid| check_game_id_not_reserved(&state, gameid).is_ok() && !dict.contains_key(gameid) && !ids.contains(gameid));
+            let Some(gameid) = gameid else {
+                error!("no free game id for a batch of {count} after {} attempts (request from {addr})",state.id_gen_max_attempts);
+                return (StatusCode::SERVICE_UNAVAILABLE, "cannot generate free game ids, try again\n").into_response();
+            };
+            ids.push(gameid);
+        }
+        for gameid in ids.iter() {
+            dict.insert(gameid.clone(), GameEntry {
+                max_turns: request.max_turns,
+                meta: request.meta.clone().unwrap_or_default(),
+                host: host.clone(),
+                created_ttl_secs: request.ttl_secs,
+                ..GameEntry::new(username.clone())
+            });
+        }
     }
-    (StatusCode::OK, Json(reply))
+    for _ in 0..count {
+        state.activity.record(ActivityEvent::GameCreated);
+    }
+    info!(target: audit::AUDIT_TARGET, "batch_generated by {} from {addr}: {}",username.as_deref().unwrap_or("(anonymous)"),ids.join(","));
+    Json(GameBatchReply { ids }).into_response()
 }
 
-async fn game_post(
+// must stay below the request timeout
+const MAX_WAIT_SECS: u64 = 8;
+
+async fn game_get(
     Path(gameid): Path<String>,
-    Query(_params): Query<RequestParams>,
+    Query(params): Query<RequestParams>,
     Extension(role): Extension<ConfigUserRole>,
     State(state): State<SharedState>, 
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    Json(mut payload): Json<GameTurn>
-) -> (StatusCode, Json<GameReply>) {
+) -> impl IntoResponse {
     debug!("Role: {:?}",role);
-    let mut reply = GameReply::default();
+    let envelope = params.envelope.unwrap_or(!state.omit_envelope);
     if role < ConfigUserRole::User {
         debug!("failed auth from {addr}");
-        reply.success = false;
-        reply.error = Some(String::from("invalid client auth"));
-        return (StatusCode::UNAUTHORIZED, Json(reply));
+        return game_reply(StatusCode::UNAUTHORIZED, GameReply::error("invalid client auth"), envelope);
     }
-    payload.updated = Some(SystemTime::now());
-    info!("game {} turn {:03} move {} -> {} written from {addr}",gameid,payload.turn,payload.from,payload.to);
-    reply.success = true;
-    let mut dict = state.game_data.write().await;
-    dict.insert(gameid, payload);
-    reply.data = Some(payload);
-    (StatusCode::OK, Json(reply))
+    if state.storage == ConfigStorageBackend::Noop {
+        return game_reply(StatusCode::OK, GameReply::empty_success(), envelope);
+    }
+    if let (Some(wait), Some(since_version)) = (params.wait, params.since_version) {
+        let version_rx = state.game_data.read().await.get(&gameid).map(|entry| entry.version_tx.subscribe());
+        if let Some(mut version_rx) = version_rx {
+            let wait = Duration::from_secs(wait.min(MAX_WAIT_SECS));
+            let _ = tokio::time::timeout(wait, version_rx.wait_for(|version| *version > since_version)).await;
+        }
+    }
+    let links = params.links.unwrap_or(false).then(|| GameLinks::new(&gameid));
+    let dict = state.game_data.read().await;
+    let Some(entry) = dict.get(&gameid) else {
+        if !envelope {
+            return game_reply(StatusCode::NOT_FOUND, GameReply::error("game not found"), envelope);
+        }
+        return game_reply(StatusCode::OK, GameReply { links, ..GameReply::empty_success() }, envelope);
+    };
+    // only the default form of the reply is cached
+    let cacheable = state.cache_min_reads > 0 && envelope && links.is_none();
+    if cacheable {
+        if let Some(json) = entry.reply_cache.get(entry.version) {
+            state.reply_cache_hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            return (entry.version_headers(), cached_game_reply(json)).into_response();
+        }
+        state.reply_cache_misses.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+    let reply = GameReply {
+        data: entry.turn,
+        meta: Some(entry.meta.clone()),
+        result: entry.result.clone(),
+        turns_remaining: entry.turns_remaining(state.max_turns_per_game),
+        forfeit_at: entry.forfeit_at,
+        links,
+        ..GameReply::empty_success()
+    };
+    if let Some(payload) = reply.data.as_ref() {
+        debug!(gameid = %gameid, "turn {:03} move {} -> {} read from {addr}",payload.turn,payload.from,payload.to);
+    }
+    if cacheable && entry.reply_cache.count_read() > state.cache_min_reads {
+        if let Ok(json) = serde_json::to_vec(&reply) {
+            let json = Bytes::from(json);
+            entry.reply_cache.store(entry.version, json.clone());
+            return (entry.version_headers(), cached_game_reply(json)).into_response();
+        }
+    }
+    (entry.version_headers(), game_reply(StatusCode::OK, reply, envelope)).into_response()
+}
+
+// same response as game_reply for a successful GameReply with the envelope
+fn cached_game_reply(json: Bytes) -> Response {
+    (
+        [
+            (header::HeaderName::from_static("x-broker-success"), String::from("true")),
+            (header::CONTENT_TYPE, String::from("application/json")),
+        ],
+        json,
+    ).into_response()
+}
+
+// one extractor per argument (axum handler)
+#[allow(clippy::too_many_arguments)]
+async fn game_post(
+    Path(gameid): Path<String>,
+    Query(params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    host: Option<Host>,
+    body_hash: Option<Extension<BodyHash>>,
+    Json(mut payload): Json<GameTurn>
+) -> Response {
+    debug!("Role: {:?}",role);
+    let envelope = params.envelope.unwrap_or(!state.omit_envelope);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return game_reply(StatusCode::UNAUTHORIZED, GameReply::error("invalid client auth"), envelope);
+    }
+    if let Err(errors) = payload.validate(state.bounds.as_ref()) {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        debug!(gameid = %gameid, "rejected move {} -> {} from {addr}: {}",payload.from,payload.to,errors.join("; "));
+        return game_reply(StatusCode::UNPROCESSABLE_ENTITY, GameReply::error(errors.join("; ")), envelope);
+    }
+    if !state.move_rules.allows(payload.from, payload.to) {
+        let delta = payload.from.delta(payload.to);
+        debug!(gameid = %gameid, "rejected move {} -> {} from {addr}",payload.from,payload.to);
+        return game_reply(StatusCode::UNPROCESSABLE_ENTITY, GameReply::error(format!("invalid move {} -> {}: delta {:?} is not allowed",payload.from,payload.to,delta)), envelope);
+    }
+    if let Some(validator) = state.validator.as_ref() {
+        let turn = serde_json::to_string(&payload).unwrap_or_default();
+        match validator.validate(&gameid, &turn).await {
+            Ok(()) => (),
+            Err(validator::Rejection::Invalid(reason)) => {
+                debug!(gameid = %gameid, "rejected move {} -> {} from {addr}: {reason}",payload.from,payload.to);
+                return game_reply(StatusCode::UNPROCESSABLE_ENTITY, GameReply::error(format!("invalid move {} -> {}: {reason}",payload.from,payload.to)), envelope);
+            },
+            Err(validator::Rejection::Unavailable(e)) => {
+                error!(gameid = %gameid, "move validator failed: {e}");
+                return game_reply(StatusCode::SERVICE_UNAVAILABLE, GameReply::error("the move validator is unavailable"), envelope);
+            },
+        }
+    }
+    payload.fingerprint = Some(Fingerprint::of(&payload));
+    payload.submitted_at = Some(SubmittedAt::now());
+    if state.storage == ConfigStorageBackend::Noop {
+        payload.updated = Some(SystemTime::now());
+        debug!(gameid = %gameid, "turn {:03} move {} -> {} from {addr} discarded (noop storage)",payload.turn,payload.from,payload.to);
+        state.activity.record(ActivityEvent::TurnPosted);
+        return game_post_reply(&gameid, &params, GameReply::success(payload), envelope);
+    }
+    let mut dict = state.game_data.write().await;
+    if !dict.contains_key(&gameid) {
+        if let Err(e) = check_game_id_not_reserved(&state, &gameid) {
+            return game_reply(StatusCode::CONFLICT, GameReply::error(e), envelope);
+        }
+    }
+    // a new game is only inserted once the turn is written (a rejected turn leaves no game behind)
+    let mut created = None;
+    let entry = match dict.get_mut(&gameid) {
+        Some(entry) => entry,
+        None => created.insert(GameEntry { host: host.and_then(|Host(hostname)| sni_scope(&state, &hostname)), ..GameEntry::new(username.clone()) }),
+    };
+    if let Some(forfeit_at) = entry.forfeit_at {
+        debug!(gameid = %gameid, "turn {:03} rejected from {addr}: turn {forfeit_at:03} was forfeited",payload.turn);
+        return game_reply(StatusCode::CONFLICT, GameReply::error(format!("game was forfeited at turn {forfeit_at}")), envelope);
+    }
+    if entry.result.is_some() {
+        debug!(gameid = %gameid, "turn {:03} rejected from {addr}: game is finished",payload.turn);
+        return game_reply(StatusCode::CONFLICT, GameReply::error("game is finished"), envelope);
+    }
+    let body_hash = body_hash.map(|Extension(hash)| hash);
+    if entry.last_body.as_ref().is_some_and(|last| last.at.elapsed() >= Duration::from_secs(state.dedup_window_secs)) {
+        entry.last_body = None;
+    }
+    if let (Some(last), Some(current)) = (entry.last_body.as_ref(), entry.turn) {
+        if Some(last.hash) == body_hash && last.posted_by == username {
+            // a client resending the same request: the stored turn is returned as if it had been written again
+            debug!(gameid = %gameid, "same body as the previous turn {:03} from {addr}, not written again",current.turn);
+            return game_post_reply(&gameid, &params, GameReply::success(current), envelope);
+        }
+    }
+    if let Some(current) = entry.turn.filter(|current| params.idempotent_by_turn.unwrap_or(false) && current.turn == payload.turn) {
+        if current.from != payload.from || current.to != payload.to {
+            debug!(gameid = %gameid, "turn {:03} move {} -> {} rejected from {addr}: already played as {} -> {}",payload.turn,payload.from,payload.to,current.from,current.to);
+            return game_reply(StatusCode::CONFLICT, GameReply::error(format!("turn {} was already played with a different move {} -> {}",current.turn,current.from,current.to)), envelope);
+        }
+        // a retry: the stored turn is returned as if it had been written again
+        debug!(gameid = %gameid, "turn {:03} move {} -> {} already written, retry from {addr} ignored",payload.turn,payload.from,payload.to);
+        return game_post_reply(&gameid, &params, GameReply::success(current), envelope);
+    }
+    if let Some(max_turns) = entry.max_turns(state.max_turns_per_game) {
+        if payload.turn >= max_turns {
+            info!(gameid = %gameid, "turn {:03} rejected from {addr}: max turns reached",payload.turn);
+            // the turn is not written but the game is finished (a new version for the long-polling clients)
+            if entry.result.is_none() {
+                entry.result = Some(GameResult { winner: None, reason: Some(String::from("max_turns")) });
+                entry.touch();
+            }
+            return game_reply(StatusCode::CONFLICT, GameReply::error("max turns reached, game must be concluded"), envelope);
+        }
+    }
+    if let Some(previous) = entry.turn.filter(|previous| state.warn_duplicate_moves && previous.turn != payload.turn) {
+        if previous.from == payload.from && previous.to == payload.to {
+            warn!("game {} turns {:03} and {:03} have the same move {} -> {} (from {addr})",gameid,previous.turn,payload.turn,payload.from,payload.to);
+            if state.reject_duplicate_moves {
+                return game_reply(StatusCode::CONFLICT, GameReply::error("duplicate move detected: same coordinates as previous turn"), envelope);
+            }
+        }
+    }
+    payload.updated = Some(SystemTime::now());
+    info!(gameid = %gameid, "turn {:03} move {} -> {} written from {addr}",payload.turn,payload.from,payload.to);
+    entry.turn = Some(payload);
+    entry.push_history(payload, state.max_history_per_game);
+    entry.last_body = body_hash.map(|hash| LastBody { hash, posted_by: username.clone(), at: Instant::now() });
+    if let Some(name) = username.as_ref().filter(|name| !entry.players.contains(name)) {
+        entry.players.push(name.clone());
+    }
+    entry.posted_by = username;
+    entry.schedule_turn_timer(&gameid, &state);
+    entry.touch();
+    if let Some(created) = created {
+        dict.insert(gameid.clone(), created);
+        state.activity.record(ActivityEvent::GameCreated);
+    }
+    state.activity.record(ActivityEvent::TurnPosted);
+    game_post_reply(&gameid, &params, GameReply::success(payload), envelope)
+}
+
+// successful game_post reply with the location (and optionally the links) of the game
+fn game_post_reply(gameid: &str, params: &RequestParams, mut reply: GameReply, envelope: bool) -> Response {
+    if params.links.unwrap_or(false) {
+        reply.links = Some(GameLinks::new(gameid));
+    }
+    // canonical URL of the game (an id that is not a valid header value is only possible in theory)
+    match HeaderValue::from_str(&format!("/game/{gameid}")) {
+        Ok(location) => ([(header::LOCATION, location)], game_reply(StatusCode::OK, reply, envelope)).into_response(),
+        Err(_) => game_reply(StatusCode::OK, reply, envelope),
+    }
+}
+
+// reply of game_get and game_post with X-Broker-Success, without the envelope the
+// body is the turn alone (or {"error":...}) and only the status tells if it succeeded
+fn game_reply(status: StatusCode, reply: GameReply, envelope: bool) -> Response {
+    let success_header = |success: bool| [(header::HeaderName::from_static("x-broker-success"), success.to_string())];
+    if envelope {
+        return (status, success_header(reply.success), Json(reply)).into_response();
+    }
+    match (reply.success, reply.data) {
+        (true, Some(turn)) => (status, success_header(true), Json(turn)).into_response(),
+        // there is no turn to return
+        (true, None) => (StatusCode::NOT_FOUND, success_header(false), Json(serde_json::json!({ "error": "no turn played yet" }))).into_response(),
+        (false, _) => (status, success_header(false), Json(serde_json::json!({ "error": reply.error }))).into_response(),
+    }
+}
+
+async fn game_history(
+    Path(gameid): Path<String>,
+    Query(_params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    match dict.get(&gameid) {
+        Some(entry) => Json(&entry.history).into_response(),
+        None => GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response(),
+    }
+}
+
+#[derive(Serialize,Debug)]
+struct TurnDiff {
+    from: GameTurn,
+    to: GameTurn,
+    delta: TurnDelta,
+}
+
+#[derive(Serialize,Debug)]
+struct TurnDelta {
+    from_coord_changed: bool,
+    to_coord_changed: bool,
+    from_delta: (i16,i16),
+    to_delta: (i16,i16),
+    turn_advance: u16,
+}
+
+impl TurnDiff {
+    fn new(from: GameTurn, to: GameTurn) -> Self {
+        let from_delta = from.from.delta(to.from);
+        let to_delta = from.to.delta(to.to);
+        TurnDiff {
+            from, to,
+            delta: TurnDelta {
+                from_coord_changed: from_delta != (0,0),
+                to_coord_changed: to_delta != (0,0),
+                from_delta,
+                to_delta,
+                turn_advance: to.turn - from.turn,
+            },
+        }
+    }
+}
+
+async fn game_diff(
+    Path(gameid): Path<String>,
+    Query(params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let error = |status: StatusCode, msg: String| {
+        GameReply::error(msg).with_status(status).into_response()
+    };
+    let (Some(from_turn), Some(to_turn)) = (params.from_turn, params.to_turn) else {
+        return error(StatusCode::BAD_REQUEST, String::from("from_turn and to_turn are required"));
+    };
+    if from_turn > to_turn {
+        return error(StatusCode::BAD_REQUEST, format!("from_turn {from_turn} is after to_turn {to_turn}"));
+    }
+    let dict = state.game_data.read().await;
+    let Some(entry) = dict.get(&gameid) else {
+        return error(StatusCode::NOT_FOUND, String::from("game not found"));
+    };
+    let Some(from) = entry.turn_in_history(from_turn) else {
+        return error(StatusCode::NOT_FOUND, format!("turn {from_turn} not found"));
+    };
+    let Some(to) = entry.turn_in_history(to_turn) else {
+        return error(StatusCode::NOT_FOUND, format!("turn {to_turn} not found"));
+    };
+    Json(TurnDiff::new(*from, *to)).into_response()
+}
+
+// turn of the history in display format (letter-number coordinates)
+#[derive(Serialize,Debug,PartialEq)]
+struct MoveRecord {
+    turn: u16,
+    from: String,
+    to: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    move_type: Option<MoveType>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    forfeited: bool,
+    // UTC time the turn was posted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    at: Option<String>,
+}
+
+impl From<&GameTurn> for MoveRecord {
+    fn from(turn: &GameTurn) -> Self {
+        MoveRecord {
+            turn: turn.turn,
+            from: turn.from.to_string(),
+            to: turn.to.to_string(),
+            move_type: turn.move_type,
+            forfeited: turn.forfeited,
+            at: turn.updated.map(|updated| snapshot::iso8601(updated.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0))),
+        }
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum MovesFormat {
+    #[default]
+    Json,
+    // chess style approximation: "1. A3-B4; 2. B1-B2"
+    Pgn,
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct MovesParams {
+    format: MovesFormat,
+}
+
+fn moves_pgn(moves: &[MoveRecord]) -> String {
+    moves.iter()
+        .map(|m| format!("{}. {}-{}",m.turn,m.from,m.to))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+async fn game_moves(
+    Path(gameid): Path<String>,
+    Query(params): Query<MovesParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    let Some(entry) = dict.get(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    let moves: Vec<MoveRecord> = entry.history.iter().map(MoveRecord::from).collect();
+    match params.format {
+        MovesFormat::Json => Json(moves).into_response(),
+        MovesFormat::Pgn => ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], moves_pgn(&moves)).into_response(),
+    }
+}
+
+async fn game_annotation_post(
+    Path((gameid,turn_num)): Path<(String,u16)>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<AnnotationRequest>
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let error = |status: StatusCode, msg: String| {
+        GameReply::error(msg).with_status(status).into_response()
+    };
+    if request.text.chars().count() > MAX_ANNOTATION_LEN {
+        return error(StatusCode::UNPROCESSABLE_ENTITY, format!("annotations are limited to {MAX_ANNOTATION_LEN} characters"));
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return error(StatusCode::NOT_FOUND, String::from("game not found"));
+    };
+    if entry.annotations.len() >= state.max_annotations_per_game {
+        return error(StatusCode::CONFLICT, format!("games are limited to {} annotations",state.max_annotations_per_game));
+    }
+    let annotation = Annotation {
+        turn_num,
+        text: request.text,
+        author: request.author,
+        created_at: unix_time_secs(),
+        posted_by: username,
+    };
+    info!(gameid = %gameid, "turn {:03} annotated from {addr}",turn_num);
+    entry.annotations.push(annotation.clone());
+    entry.touch();
+    Json(annotation).into_response()
+}
+
+async fn game_annotations(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    match dict.get(&gameid) {
+        Some(entry) => Json(entry.annotations.clone()).into_response(),
+        None => GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response(),
+    }
+}
+
+// idx counts the annotations of the given turn only
+async fn game_annotation_delete(
+    Path((gameid,turn_num,idx)): Path<(String,u16,usize)>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let error = |status: StatusCode, msg: String| {
+        GameReply::error(msg).with_status(status).into_response()
+    };
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return error(StatusCode::NOT_FOUND, String::from("game not found"));
+    };
+    let Some(position) = entry.annotations.iter().enumerate()
+        .filter(|(_,annotation)| annotation.turn_num == turn_num)
+        .nth(idx)
+        .map(|(position,_)| position) else {
+        return error(StatusCode::NOT_FOUND, format!("annotation {idx} of turn {turn_num} not found"));
+    };
+    let annotation = &entry.annotations[position];
+    if role < ConfigUserRole::Admin && (username.is_none() || annotation.posted_by != username) {
+        return error(StatusCode::FORBIDDEN, String::from("only the user who posted an annotation can delete it"));
+    }
+    info!(gameid = %gameid, "turn {:03} annotation {} deleted from {addr}",turn_num,idx);
+    let annotation = entry.annotations.remove(position);
+    entry.touch();
+    Json(annotation).into_response()
+}
+
+async fn game_turn_limit_post(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(turn_limit): Json<TurnLimit>
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    entry.turn_limit = Some(turn_limit);
+    entry.schedule_turn_timer(&gameid, &state);
+    entry.touch();
+    warn!("game {} turn limit set to {}s from {addr}",gameid,turn_limit.secs_per_turn);
+    Json(turn_limit).into_response()
+}
+
+async fn game_delete(
+    Path(gameid): Path<String>,
+    Query(_params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> (StatusCode, Json<GameReply>) {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return GameReply::error("invalid client auth").with_status(StatusCode::UNAUTHORIZED);
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(mut entry) = dict.remove(&gameid) else {
+        return GameReply::empty_success().with_status(StatusCode::OK);
+    };
+    entry.cancel_turn_timer();
+    info!(gameid = %gameid, "game deleted from {addr}");
+    GameReply { data: entry.turn, ..GameReply::empty_success() }.with_status(StatusCode::OK)
+}
+
+async fn game_meta_patch(
+    Path(gameid): Path<String>,
+    Query(_params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(update): Json<GameMeta>
+) -> (StatusCode, Json<GameReply>) {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return GameReply::error("invalid client auth").with_status(StatusCode::UNAUTHORIZED);
+    }
+    if update.notes.as_ref().is_some_and(|notes| notes.chars().count() > MAX_NOTES_LEN) {
+        return GameReply::error(format!("notes are limited to {MAX_NOTES_LEN} characters")).with_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    if let Err(e) = update.check_players(&state).await {
+        return GameReply::error(e).with_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND);
+    };
+    if !entry.can_be_managed_by(role, &username) {
+        debug!(gameid = %gameid, "metadata update denied to {:?} from {addr}",username);
+        return GameReply::error("only the creator of the game can update its metadata").with_status(StatusCode::FORBIDDEN);
+    }
+    entry.meta.merge(update);
+    entry.touch();
+    info!(gameid = %gameid, "game metadata updated from {addr}");
+    GameReply { data: entry.turn, meta: Some(entry.meta.clone()), ..GameReply::empty_success() }.with_status(StatusCode::OK)
+}
+
+async fn admin_state(
+    Query(params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    uri: Uri, Host(hostname): Host,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    debug!("request from {addr} for {}{}",hostname,uri.path());
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let scope = sni_scope(&state, &hostname);
+    // the template is rendered without holding the lock
+    let dict = state.snapshot().await;
+    let mut game_data: Vec<(&String, &GameEntry)> = dict.iter()
+        .filter(|(_,entry)| scope.is_none() || entry.host == scope)
+        .collect();
+    if let Some(response) = memguard::over_limit("GET /admin/state", game_data.len().saturating_mul(memguard::BYTES_PER_GAME), state.max_response_memory_bytes) {
+        return response;
+    }
+    game_data.sort_by(|(id1,entry1),(id2,entry2)| entry2.pinned.cmp(&entry1.pinned).then(id1.cmp(id2)));
+    // ?refresh=0 turns off the default interval
+    let refresh = Some(params.refresh.unwrap_or(state.default_refresh_secs)).filter(|secs| *secs > 0);
+    let blocked_requests = state.access.is_enabled().then(|| state.blocked_requests.load(std::sync::atomic::Ordering::Relaxed));
+    (StatusCode::OK, GameTemplate { refresh, game_data, qrcode: cfg!(feature = "qrcode"), blocked_requests }.into_response()).into_response()
+}
+
+async fn admin_clear(
+    Query(_params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    uri: Uri, Host(hostname): Host,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    warn!("request from {addr} for {}{}",hostname,uri.path());
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let mut dict = state.game_data.write().await;
+    for (_, mut entry) in dict.drain() {
+        entry.cancel_turn_timer();
+    }
+    (StatusCode::OK, "cleared all games from internal state\n").into_response()
+}
+
+#[derive(Serialize,Debug)]
+struct ResetReply {
+    games: usize,
+    archived_games: usize,
+    rate_limit_entries: usize,
+    ratings: usize,
+    activity_events: usize,
+    latency_routes: usize,
+    consumed_invites: usize,
+}
+
+// same state as a fresh start, except for the users and the config (only routed with general.allow_reset)
+async fn admin_reset(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    // the game data stays locked so that no request sees a partial reset
+    let mut dict = state.game_data.write().await;
+    for entry in dict.values_mut() {
+        entry.cancel_turn_timer();
+    }
+    let reply = ResetReply {
+        games: dict.drain().count(),
+        // locked after the game data, like admin_games_archive
+        archived_games: state.archived_games.write().await.drain().count(),
+        rate_limit_entries: state.rate_limiter.clear(),
+        ratings: state.ratings.write().await.drain().count(),
+        activity_events: state.activity.clear(),
+        latency_routes: state.latencies.lock().map(|mut latencies| latencies.drain().count()).unwrap_or(0),
+        consumed_invites: state.consumed_invites.write().await.drain().count(),
+    };
+    warn!("reset from {addr}: {:?}",reply);
+    Json(reply).into_response()
+}
+
+#[derive(Deserialize,Default,Debug)]
+struct BulkDeleteParams {
+    older_than_secs: Option<u64>,
+    status: Option<GameStatus>,
+    tag: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize,Debug)]
+struct BulkDeleteReply {
+    deleted_count: usize,
+    remaining_count: usize,
+    dry_run: bool,
+}
+
+// all the filters must match
+async fn admin_games_delete(
+    Query(params): Query<BulkDeleteParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    if params.tag.is_some() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "games have no tags, cannot filter by tag\n").into_response();
+    }
+    if params.older_than_secs.is_none() && params.status.is_none() {
+        return (StatusCode::BAD_REQUEST, "at least one filter is required (use /admin/clear to delete all games)\n").into_response();
+    }
+    // pinned games are never deleted because of their age
+    let matches = |entry: &GameEntry| {
+        params.older_than_secs.is_none_or(|secs| !entry.pinned && entry.last_activity().elapsed().is_ok_and(|age| age.as_secs() > secs))
+            && params.status.is_none_or(|status| entry.status() == status)
+    };
+    let mut dict = state.game_data.write().await;
+    let deleted_count = if params.dry_run {
+        dict.values().filter(|entry| matches(entry)).count()
+    } else {
+        let before = dict.len();
+        dict.retain(|_,entry| !matches(entry));
+        before - dict.len()
+    };
+    let remaining_count = dict.len() - if params.dry_run { deleted_count } else { 0 };
+    warn!("{} {} games from {addr} ({:?})",if params.dry_run { "would delete" } else { "deleted" },deleted_count,params);
+    Json(BulkDeleteReply { deleted_count, remaining_count, dry_run: params.dry_run }).into_response()
+}
+
+async fn game_result_post(
+    Path(gameid): Path<String>,
+    Query(_params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(result): Json<GameResult>
+) -> (StatusCode, Json<GameReply>) {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return GameReply::error("invalid client auth").with_status(StatusCode::UNAUTHORIZED);
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND);
+    };
+    if !entry.can_be_managed_by(role, &username) {
+        return GameReply::error("only the creator of the game can record its result").with_status(StatusCode::FORBIDDEN);
+    }
+    if entry.result.is_some() {
+        return GameReply::error("game result already recorded").with_status(StatusCode::CONFLICT);
+    }
+    info!(gameid = %gameid, "game result {:?} recorded from {addr}",result);
+    let score = match result.winner {
+        Some(GameWinner::White) => Some(1.0),
+        Some(GameWinner::Black) => Some(0.0),
+        Some(GameWinner::Draw) => Some(0.5),
+        None => None,
+    };
+    // only players who are configured users and posted turns in the game are rated (the
+    // metadata alone can name anyone)
+    if let (Some(score), Some(white), Some(black)) = (score, entry.meta.player_white.as_deref(), entry.meta.player_black.as_deref()) {
+        let played = |name: &str| entry.players.iter().any(|player| player == name);
+        let configured = {
+            let users = state.users.read().await;
+            [white, black].iter().all(|name| users.iter().any(|user| user.name == *name))
+        };
+        if white != black && played(white) && played(black) && configured {
+            let mut ratings = state.ratings.write().await;
+            rating::update_ratings(&mut ratings, &gameid, white, black, score, state.elo_k_factor);
+        }
+    }
+    entry.result = Some(result);
+    entry.touch();
+    let reply = GameReply {
+        data: entry.turn,
+        meta: Some(entry.meta.clone()),
+        result: entry.result.clone(),
+        ..GameReply::empty_success()
+    };
+    reply.with_status(StatusCode::OK)
+}
+
+async fn leaderboard(
+    State(state): State<SharedState>, 
+) -> impl IntoResponse {
+    let ratings = state.ratings.read().await;
+    let mut board: Vec<UserRating> = ratings.values().map(UserRating::summary).collect();
+    board.sort_by(|a,b| b.elo.total_cmp(&a.elo).then_with(|| a.username.cmp(&b.username)));
+    Json(board)
+}
+
+async fn user_rating(
+    Path(name): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let ratings = state.ratings.read().await;
+    match ratings.get(&name) {
+        Some(rating) => Json(rating.clone()).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no rating for user {name}\n")).into_response(),
+    }
+}
+
+#[derive(Deserialize,Debug)]
+struct RatingOverride {
+    elo: f64,
+}
+
+async fn admin_user_rating(
+    Path(name): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(rating_override): Json<RatingOverride>
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    if !rating_override.elo.is_finite() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "elo must be a finite number\n").into_response();
+    }
+    let mut ratings = state.ratings.write().await;
+    let rating = ratings.entry(name.clone()).or_insert_with(|| UserRating::new(&name));
+    warn!("rating of {} set from {} to {} from {addr}",name,rating.elo,rating_override.elo);
+    rating.set_elo(rating_override.elo);
+    Json(rating.clone()).into_response()
+}
+
+#[derive(Deserialize,Default,Debug)]
+struct GamesParams {
+    move_type: Option<MoveType>,
+}
+
+// current turn of every game (that has one), by game id
+async fn games(
+    Query(params): Query<GamesParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    // the game ids are not listed to players
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    let turns: std::collections::BTreeMap<&String,GameTurn> = dict.iter()
+        .filter_map(|(gameid,entry)| entry.turn.map(|turn| (gameid, turn)))
+        .filter(|(_,turn)| params.move_type.is_none() || turn.move_type == params.move_type)
+        .collect();
+    Json(turns).into_response()
+}
+
+#[derive(Serialize,Debug)]
+struct UserRateLimitReply {
+    name: String,
+    source: RateLimitSource,
+    #[serde(flatten)]
+    limit: ConfigRateLimit,
+}
+
+async fn admin_user_rate(
+    Path(name): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let Some(user) = state.users.read().await.iter().find(|u| u.name == name).cloned() else {
+        return (StatusCode::NOT_FOUND, format!("no user {name}\n")).into_response();
+    };
+    let (source, limit) = user.rate_limit(&state.rate_limit);
+    Json(UserRateLimitReply { name, source, limit }).into_response()
+}
+
+#[derive(Serialize,Deserialize,Default,Debug)]
+struct GameIds {
+    ids: Vec<String>,
+}
+
+// unstable debug view of a GameEntry with all its internal fields
+#[derive(Serialize,Debug)]
+struct GameEntryDebug {
+    status: GameStatus,
+    turn: Option<GameTurnDebug>,
+    history: Vec<GameTurnDebug>,
+    meta: GameMeta,
+    creator: Option<String>,
+    result: Option<GameResult>,
+    // unix timestamps (milliseconds)
+    created_at: u128,
+    last_activity: u128,
+    reserved: bool,
+    max_turns: Option<u16>,
+    annotations: Vec<Annotation>,
+    turn_limit: Option<TurnLimit>,
+    forfeit_at: Option<u16>,
+    turn_timer_pending: bool,
+    version: u64,
+    last_write: u128,
+    pinned: bool,
+    host: Option<String>,
+    posted_by: Option<String>,
+    players: Vec<String>,
+    // rating changes caused by this game, keyed by player
+    elo_changes: HashMap<String,rating::RatingChange>,
+}
+
+#[derive(Serialize,Debug)]
+struct GameTurnDebug {
+    #[serde(flatten)]
+    turn: GameTurn,
+    // unix timestamp (milliseconds)
+    updated: Option<u128>,
+}
+
+fn unix_time_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+impl From<GameTurn> for GameTurnDebug {
+    fn from(turn: GameTurn) -> Self {
+        GameTurnDebug { turn, updated: turn.updated.map(unix_time_millis) }
+    }
+}
+
+impl GameEntryDebug {
+    fn new(gameid: &str, entry: &GameEntry, ratings: &HashMap<String,UserRating>) -> Self {
+        let elo_changes = ratings.values()
+            .filter_map(|rating| rating.history.iter()
+                .find(|change| change.gameid.as_deref() == Some(gameid))
+                .map(|change| (rating.username.clone(), change.clone())))
+            .collect();
+        GameEntryDebug {
+            status: entry.status(),
+            turn: entry.turn.map(GameTurnDebug::from),
+            history: entry.history.iter().copied().map(GameTurnDebug::from).collect(),
+            meta: entry.meta.clone(),
+            creator: entry.creator.clone(),
+            result: entry.result.clone(),
+            created_at: unix_time_millis(entry.created_at),
+            last_activity: unix_time_millis(entry.last_activity()),
+            reserved: entry.reserved,
+            max_turns: entry.max_turns,
+            annotations: entry.annotations.clone(),
+            turn_limit: entry.turn_limit,
+            forfeit_at: entry.forfeit_at,
+            turn_timer_pending: entry.turn_timer.as_ref().is_some_and(|timer| !timer.handle.is_finished()),
+            version: entry.version,
+            last_write: unix_time_millis(entry.last_write),
+            pinned: entry.pinned,
+            host: entry.host.clone(),
+            posted_by: entry.posted_by.clone(),
+            players: entry.players.clone(),
+            elo_changes,
+        }
+    }
+}
+
+async fn admin_game_raw(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    let Some(entry) = dict.get(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    let ratings = state.ratings.read().await;
+    (
+        // the schema of this endpoint can change between versions
+        [(header::HeaderName::from_static("x-stability"), HeaderValue::from_static("internal"))],
+        Json(GameEntryDebug::new(&gameid, entry, &ratings)),
+    ).into_response()
+}
+
+#[derive(Serialize,Debug)]
+struct GameStats {
+    // every turn written (including the overwritten ones)
+    turns_posted: usize,
+    // turns posted again with the same number
+    turns_overwritten: usize,
+    forfeited_turns: usize,
+    annotations: usize,
+}
+
+impl GameStats {
+    fn new(entry: &GameEntry) -> Self {
+        let mut numbers: Vec<u16> = entry.history.iter().map(|turn| turn.turn).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        GameStats {
+            turns_posted: entry.history.len(),
+            turns_overwritten: entry.history.len() - numbers.len(),
+            forfeited_turns: entry.history.iter().filter(|turn| turn.forfeited).count(),
+            annotations: entry.annotations.len(),
+        }
+    }
+}
+
+// the fields of /admin/game/:gameid/raw with the game id and statistics
+#[derive(Serialize,Debug)]
+struct GameAdminDetail {
+    gameid: String,
+    #[serde(flatten)]
+    entry: GameEntryDebug,
+    stats: GameStats,
+}
+
+#[derive(Template)]
+#[template(path = "game_detail.html")]
+struct GameDetailTemplate<'a> {
+    gameid: &'a str,
+    entry: &'a GameEntry,
+    stats: GameStats,
+}
+
+impl GameDetailTemplate<'_> {
+    fn players(&self) -> String {
+        let player = |name: &Option<String>| name.clone().unwrap_or_else(|| String::from("?"));
+        format!("{} vs {}",player(&self.entry.meta.player_white),player(&self.entry.meta.player_black))
+    }
+    fn result(&self) -> String {
+        let Some(result) = self.entry.result.as_ref() else { return String::new() };
+        let winner = result.winner.map_or(String::from("no winner"), |winner| format!("{winner:?}").to_lowercase());
+        match result.reason.as_deref() {
+            Some(reason) => format!("{winner} ({reason})"),
+            None => winner,
+        }
+    }
+    fn created_secs_ago(&self) -> u64 {
+        self.entry.created_at.elapsed().map_or(0, |age| age.as_secs())
+    }
+    fn idle_secs(&self) -> u64 {
+        self.entry.last_activity().elapsed().map_or(0, |age| age.as_secs())
+    }
+}
+
+#[derive(Deserialize,Default,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum GameDetailFormat {
+    #[default]
+    Json,
+    // fragment shown by the admin state page
+    Html,
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct GameDetailParams {
+    format: GameDetailFormat,
+}
+
+async fn admin_game_detail(
+    Path(gameid): Path<String>,
+    Query(params): Query<GameDetailParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    let Some(entry) = dict.get(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    match params.format {
+        GameDetailFormat::Json => {
+            let ratings = state.ratings.read().await;
+            Json(GameAdminDetail { gameid: gameid.clone(), entry: GameEntryDebug::new(&gameid, entry, &ratings), stats: GameStats::new(entry) }).into_response()
+        },
+        GameDetailFormat::Html => GameDetailTemplate { gameid: &gameid, entry, stats: GameStats::new(entry) }.into_response(),
+    }
+}
+
+async fn admin_game_rewind(
+    Path(gameid): Path<String>,
+    Query(params): Query<RequestParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> (StatusCode, Json<GameReply>) {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return GameReply::error("invalid client auth").with_status(StatusCode::UNAUTHORIZED);
+    }
+    let Some(to_turn) = params.to_turn else {
+        return GameReply::error("to_turn is required").with_status(StatusCode::BAD_REQUEST);
+    };
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND);
+    };
+    let current_turn = entry.turn.map(|turn| turn.turn);
+    if current_turn.is_none_or(|current_turn| to_turn > current_turn) {
+        return GameReply::error(format!("cannot rewind to turn {to_turn} after the current turn {current_turn:?}")).with_status(StatusCode::BAD_REQUEST);
+    }
+    let Some(position) = entry.history.iter().rposition(|turn| turn.turn == to_turn) else {
+        return GameReply::error(format!("turn {to_turn} not found")).with_status(StatusCode::NOT_FOUND);
+    };
+    let before = entry.turn;
+    entry.history.truncate(position + 1);
+    entry.turn = entry.history.last().copied();
+    // only the poster of the latest turn is known
+    entry.posted_by = None;
+    if entry.forfeit_at.is_some_and(|forfeit_at| forfeit_at > to_turn) {
+        entry.forfeit_at = None;
+    }
+    entry.schedule_turn_timer(&gameid, &state);
+    entry.touch();
+    warn!("game {} rewound to turn {:03} from {addr}: before {:?}, after {:?}",gameid,to_turn,before,entry.turn);
+    GameReply { data: entry.turn, ..GameReply::empty_success() }.with_status(StatusCode::OK)
+}
+
+#[derive(Deserialize,Debug)]
+#[serde(default)]
+struct TurnCorrectionParams {
+    // bump the version of the game (wakes up the long-polling clients)
+    notify: bool,
+    // false: the turn number is not checked, a past turn with the same number is corrected
+    validate: bool,
+}
+
+impl Default for TurnCorrectionParams {
+    fn default() -> Self {
+        TurnCorrectionParams { notify: false, validate: true }
+    }
+}
+
+// emergency edit of a stored turn (ex: a move posted by a buggy AI)
+async fn admin_game_turn_patch(
+    Path(gameid): Path<String>,
+    Query(params): Query<TurnCorrectionParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(mut payload): Json<GameTurn>
+) -> (StatusCode, Json<GameReply>) {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return GameReply::error("invalid client auth").with_status(StatusCode::UNAUTHORIZED);
+    }
+    if let Err(errors) = payload.validate(state.bounds.as_ref()) {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return GameReply::error(errors.join("; ")).with_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND);
+    };
+    if entry.history.is_empty() {
+        return GameReply::error("no turn to correct").with_status(StatusCode::NOT_FOUND);
+    }
+    // the current turn unless a past turn is explicitly corrected
+    let position = match params.validate {
+        false => entry.history.iter().rposition(|turn| turn.turn == payload.turn).unwrap_or(entry.history.len() - 1),
+        true => entry.history.len() - 1,
+    };
+    if params.validate && position > 0 && payload.turn <= entry.history[position - 1].turn {
+        let previous = entry.history[position - 1].turn;
+        return GameReply::error(format!("turn {} is not after the previous turn {previous} (use validate=false to correct a past turn)",payload.turn)).with_status(StatusCode::CONFLICT);
+    }
+    let before = entry.history[position];
+    payload.fingerprint = Some(Fingerprint::of(&payload));
+    payload.updated = before.updated;
+    payload.submitted_at = before.submitted_at;
+    entry.history[position] = payload;
+    if position == entry.history.len() - 1 {
+        entry.turn = Some(payload);
+    }
+    if params.notify {
+        entry.touch();
+    } else {
+        entry.reply_cache.clear();
+    }
+    warn!(target: audit::AUDIT_TARGET, "game {} turn corrected by {} from {addr}: before {:?}, after {:?}",
+        gameid,username.as_deref().unwrap_or("(anonymous)"),before,payload);
+    GameReply::success(payload).with_status(StatusCode::OK)
+}
+
+#[derive(Serialize,Debug)]
+struct PinReply {
+    gameid: String,
+    pinned: bool,
+}
+
+async fn set_pinned(state: &SharedState, gameid: String, pinned: bool, addr: SocketAddr) -> Response {
+    let mut dict = state.game_data.write().await;
+    let Some(entry) = dict.get_mut(&gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    entry.pinned = pinned;
+    warn!("game {} {} from {addr}",gameid,if pinned { "pinned" } else { "unpinned" });
+    Json(PinReply { gameid, pinned }).into_response()
+}
+
+async fn admin_game_pin(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    set_pinned(&state, gameid, true, addr).await
+}
+
+async fn admin_game_unpin(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    set_pinned(&state, gameid, false, addr).await
+}
+
+#[derive(Deserialize,Debug)]
+struct RenameRequest {
+    new_gameid: String,
+}
+
+#[derive(Serialize,Debug)]
+struct RenameReply {
+    gameid: String,
+    old_gameid: String,
+}
+
+// moves a game to another ID (clients still using the old one get 404)
+async fn admin_game_rename(
+    Path(old_gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<RenameRequest>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let gameid = state.game_id_case.apply(&request.new_gameid);
+    if let Err(e) = validate_game_id(&gameid) {
+        return GameReply::error(e).with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response();
+    }
+    if let Err(e) = check_game_id_not_reserved(&state, &gameid) {
+        return GameReply::error(e).with_status(StatusCode::CONFLICT).into_response();
+    }
+    let mut dict = state.game_data.write().await;
+    if dict.contains_key(&gameid) {
+        return GameReply::error(format!("game {gameid} already exists")).with_status(StatusCode::CONFLICT).into_response();
+    }
+    let Some(mut entry) = dict.remove(&old_gameid) else {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    // the pending forfeit looks the game up by ID (the time left to play is unchanged)
+    entry.reschedule_turn_timer(&gameid, &state);
+    // wakes up the long-polling clients (which then get 404 for the old ID)
+    entry.touch();
+    dict.insert(gameid.clone(), entry);
+    for rating in state.ratings.write().await.values_mut() {
+        for change in rating.history.iter_mut().filter(|change| change.gameid.as_deref() == Some(old_gameid.as_str())) {
+            change.gameid = Some(gameid.clone());
+        }
+    }
+    warn!(target: audit::AUDIT_TARGET, "game {} renamed to {} from {addr}",old_gameid,gameid);
+    Json(RenameReply { gameid, old_gameid }).into_response()
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct AdminGamesParams {
+    pinned: Option<bool>,
+}
+
+async fn admin_games(
+    Query(params): Query<AdminGamesParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Host(hostname): Host,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let scope = sni_scope(&state, &hostname);
+    let dict = state.game_data.read().await;
+    let mut ids: Vec<String> = dict.iter()
+        .filter(|(_,entry)| scope.is_none() || entry.host == scope)
+        .filter(|(_,entry)| params.pinned.is_none_or(|pinned| entry.pinned == pinned))
+        .map(|(gameid,_)| gameid.clone())
+        .collect();
+    ids.sort();
+    Json(GameIds { ids }).into_response()
+}
+
+#[derive(Serialize,Debug)]
+struct AwaitingGame {
+    gameid: String,
+    turn: u16,
+    // since the last turn was posted
+    elapsed_secs: u64,
+}
+
+// games of the user (creator or named player) whose current turn was posted by someone else
+async fn admin_games_awaiting(
+    Path(username): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    // games that switched turns less than this many seconds ago are left out
+    let stale_threshold = match headers.get("x-stale-threshold").map(|value| value.to_str().ok().and_then(|value| value.trim().parse::<u64>().ok())) {
+        None => 0,
+        Some(Some(secs)) => secs,
+        Some(None) => return GameReply::error("X-Stale-Threshold must be a number of seconds").with_status(StatusCode::BAD_REQUEST).into_response(),
+    };
+    let dict = state.game_data.read().await;
+    let mut games: Vec<AwaitingGame> = dict.iter()
+        .filter(|(_,entry)| entry.result.is_none() && entry.forfeit_at.is_none() && entry.is_participant(&username))
+        .filter(|(_,entry)| entry.posted_by.as_deref().is_some_and(|posted_by| posted_by != username))
+        .filter_map(|(gameid,entry)| {
+            let turn = entry.turn?;
+            let elapsed_secs = turn.updated.and_then(|updated| updated.elapsed().ok()).map_or(0, |elapsed| elapsed.as_secs());
+            Some(AwaitingGame { gameid: gameid.clone(), turn: turn.turn, elapsed_secs })
+        })
+        .filter(|game| game.elapsed_secs >= stale_threshold)
+        .collect();
+    games.sort_by(|a,b| a.gameid.cmp(&b.gameid));
+    Json(games).into_response()
+}
+
+// quoted DOT identifier (a \n left in the text is a line break in a label)
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+// one node per game (games have no tags, so there are no bracket edges to draw)
+fn games_dot(dict: &GameData) -> String {
+    let mut games: Vec<(&String, &GameEntry)> = dict.iter().collect();
+    games.sort_by(|a,b| a.0.cmp(b.0));
+    let mut dot = String::from("digraph broker {\n    node [shape=box];\n");
+    for (gameid, entry) in games {
+        let player = |name: &Option<String>| name.clone().unwrap_or_else(|| "?".to_string());
+        let turn = entry.turn.map_or("no turn yet".to_string(), |turn| format!("Turn {}", turn.turn));
+        let label = format!("{gameid}\n{} vs {}\n{turn}", player(&entry.meta.player_white), player(&entry.meta.player_black));
+        dot.push_str(&format!("    {} [label={}];\n", dot_quote(gameid), dot_quote(&label)));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+async fn admin_graph_dot(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dot = games_dot(&*state.game_data.read().await);
+    ([(header::CONTENT_TYPE, HeaderValue::from_static("text/vnd.graphviz; charset=utf-8"))], dot).into_response()
+}
+
+// rendered by the dot command of Graphviz when it is installed on the host
+async fn admin_graph_svg(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dot = games_dot(&*state.game_data.read().await);
+    let child = tokio::process::Command::new("dot").arg("-Tsvg")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("cannot run dot: {e}");
+            return GameReply::error("Graphviz (dot) is not installed on the server: get /admin/graph.dot and render it offline")
+                .with_status(StatusCode::NOT_IMPLEMENTED).into_response();
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        use tokio::io::AsyncWriteExt;
+        if let Err(e) = stdin.write_all(dot.as_bytes()).await {
+            warn!("cannot write to dot: {e}");
+        }
+    }
+    match child.wait_with_output().await {
+        Ok(output) if output.status.success() => {
+            ([(header::CONTENT_TYPE, HeaderValue::from_static("image/svg+xml"))], output.stdout).into_response()
+        },
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            error!("dot failed ({}): {}", output.status, stderr.trim());
+            GameReply::error(format!("dot failed: {}", stderr.trim())).with_status(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+        },
+        Err(e) => {
+            error!("dot failed: {e}");
+            GameReply::error(format!("dot failed: {e}")).with_status(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+        },
+    }
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct InviteRequest {
+    // defaults to auth.token_ttl_secs
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Serialize,Debug)]
+struct InviteReply {
+    gameid: String,
+    url: String,
+    // unix timestamp (seconds)
+    expires: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn admin_game_invite(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Host(hostname): Host,
+    headers: HeaderMap,
+    request: Option<Json<InviteRequest>>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let Some(username) = username else {
+        return (StatusCode::BAD_REQUEST, "invites require an authenticated admin\n").into_response();
+    };
+    if !state.game_data.read().await.contains_key(&gameid) {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    }
+    let Json(request) = request.unwrap_or_default();
+    let claims = token::InviteClaims {
+        sub: username,
+        gameid: gameid.clone(),
+        role: String::from("user"),
+        exp: unix_time_secs().saturating_add(request.ttl_secs.unwrap_or(state.token_ttl_secs)),
+    };
+    let invite = token::issue(&state.token_secret, &claims);
+    warn!("invite to game {} created from {addr}",gameid);
+    Json(InviteReply {
+        gameid,
+        url: canonical_url(&state, &hostname, &format!("/join/{invite}"), &headers, addr),
+        expires: claims.exp,
+    }).into_response()
+}
+
+#[cfg(feature = "qrcode")]
+const DEFAULT_QR_SIZE: usize = 200;
+#[cfg(feature = "qrcode")]
+const MAX_QR_SIZE: usize = 1000;
+
+#[cfg(feature = "qrcode")]
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct QrParams {
+    // width and height in pixels
+    size: Option<usize>,
+}
+
+// PNG of a QR code of the game URL (with the Host of the request) for agents configured by scanning it
+#[cfg(feature = "qrcode")]
+async fn game_qr(
+    Path(gameid): Path<String>,
+    Query(params): Query<QrParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Host(hostname): Host,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::User {
+        debug!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    if !state.game_data.read().await.contains_key(&gameid) {
+        return GameReply::error("game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    }
+    let size = params.size.unwrap_or(DEFAULT_QR_SIZE);
+    if size > MAX_QR_SIZE {
+        return GameReply::error(format!("size is limited to {MAX_QR_SIZE} pixels")).with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response();
+    }
+    let url = canonical_url(&state, &hostname, &format!("/game/{gameid}"), &headers, addr);
+    let Ok(code) = qrcode::QrCode::new(url.as_bytes()) else {
+        return GameReply::error("the game URL is too long for a QR code").with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response();
+    };
+    match qrpng::to_png(&code, size) {
+        Some(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        None => GameReply::error(format!("size must be at least {} pixels for this URL",qrpng::min_pixels(&code)))
+            .with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response(),
+    }
+}
+
+// consumes an invite and returns a game token for the invited game
+async fn join(
+    Path(invite): Path<String>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    let error = |status: StatusCode, msg: String| {
+        GameReply::error(msg).with_status(status).into_response()
+    };
+    let claims = match token::verify::<token::InviteClaims>(&state.token_secret, &invite) {
+        Ok(claims) => claims,
+        Err(e) => {
+            debug!("rejected invite from {addr}: {e}");
+            return error(StatusCode::UNAUTHORIZED, format!("invalid invite: {e}"));
+        }
+    };
+    {
+        let mut consumed = state.consumed_invites.write().await;
+        let now = unix_time_secs();
+        consumed.retain(|_,exp| *exp > now);
+        if consumed.insert(invite, claims.exp).is_some() {
+            return error(StatusCode::GONE, String::from("invite was already used"));
+        }
+    }
+    if !state.game_data.read().await.contains_key(&claims.gameid) {
+        return error(StatusCode::NOT_FOUND, String::from("game not found"));
+    }
+    let game_claims = token::GameClaims::new(&claims.sub, &claims.gameid, claims.exp.saturating_sub(unix_time_secs()));
+    info!(gameid = %claims.gameid, "game invite used from {addr}");
+    Json(GameTokenReply {
+        id: claims.gameid,
+        token: token::issue(&state.token_secret, &game_claims),
+        expires: game_claims.exp,
+    }).into_response()
+}
+
+async fn admin_reserve(
+    Extension(role): Extension<ConfigUserRole>,
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Host(hostname): Host,
+    Json(mut request): Json<GameIds>
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    request.ids = request.ids.iter().map(|gameid| state.game_id_case.apply(gameid)).collect();
+    let error = |status: StatusCode, msg: String| {
+        GameReply::error(msg).with_status(status).into_response()
+    };
+    for gameid in request.ids.iter() {
+        if let Err(e) = validate_game_id(gameid) {
+            return error(StatusCode::UNPROCESSABLE_ENTITY, e);
+        }
+        if let Err(e) = check_game_id_not_reserved(&state, gameid) {
+            return error(StatusCode::CONFLICT, e);
+        }
+    }
+    let mut dict = state.game_data.write().await;
+    for (i,gameid) in request.ids.iter().enumerate() {
+        if dict.contains_key(gameid) || request.ids[..i].contains(gameid) {
+            return error(StatusCode::CONFLICT, format!("game ID '{gameid}' is already taken"));
+        }
+    }
+    let host = sni_scope(&state, &hostname);
+    for gameid in request.ids.iter() {
+        dict.insert(gameid.clone(), GameEntry { reserved: true, host: host.clone(), ..GameEntry::new(username.clone()) });
+        state.activity.record(ActivityEvent::GameCreated);
+    }
+    warn!("reserved {} games from {addr}: {:?}",request.ids.len(),request.ids);
+    Json(request).into_response()
+}
+
+#[derive(Deserialize,Debug)]
+struct ArchiveRequest {
+    ids: Vec<String>,
+    // stored as the reason of the result of every archived game
+    reason: String,
+}
+
+#[derive(Serialize,Debug)]
+struct ArchiveReply {
+    archived_count: usize,
+    // ids that do not exist (nothing is archived then)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing: Vec<String>,
+}
+
+// all or nothing: the games are finished with the reason and moved out of the game data
+async fn admin_games_archive(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<ArchiveRequest>
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let mut ids: Vec<String> = request.ids.iter().map(|gameid| state.game_id_case.apply(gameid)).collect();
+    ids.sort();
+    ids.dedup();
+    let mut dict = state.game_data.write().await;
+    let missing: Vec<String> = ids.iter().filter(|gameid| !dict.contains_key(*gameid)).cloned().collect();
+    if !missing.is_empty() {
+        return (StatusCode::NOT_FOUND, Json(ArchiveReply { archived_count: 0, missing })).into_response();
+    }
+    let mut archives = state.archived_games.write().await;
+    for gameid in ids.iter() {
+        let Some(mut entry) = dict.remove(gameid) else { continue };
+        entry.cancel_turn_timer();
+        let winner = entry.result.as_ref().and_then(|result| result.winner);
+        entry.result = Some(GameResult { winner, reason: Some(request.reason.clone()) });
+        entry.touch();
+        archives.insert(gameid.clone(), entry);
+    }
+    warn!("archived {} games from {addr} ({}): {:?}",ids.len(),request.reason,ids);
+    Json(ArchiveReply { archived_count: ids.len(), missing: Vec::new() }).into_response()
+}
+
+async fn admin_archive(
+    Path(gameid): Path<String>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let archives = state.archived_games.read().await;
+    let Some(entry) = archives.get(&state.game_id_case.apply(&gameid)) else {
+        return GameReply::error("archived game not found").with_status(StatusCode::NOT_FOUND).into_response();
+    };
+    let ratings = state.ratings.read().await;
+    Json(GameEntryDebug::new(&gameid, entry, &ratings)).into_response()
+}
+
+async fn admin_reserved(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let dict = state.game_data.read().await;
+    let mut ids: Vec<String> = dict.iter()
+        .filter(|(_,entry)| entry.reserved && entry.status() == GameStatus::Created)
+        .map(|(gameid,_)| gameid.clone())
+        .collect();
+    ids.sort();
+    Json(GameIds { ids }).into_response()
+}
+
+async fn admin_perf(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let summaries: std::collections::BTreeMap<String,perf::LatencySummary> = match state.latencies.lock() {
+        Ok(latencies) => latencies.iter().map(|(route,histogram)| (route.clone(), histogram.summary())).collect(),
+        Err(_) => Default::default(),
+    };
+    Json(summaries).into_response()
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct LogParams {
+    limit: Option<usize>,
+    // minimum level (error, warn, info, debug or trace), defaults to trace (all lines)
+    level: Option<String>,
+}
+
+impl LogParams {
+    fn min_level(&self) -> Result<tracing::Level,String> {
+        match self.level.as_deref() {
+            None => Ok(tracing::Level::TRACE),
+            Some(level) => tracing::Level::from_str(level).map_err(|_| format!("invalid log level {level:?}")),
+        }
+    }
+}
+
+const DEFAULT_LOG_LIMIT: usize = 100;
+
+async fn admin_logs(
+    Query(params): Query<LogParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let min_level = match params.min_level() {
+        Ok(min_level) => min_level,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, format!("{e}\n")).into_response(),
+    };
+    Json(logbuf::LOG_BUFFER.lines(min_level, Some(params.limit.unwrap_or(DEFAULT_LOG_LIMIT)))).into_response()
+}
+
+// all the buffered lines unless limit is given
+async fn admin_logs_download(
+    Query(params): Query<LogParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let min_level = match params.min_level() {
+        Ok(min_level) => min_level,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, format!("{e}\n")).into_response(),
+    };
+    let mut text = logbuf::LOG_BUFFER.lines(min_level, params.limit).join("\n");
+    text.push('\n');
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8")),
+            (header::CONTENT_DISPOSITION, HeaderValue::from_static("attachment; filename=\"ai_wargame_broker.log\"")),
+        ],
+        text,
+    ).into_response()
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct ActivityParams {
+    // comma separated (ex: 1m,5m,15m)
+    buckets: Option<String>,
+}
+
+const DEFAULT_ACTIVITY_BUCKETS: &str = "1m,5m,15m";
+
+async fn admin_activity(
+    Query(params): Query<ActivityParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let retention = state.activity.retention();
+    // buckets are listed in the requested order
+    let mut buckets = serde_json::Map::new();
+    for bucket in params.buckets.as_deref().unwrap_or(DEFAULT_ACTIVITY_BUCKETS).split(',').map(str::trim) {
+        let Some(window) = activity::parse_bucket(bucket) else {
+            return (StatusCode::UNPROCESSABLE_ENTITY, format!("invalid bucket {bucket:?} (expected Ns, Nm or Nh)\n")).into_response();
+        };
+        if window > retention {
+            return (StatusCode::UNPROCESSABLE_ENTITY, format!("bucket {bucket:?} is longer than the activity retention ({}s)\n",retention.as_secs())).into_response();
+        }
+        let counts = serde_json::to_value(state.activity.counts(window)).unwrap_or_default();
+        buckets.insert(bucket.to_string(), counts);
+    }
+    Json(buckets).into_response()
+}
+
+async fn admin_perf_reset(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    if let Ok(mut latencies) = state.latencies.lock() {
+        latencies.clear();
+    }
+    warn!("latency statistics reset from {addr}");
+    (StatusCode::OK, "latency statistics reset\n").into_response()
+}
+
+// SNI name of the [[tls.certs]] entry matching the Host of a request (None for other hosts)
+fn sni_scope(state: &SharedData, hostname: &str) -> Option<String> {
+    let host = hostname.rsplit_once(':')
+        .filter(|(_,port)| port.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(hostname, |(host,_)| host)
+        .to_ascii_lowercase();
+    state.sni_hosts.contains(&host).then_some(host)
+}
+
+#[derive(Deserialize,Default,Debug)]
+struct TlsReloadParams {
+    // reloads all certificates if None ("default" is the fallback certificate)
+    sni: Option<String>,
+}
+
+#[derive(Serialize,Debug)]
+struct TlsReloadReply {
+    reloaded: Vec<String>,
+}
+
+async fn admin_tls_reload(
+    Query(params): Query<TlsReloadParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let Some(resolver) = state.tls_certs.get() else {
+        return (StatusCode::CONFLICT, "TLS is not enabled\n").into_response();
+    };
+    if let Some(sni) = params.sni.as_deref() {
+        if sni != sni::FALLBACK_NAME && !resolver.has_host(sni) {
+            return (StatusCode::NOT_FOUND, format!("no certificate for {sni:?}\n")).into_response();
+        }
+    }
+    // the certificates are read from disk and checked (the current ones are kept on errors)
+    match resolver.reload(params.sni.as_deref()) {
+        Ok(reloaded) => {
+            warn!("reloaded TLS certificates {:?} from {addr}",reloaded);
+            Json(TlsReloadReply { reloaded }).into_response()
+        },
+        Err(e) => {
+            error!("cannot reload TLS certificates: {e}");
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("cannot reload TLS certificates: {e}\n")).into_response()
+        }
+    }
+}
+
+async fn admin_snapshot(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let Some(path) = state.state_file.as_deref() else {
+        return (StatusCode::CONFLICT, "no state file configured\n").into_response();
+    };
+    match snapshot::write(&state, path).await {
+        Ok(count) => {
+            warn!("snapshot of {count} games written to {:?} from {addr}",path);
+            (StatusCode::OK, format!("snapshot of {count} games written\n")).into_response()
+        },
+        Err(e) => {
+            error!("snapshot failed: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("snapshot failed: {e}\n")).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct ExportParams {
+    // add X-Broker-Signature (base64 signature of the SHA-256 of the body)
+    signed: bool,
+}
+
+async fn admin_export(
+    Query(params): Query<ExportParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let signer = match (params.signed, state.export_signer.as_ref()) {
+        (false, _) => None,
+        (true, Some(signer)) => Some(signer),
+        (true, None) => return (StatusCode::CONFLICT, "signing is not enabled (general.enable_signing)\n").into_response(),
+    };
+    let estimated_bytes = state.game_data.read().await.values().map(GameEntry::estimated_export_bytes).fold(0, usize::saturating_add);
+    if let Some(response) = memguard::over_limit("GET /admin/export", estimated_bytes, state.max_response_memory_bytes) {
+        return response;
+    }
+    let (json, count) = match snapshot::to_json(&state).await {
+        Ok(export) => export,
+        Err(e) => {
+            error!("export failed: {e}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("export failed: {e}\n")).into_response();
+        }
+    };
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/json")),
+            (header::CONTENT_DISPOSITION, HeaderValue::from_static("attachment; filename=\"ai_wargame_broker_export.json\"")),
+        ],
+        json.clone(),
+    ).into_response();
+    if let Some(signer) = signer {
+        match signer.sign(&json) {
+            Ok(sig) => {
+                let sig = BASE64.encode(sig);
+                response.headers_mut().insert("x-broker-signature", HeaderValue::from_str(&sig).expect("base64 is a valid header value"));
+            },
+            Err(e) => {
+                error!("export signature failed: {e}");
+                return (StatusCode::INTERNAL_SERVER_ERROR, format!("export signature failed: {e}\n")).into_response();
+            }
+        }
+    }
+    info!("export of {count} games (signed: {}) from {addr}",signer.is_some());
+    response
+}
+
+// no auth required (the key is needed to check exports received from someone else)
+async fn admin_export_pubkey(
+    State(state): State<SharedState>,
+) -> impl IntoResponse {
+    match state.export_signer.as_ref() {
+        Some(signer) => ([(header::CONTENT_TYPE, "application/x-pem-file")], signer.public_key_pem().to_string()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn admin_cleaner_status(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let status = state.cleaner_status.read().await.clone();
+    Json(CleanerStatusReply { status, games: state.game_count_by_status().await }).into_response()
+}
+
+#[derive(Serialize,Debug)]
+struct CacheStatsReply {
+    enabled: bool,
+    min_reads: u64,
+    hits: u64,
+    misses: u64,
+    // hits / (hits + misses), None before the first cacheable read
+    hit_ratio: Option<f64>,
+    // games whose current reply is cached
+    cached_games: usize,
+}
+
+async fn admin_cache_stats(
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let hits = state.reply_cache_hits.load(std::sync::atomic::Ordering::Relaxed);
+    let misses = state.reply_cache_misses.load(std::sync::atomic::Ordering::Relaxed);
+    let cached_games = state.game_data.read().await.values().filter(|entry| entry.reply_cache.is_filled()).count();
+    Json(CacheStatsReply {
+        enabled: state.cache_min_reads > 0,
+        min_reads: state.cache_min_reads,
+        hits,
+        misses,
+        hit_ratio: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        cached_games,
+    }).into_response()
+}
+
+#[derive(Serialize,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum IntegrityIssue {
+    FutureTimestamp,
+    // turn numbers start at 1
+    TurnZero,
+    // the current turn is not the last one of the history
+    CurrentTurnMismatch,
+    TurnAfterForfeit,
+    TurnOverMax,
+}
+
+impl IntegrityIssue {
+    fn is_fixable(self) -> bool {
+        matches!(self, IntegrityIssue::FutureTimestamp | IntegrityIssue::CurrentTurnMismatch)
+    }
+}
+
+#[derive(Serialize,Debug)]
+struct IntegrityAnomaly {
+    gameid: String,
+    issue: IntegrityIssue,
+    detail: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    fixed: bool,
+}
+
+#[derive(Serialize,Debug)]
+struct IntegrityReply {
+    games: usize,
+    anomalies: Vec<IntegrityAnomaly>,
+}
+
+#[derive(Deserialize,Default,Debug)]
+#[serde(default)]
+struct IntegrityParams {
+    // correct what can be (future timestamps are set to now, the current turn to the last one of the history)
+    fix: bool,
+}
+
+impl GameEntry {
+    fn integrity_anomalies(&self, now: SystemTime, default_max_turns: u16) -> Vec<(IntegrityIssue,String)> {
+        let mut anomalies = Vec::new();
+        let in_future = |time: SystemTime| time.duration_since(now).ok().filter(|ahead| !ahead.is_zero());
+        if let Some(ahead) = in_future(self.created_at) {
+            anomalies.push((IntegrityIssue::FutureTimestamp, format!("created_at is {}s in the future",ahead.as_secs())));
+        }
+        for turn in &self.history {
+            if let Some(ahead) = turn.updated.and_then(in_future) {
+                anomalies.push((IntegrityIssue::FutureTimestamp, format!("updated of turn {} is {}s in the future",turn.turn,ahead.as_secs())));
+            }
+            if turn.turn == 0 && turn.updated.is_some() {
+                anomalies.push((IntegrityIssue::TurnZero, String::from("turn 0 was posted")));
+            }
+        }
+        let last = self.history.last();
+        let same_turn = match (self.turn, last) {
+            (Some(turn), Some(last)) => turn.turn == last.turn && turn.updated == last.updated,
+            (turn, last) => turn.is_none() && last.is_none(),
+        };
+        if !same_turn {
+            let describe = |turn: Option<u16>| turn.map_or(String::from("none"), |turn| turn.to_string());
+            anomalies.push((IntegrityIssue::CurrentTurnMismatch, format!("current turn is {} but the last turn of the history is {}",
+                describe(self.turn.map(|turn| turn.turn)),describe(last.map(|turn| turn.turn)))));
+        }
+        if let Some(forfeit_at) = self.forfeit_at {
+            let forfeit = self.history.iter().position(|turn| turn.forfeited && turn.turn == forfeit_at);
+            for turn in forfeit.map_or(&[][..], |forfeit| &self.history[forfeit + 1..]) {
+                anomalies.push((IntegrityIssue::TurnAfterForfeit, format!("turn {} was posted after the forfeit of turn {forfeit_at}",turn.turn)));
+            }
+        }
+        if let Some(max_turns) = self.max_turns(default_max_turns) {
+            for turn in self.history.iter().filter(|turn| turn.turn >= max_turns) {
+                anomalies.push((IntegrityIssue::TurnOverMax, format!("turn {} is at or above the limit of {max_turns} turns",turn.turn)));
+            }
+        }
+        anomalies
+    }
+    // corrects the fixable issues (see IntegrityIssue::is_fixable)
+    fn fix_integrity(&mut self, now: SystemTime) {
+        self.created_at = self.created_at.min(now);
+        for turn in self.history.iter_mut() {
+            turn.updated = turn.updated.map(|updated| updated.min(now));
+        }
+        self.turn = self.history.last().copied();
+    }
+}
+
+// read only unless fix=true
+async fn admin_integrity(
+    Query(params): Query<IntegrityParams>,
+    Extension(role): Extension<ConfigUserRole>,
+    State(state): State<SharedState>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    debug!("Role: {:?}",role);
+    if role < ConfigUserRole::Admin {
+        error!("failed auth from {addr}");
+        return authenticate(&state).into_response();
+    }
+    let now = SystemTime::now();
+    let anomalies_of = |gameid: &String, entry: &GameEntry| entry.integrity_anomalies(now, state.max_turns_per_game).into_iter()
+        .map(|(issue,detail)| IntegrityAnomaly { gameid: gameid.clone(), issue, detail, fixed: false })
+        .collect::<Vec<_>>();
+    let mut anomalies = Vec::new();
+    let games = if params.fix {
+        let mut dict = state.game_data.write().await;
+        for (gameid,entry) in dict.iter_mut() {
+            let mut found = anomalies_of(gameid, entry);
+            if found.iter().any(|anomaly| anomaly.issue.is_fixable()) {
+                entry.fix_integrity(now);
+                entry.touch();
+                for anomaly in found.iter_mut() {
+                    anomaly.fixed = anomaly.issue.is_fixable();
+                }
+                warn!("game {gameid} repaired by integrity check from {addr}");
+            }
+            anomalies.append(&mut found);
+        }
+        dict.len()
+    } else {
+        let dict = state.snapshot().await;
+        for (gameid,entry) in dict.iter() {
+            anomalies.append(&mut anomalies_of(gameid, entry));
+        }
+        dict.len()
+    };
+    anomalies.sort_by(|a,b| a.gameid.cmp(&b.gameid));
+    Json(IntegrityReply { games, anomalies }).into_response()
+}
+
+async fn robots_txt(
+    State(state): State<SharedState>, 
+) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], state.robots_txt.to_string())
+}
+
+async fn security_txt(
+    State(state): State<SharedState>, 
+) -> impl IntoResponse {
+    match state.security_txt.as_ref() {
+        Some(txt) => ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], txt.to_string()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn unix_time_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn get_config_file_name(in_cwd: bool) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|pb| if in_cwd {
+            pb.file_name().map(PathBuf::from)
+        } else {
+            Some(pb)
+        })
+        .unwrap_or(PathBuf::from(env!("CARGO_PKG_NAME")))
+        .with_extension("toml")
+}
+
+// one cleanup pass, returns the number of (expired, orphaned, finished) games removed
+async fn clean_games(state: &SharedState, expires_secs: u64) -> (usize, usize, usize) {
+    let created_expires_secs = state.created_expires_secs.unwrap_or(expires_secs / 10);
+    let finished_expires_secs = state.finished_expires_secs.unwrap_or(expires_secs.saturating_mul(10));
+    let (mut expired, mut orphaned, mut finished) = (0, 0, 0);
+    let mut dict = state.game_data.write().await;
+    dict.retain(|gameid, entry| {
+        let Ok(age) = entry.last_activity().elapsed() else { return true };
+        let age = age.as_secs();
+        match entry.status() {
+            GameStatus::Active if age > expires_secs => {
+                info!("game {gameid} has expired");
+                expired += 1;
+                false
+            },
+            GameStatus::Created if entry.reserved && age > state.reserved_expires_secs => {
+                info!("reserved game {gameid} was never played and has expired");
+                orphaned += 1;
+                false
+            },
+            GameStatus::Created if !entry.reserved && age > entry.created_ttl_secs.unwrap_or(created_expires_secs) => {
+                info!("game {gameid} was never played and has expired");
+                orphaned += 1;
+                false
+            },
+            GameStatus::Finished if age > finished_expires_secs => {
+                info!("finished game {gameid} has expired");
+                finished += 1;
+                false
+            },
+            _ => true,
+        }
+    });
+    (expired, orphaned, finished)
+}
+
+// removes the games over the hard limits (after the normal expiry), returns their number
+async fn evict_games(state: &SharedState) -> usize {
+    let mut dict = state.game_data.write().await;
+    let count = dict.len();
+    if let Some(max_age_secs) = state.hard_max_age_secs {
+        dict.retain(|gameid, entry| {
+            let too_old = entry.created_at.elapsed().is_ok_and(|age| age.as_secs() > max_age_secs);
+            if too_old {
+                warn!("game {gameid} removed: evicted_max_age");
+            }
+            !too_old
+        });
+    }
+    if let Some(max_games) = state.hard_max_games.filter(|max_games| dict.len() > *max_games) {
+        // pinned games go last
+        let mut games: Vec<(bool, SystemTime, String)> = dict.iter()
+            .map(|(gameid, entry)| (entry.pinned, entry.last_activity(), gameid.clone()))
+            .collect();
+        games.sort();
+        let excess = dict.len() - max_games;
+        for (_, _, gameid) in games.into_iter().take(excess) {
+            warn!("game {gameid} removed: evicted_capacity");
+            dict.remove(&gameid);
+        }
+    }
+    count - dict.len()
+}
+
+async fn clean_archives(state: &SharedState) -> usize {
+    let Some(retention_secs) = state.archived_retention_secs else { return 0 };
+    let mut archives = state.archived_games.write().await;
+    let count = archives.len();
+    archives.retain(|_, entry| entry.last_write.elapsed().map_or(true, |age| age.as_secs() <= retention_secs));
+    count - archives.len()
+}
+
+async fn cleaner(expires_secs: u64, cleanup_interval_secs: u64, state: SharedState) {
+    {
+        let mut status = state.cleaner_status.write().await;
+        status.interval_secs = cleanup_interval_secs;
+        status.next_run = Some(unix_time_secs() + cleanup_interval_secs);
+    }
+    loop {
+        sleep(Duration::from_secs(cleanup_interval_secs)).await;
+        debug!("cleaner starting");
+        let (expired, orphaned, finished) = clean_games(&state, expires_secs).await;
+        let evicted = evict_games(&state).await;
+        let archives_removed = clean_archives(&state).await;
+        state.activity.trim();
+        info!("cleaned {expired} expired, {orphaned} orphaned, {finished} finished, {evicted} evicted games, {archives_removed} archives, {} left",state.total_game_count().await);
+        let mut status = state.cleaner_status.write().await;
+        status.last_run = Some(unix_time_secs());
+        status.next_run = Some(unix_time_secs() + cleanup_interval_secs);
+        status.removed_last_cycle = expired + orphaned + finished + evicted;
+        status.expired = expired;
+        status.orphaned = orphaned;
+        status.finished = finished;
+        status.evicted = evicted;
+        status.archives_removed = archives_removed;
+        if let Some(acme) = state.acme.get() {
+            acme::renew_if_needed(acme.clone(), state.tls_certs.get().cloned());
+        }
+        debug!("cleaner ending");
+    }
+}
+
+fn authenticate(state: &SharedData) -> impl IntoResponse {
+    // the realm is validated at startup (printable ASCII without quotes or backslashes)
+    let challenge = HeaderValue::from_str(&format!("Basic realm=\"{}\"",state.auth_realm))
+        .unwrap_or_else(|_| HeaderValue::from_static("Basic"));
+    (
+        [
+            (header::WWW_AUTHENTICATE, challenge),
+        ],
+        StatusCode::UNAUTHORIZED
+    )
+}
+
+// game tokens are only valid to read or post turns of the game they were issued for
+fn game_token_scope_matches<B>(request: &Request<B>, gameid: &str) -> bool {
+    let method = request.method();
+    (method == axum::http::Method::GET || method == axum::http::Method::POST)
+        && request.uri().path().trim_end_matches('/').strip_prefix("/game/") == Some(gameid)
+}
+
+async fn auth_basic<B>(
+    client_cert: Option<Extension<mtls::ClientCertName>>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Query(params): Query<RequestParams>,
+    State(state): State<SharedState>, 
+    mut request: Request<B>,
+    next: Next<B>,
+) -> impl IntoResponse {
+    // a verified client certificate is equivalent to valid credentials
+    if let Some(Extension(mtls::ClientCertName(Some(cn)))) = client_cert {
+        let user = state.users.read().await.iter().find(|u| u.certificate_cn.as_deref() == Some(cn.as_str())).cloned();
+        match user {
+            Some(user) => {
+                debug!("CLIENT CERT: {} user: {}",cn,user.name);
+                request.extensions_mut().insert(user.role);
+                request.extensions_mut().insert(UserName(Some(user.name)));
+                return next.run(request).await;
+            },
+            None => debug!("no user for client certificate {cn}"),
+        }
+    }
+    if let Some(bearer) = bearer.as_deref() {
+        match token::verify::<token::GameClaims>(&state.token_secret, bearer.token()) {
+            Ok(claims) if game_token_scope_matches(&request, &claims.game) => {
+                // the token is revoked if its user was removed
+                if state.users.read().await.iter().any(|u| u.name == claims.sub) {
+                    debug!("TOKEN user: {} game: {}",claims.sub,claims.game);
+                    request.extensions_mut().insert(ConfigUserRole::User);
+                    request.extensions_mut().insert(UserName(Some(claims.sub)));
+                    return next.run(request).await;
+                }
+            },
+            Ok(claims) => debug!("token for game {} used on {} {}",claims.game,request.method(),request.uri().path()),
+            Err(e) => debug!("rejected token: {e}"),
+        }
+    }
+    let mut opt_username = None;
+    let mut opt_password = Some("");
+    if let Some(auth) = auth.as_deref() {
+        opt_username = Some(auth.username());
+        opt_password = Some(auth.password());
+    }
+    if let Some(username) = params.username.as_deref() {
+        opt_username = Some(username);
+    } 
+    if let Some(password) = params.password.as_deref() {
+        opt_password = Some(password);
+    } 
+    if let Some(username) = opt_username {
+        if let Some(password) = opt_password {
+            debug!("REQUEST username: {} password: {}",username,password);
+            let user = state.users.read().await.iter().find(|u| u.name == username).cloned();
+            if let Some(user) = user {
+                debug!("CONFIG USER: {:?}",user);
+                if user.certificate_cn.is_some() && user.password.is_empty() {
+                    debug!("user {} only authenticates with a client certificate",user.name);
+                } else if user.accepts_password(password) {
+                    request.extensions_mut().insert(user.role);
+                    request.extensions_mut().insert(UserName(Some(user.name.clone())));
+                    return next.run(request).await;
+                }
+            }
+        }        
+    }
+    request.extensions_mut().insert(state.unauthenticated_role);
+    request.extensions_mut().insert(UserName(None));
+    next.run(request).await
+}
+
+// applied after authentication: per user limit (override, unlimited admins or global)
+// and the global limit per address for unauthenticated requests
+async fn rate_limit<B>(
+    Extension(UserName(username)): Extension<UserName>,
+    State(state): State<SharedState>, 
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let (key, limit) = match username {
+        Some(name) => {
+            let limit = state.users.read().await.iter().find(|u| u.name == name)
+                .map_or(state.rate_limit, |user| user.rate_limit(&state.rate_limit).1);
+            (format!("user {name}"), limit)
+        },
+        None => {
+            let addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip().to_string());
+            (format!("address {}",addr.unwrap_or_default()), state.rate_limit)
+        },
+    };
+    let write = ![axum::http::Method::GET, axum::http::Method::HEAD, axum::http::Method::OPTIONS].contains(request.method());
+    if let Some(limit) = limit.limit(write) {
+        if !state.rate_limiter.check(&key, write, limit) {
+            debug!("rate limit of {key} exceeded: {} {}",request.method(),request.uri().path());
+            let retry_after = (1.0 / limit.per_second).ceil().max(1.0) as u64;
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())], "rate limit exceeded\n").into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn check_user_agent<B>(
+    State(state): State<SharedState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user_agent = request.headers().get(header::USER_AGENT).map(|agent| agent.to_str().unwrap_or_default());
+    if let Some(reason) = state.access.rejects(user_agent) {
+        let addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip().to_string());
+        warn!("client blocked ({reason}) from {}: {} {}",addr.unwrap_or_default(),request.method(),request.uri().path());
+        state.blocked_requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        return (StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "client blocked" }))).into_response();
+    }
+    next.run(request).await
+}
+
+// absolute URL given to clients: https when the broker has TLS, otherwise the scheme
+// of the X-Forwarded-Proto header if the request comes from a trusted reverse proxy
+fn canonical_url(state: &SharedData, host: &str, path: &str, headers: &HeaderMap, peer: SocketAddr) -> String {
+    let forwarded = headers.get("x-forwarded-proto")
+        .filter(|_| state.trusted_proxies.contains(&peer.ip()))
+        .and_then(|proto| proto.to_str().ok())
+        .and_then(|proto| proto.split(',').next())
+        .map(|proto| proto.trim().to_ascii_lowercase());
+    let scheme = match forwarded.as_deref() {
+        _ if state.tls_enabled => "https",
+        Some("https") => "https",
+        _ => "http",
+    };
+    format!("{scheme}://{host}{path}")
+}
+
+fn https_url(hostname: &str, https_port: u32, uri: &Uri) -> String {
+    let host = match hostname.rsplit_once(':') {
+        // keep bracketed ipv6 addresses intact
+        Some((host,port)) if !port.contains(']') => host,
+        _ => hostname,
+    };
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    if https_port == 443 {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}:{https_port}{path}")
+    }
+}
+
+async fn https_redirect(
+    State(https_port): State<u32>,
+    uri: Uri, Host(hostname): Host,
+) -> impl IntoResponse {
+    let target = https_url(&hostname, https_port, &uri);
+    debug!("redirecting http://{}{} to {}",hostname,uri.path(),target);
+    (
+        StatusCode::MOVED_PERMANENTLY,
+        [(header::LOCATION, target)],
+    )
+}
+
+struct HttpsPolicy {
+    hsts: HeaderValue,
+    // public https port when plain http requests must be redirected
+    redirect_to_port: Option<u32>,
+}
+
+// Strict-Transport-Security on the https responses, plain http requests (both mode
+// on a single port) are served or redirected like on the redirect listener
+async fn https_policy<B>(
+    State(policy): State<Arc<HttpsPolicy>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if request.extensions().get::<axum_server_dual_protocol::Protocol>() != Some(&axum_server_dual_protocol::Protocol::Plain) {
+        let mut response = next.run(request).await;
+        response.headers_mut().insert(header::STRICT_TRANSPORT_SECURITY, policy.hsts.clone());
+        return response;
+    }
+    let Some(https_port) = policy.redirect_to_port else {
+        return next.run(request).await;
+    };
+    // the CA fetches the HTTP-01 challenges over plain http
+    if request.uri().path().starts_with(acme::CHALLENGE_PATH.trim_end_matches(":token")) {
+        return next.run(request).await;
+    }
+    let Some(hostname) = request.headers().get(header::HOST).and_then(|host| host.to_str().ok()) else {
+        return (StatusCode::BAD_REQUEST, "missing Host header\n").into_response();
+    };
+    let target = https_url(hostname, https_port, request.uri());
+    debug!("redirecting http://{}{} to {}",hostname,request.uri().path(),target);
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, target)]).into_response()
+}
+
+async fn serve_https_redirect(addr: SocketAddr, https_port: u32, accept_timeout: Duration, acme_challenges: Arc<acme::Challenges>) {
+    let app = Router::new()
+        .route(acme::CHALLENGE_PATH, get(acme::http01_challenge).with_state(acme_challenges))
+        .fallback(https_redirect)
+        .with_state(https_port);
+    warn!("redirecting http://{addr} to https on port {https_port}");
+    if let Err(e) = axum::Server::bind(&addr).http1_header_read_timeout(accept_timeout).serve(app.into_make_service()).await {
+        error!("https redirect listener failed: {e}");
+    }
+}
+
+// fields accepted by strict_json (fields of nested objects as "parent.field")
+const GAME_TURN_FIELDS: &[&str] = &["from", "from.row", "from.col", "to", "to.row", "to.col", "turn", "confidence", "eval", "move_type",
+    "source", "source.row", "source.col", "destination", "destination.row", "destination.col", "turn_number"];
+// (legacy, current) names of the GameTurn fields that have a serde alias
+const LEGACY_TURN_FIELDS: &[(&str, &str)] = &[("source", "from"), ("destination", "to"), ("turn_number", "turn")];
+const GAME_META_FIELDS: &[&str] = &["player_white", "player_black", "description", "notes"];
+
+fn unknown_fields(value: &serde_json::Value, known: &[&str], prefix: &str, unknown: &mut Vec<String>) {
+    let serde_json::Value::Object(map) = value else { return };
+    for (key, value) in map {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+        if !known.contains(&path.as_str()) {
+            unknown.push(path);
+        } else if known.iter().any(|field| field.starts_with(&format!("{path}."))) {
+            unknown_fields(value, known, &path, unknown);
+        }
+    }
+}
+
+// hashes the body of game_post before it is parsed, so that successive identical requests are
+// recognized whatever the serialization of the client (general.dedup_window_secs)
+async fn body_hash(
+    State(max_len): State<usize>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> Response {
+    let is_game_post = request.method() == axum::http::Method::POST
+        && request.extensions().get::<MatchedPath>().map(|path| path.as_str()) == Some("/game/:gameid");
+    if !is_game_post {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let bytes = match read_body(body, max_len).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    let mut hash = [0; 32];
+    hash.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, &bytes).as_ref());
+    parts.extensions.insert(BodyHash(hash));
+    next.run(Request::from_parts(parts, axum::body::Body::from(bytes))).await
+}
+
+// a typo such as "tur" instead of "turn" is otherwise silently ignored by serde
+async fn strict_json(
+    State(max_len): State<usize>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str());
+    let known = match (request.method(), route) {
+        (&axum::http::Method::POST, Some("/game/:gameid")) => GAME_TURN_FIELDS,
+        (&axum::http::Method::PATCH, Some("/game/:gameid/meta")) => GAME_META_FIELDS,
+        _ => return next.run(request).await,
+    };
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(body, max_len).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    // invalid JSON is left to the Json extractor of the handler
+    if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+        let mut unknown = Vec::new();
+        unknown_fields(&value, known, "", &mut unknown);
+        if !unknown.is_empty() {
+            let reply = GameReply::error(format!("unknown fields: {}",unknown.join(", ")));
+            return reply.with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, axum::body::Body::from(bytes))).await
+}
+
+// with general.strict_field_names, so that the clients still using the old field names are found
+async fn strict_field_names(
+    State(max_len): State<usize>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> Response {
+    let is_game_post = request.method() == axum::http::Method::POST
+        && request.extensions().get::<MatchedPath>().map(|path| path.as_str()) == Some("/game/:gameid");
+    if !is_game_post {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(body, max_len).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    // invalid JSON is left to the Json extractor of the handler
+    if let Ok(serde_json::Value::Object(map)) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+        let legacy: Vec<String> = LEGACY_TURN_FIELDS.iter()
+            .filter(|(legacy, _)| map.contains_key(*legacy))
+            .map(|(legacy, current)| format!("{legacy} (use {current})"))
+            .collect();
+        if !legacy.is_empty() {
+            let reply = GameReply::error(format!("legacy field names: {}",legacy.join(", ")));
+            return reply.with_status(StatusCode::UNPROCESSABLE_ENTITY).into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, axum::body::Body::from(bytes))).await
+}
+
+// JSON response serialized with indentation (for humans reading the API with curl or jq)
+struct JsonPretty<T>(T);
+
+impl<T: Serialize> IntoResponse for JsonPretty<T> {
+    fn into_response(self) -> Response {
+        match serde_json::to_string_pretty(&self.0) {
+            Ok(json) => ([(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))], json).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("cannot serialize response: {e}")).into_response(),
+        }
+    }
+}
+
+// Accept: application/json; indent=N (N > 0)
+fn accepts_indented_json(headers: &axum::http::HeaderMap) -> bool {
+    headers.get_all(header::ACCEPT).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            let mut params = media_type.split(';').map(str::trim);
+            params.next() == Some("application/json")
+                && params.any(|param| param.strip_prefix("indent=").and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| n > 0))
+        })
+}
+
+// runs after routing (route_layer) so that game_post (the high-volume path) can be left compact
+async fn pretty_json<B>(
+    State(state): State<SharedState>,
+    Query(params): Query<RequestParams>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let is_game_post = request.method() == axum::http::Method::POST
+        && request.extensions().get::<MatchedPath>().map(|path| path.as_str()) == Some("/game/:gameid");
+    let pretty = params.pretty.unwrap_or(state.pretty_json || accepts_indented_json(request.headers()));
+    if is_game_post || !pretty {
+        return next.run(request).await;
+    }
+    let response = next.run(request).await;
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("cannot read response body: {e}")).into_response(),
+    };
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Response::from_parts(parts, axum::body::boxed(axum::body::Full::from(bytes)));
+    };
+    let mut pretty = JsonPretty(value).into_response();
+    *pretty.status_mut() = parts.status;
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            pretty.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    pretty
+}
+
+const MAX_LOGGED_BODY_LEN: usize = 4096;
+// same as the default limit of axum
+const DEFAULT_MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// converts the game id of /game/GAME_ID/... and /admin/game/GAME_ID/... to general.game_id_case
+async fn normalize_game_id_case<B>(
+    State(case): State<ConfigGameIdCase>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Some(path) = game_id_case_path(case, request.uri().path()) {
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+        let mut parts = request.uri().clone().into_parts();
+        if let Ok(path_and_query) = path_and_query.parse() {
+            parts.path_and_query = Some(path_and_query);
+            if let Ok(uri) = Uri::from_parts(parts) {
+                *request.uri_mut() = uri;
+            }
+        }
+    }
+    next.run(request).await
+}
+
+// None if the path has no game id or if it is already in the right case
+fn game_id_case_path(case: ConfigGameIdCase, path: &str) -> Option<String> {
+    let prefix = ["/game/", "/admin/game/"].into_iter().find(|prefix| path.starts_with(prefix))?;
+    let rest = &path[prefix.len()..];
+    let (gameid, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if prefix == "/game/" && tail.is_empty() && GAME_LITERAL_ROUTES.contains(&gameid) {
+        return None;
+    }
+    let converted = case.apply(gameid);
+    (converted != gameid).then(|| format!("{prefix}{converted}{tail}"))
+}
+
+fn body_too_large(max_len: usize) -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, format!("request body is larger than {max_len} bytes\n")).into_response()
+}
+
+// whole request body for the middlewares that look at it before the extractors (which have
+// their own limit, DefaultBodyLimit), read no further than max_len bytes
+async fn read_body(mut body: axum::body::Body, max_len: usize) -> Result<Bytes,Response> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = axum::body::HttpBody::data(&mut body).await {
+        match chunk {
+            Ok(chunk) if bytes.len() + chunk.len() <= max_len => bytes.extend_from_slice(&chunk),
+            Ok(_) => return Err(body_too_large(max_len)),
+            Err(e) => return Err((StatusCode::BAD_REQUEST, format!("cannot read request body: {e}")).into_response()),
+        }
+    }
+    Ok(Bytes::from(bytes))
+}
+
+// replaces a gzip request body by its decompressed content (other encodings are rejected)
+async fn decompress_request(
+    State(max_len): State<usize>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> Response {
+    let encoding = request.headers().get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    match encoding.as_deref() {
+        None | Some("identity") => return next.run(request).await,
+        Some("gzip") | Some("x-gzip") => (),
+        Some(encoding) => return (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("unsupported content encoding {encoding:?} (only gzip is supported)\n")).into_response(),
+    }
+    let (mut parts, body) = request.into_parts();
+    // the compressed body cannot be larger than the decompressed limit either
+    let compressed = match read_body(body, max_len).await {
+        Ok(compressed) => compressed,
+        Err(response) => return response,
+    };
+    let decompressed = match gzip::decompress(&compressed, max_len) {
+        Ok(decompressed) => decompressed,
+        Err(gzip::GzipError::TooLarge) => return body_too_large(max_len),
+        Err(gzip::GzipError::Invalid(e)) => return (StatusCode::BAD_REQUEST, format!("{e}\n")).into_response(),
+    };
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(decompressed.len()));
+    next.run(Request::from_parts(parts, axum::body::Body::from(decompressed))).await
+}
+
+// MIME type that ServeDir would send for a path relative to a static file tree
+// (guessed from the extension only, so that no file has to be opened)
+fn static_mime_type(path: &str) -> String {
+    let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+    if path.is_empty() || path.ends_with('/') {
+        // index.html of the directory
+        return String::from("text/html");
+    }
+    mime_guess::from_path(path.as_ref()).first_or_octet_stream().essence_str().to_string()
 }
 
-async fn admin_state(
-    Query(params): Query<RequestParams>,
-    Extension(role): Extension<ConfigUserRole>,
-    State(state): State<SharedState>, 
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    uri: Uri, Host(hostname): Host,
-) -> impl IntoResponse {
-    debug!("Role: {:?}",role);
-    debug!("request from {addr} for {}{}",hostname,uri.path());
-    if role < ConfigUserRole::Admin {
-        error!("failed auth from {addr}");
-        return authenticate().into_response();
-    }
-    let dict = state.game_data.read().await;
-    (StatusCode::OK, GameTemplate { refresh: params.refresh, game_data: &dict }.into_response()).into_response()
+fn static_mime_type_allowed(allowed: &[String], mime: &str) -> bool {
+    // .js is guessed as application/javascript but text/javascript is the standard name (RFC 9239)
+    allowed.iter().any(|allowed| allowed.eq_ignore_ascii_case(mime)
+        || (mime == "application/javascript" && allowed.eq_ignore_ascii_case("text/javascript")))
 }
 
-async fn admin_clear(
-    Query(_params): Query<RequestParams>,
-    Extension(role): Extension<ConfigUserRole>,
-    State(state): State<SharedState>, 
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    uri: Uri, Host(hostname): Host,
-) -> impl IntoResponse {
-    debug!("Role: {:?}",role);
-    warn!("request from {addr} for {}{}",hostname,uri.path());
-    if role < ConfigUserRole::Admin {
-        error!("failed auth from {addr}");
-        return authenticate().into_response();
+// false only if the path is valid and there is no such file (never outside of the root)
+async fn static_file_exists(root: &std::path::Path, path: &str) -> bool {
+    let path = percent_encoding::percent_decode_str(path.trim_start_matches('/')).decode_utf8_lossy();
+    let path = std::path::Path::new(path.as_ref());
+    if !path.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+        return true;
     }
-    let mut dict = state.game_data.write().await;
-    dict.clear();
-    (StatusCode::OK, "cleared all games from internal state\n").into_response()
+    tokio::fs::metadata(root.join(path)).await.is_ok()
 }
 
-fn get_config_file_name(in_cwd: bool) -> PathBuf {
-    std::env::current_exe()
-        .ok()
-        .and_then(|pb| if in_cwd {
-            pb.file_name().map(PathBuf::from)
-        } else {
-            Some(pb)
-        })
-        .unwrap_or(PathBuf::from(env!("CARGO_PKG_NAME")))
-        .with_extension("toml")
+// response headers added to a route, checked at startup
+#[derive(Default,Debug,Clone)]
+struct ExtraHeaders {
+    headers: Vec<(header::HeaderName,HeaderValue)>,
+    // ETag = "auto"
+    etag: bool,
 }
 
-async fn cleaner(expires_secs: u64, cleanup_interval_secs: u64, state: SharedState) {
-    loop {
-        sleep(Duration::from_secs(cleanup_interval_secs)).await;
-        debug!("cleaner starting");
-        let mut dict = state.game_data.write().await;
-        dict.retain(|gameid, turndata| {
-            if let Some(last_update) = turndata.updated {
-                if let Ok(age) = last_update.elapsed() {
-                    if age.as_secs() > expires_secs {
-                        info!("game {gameid} has expired");
-                        return false;
-                    }
-                }
+impl ExtraHeaders {
+    fn parse<'a>(headers: impl IntoIterator<Item = (&'a str,&'a str)>) -> Result<Self,String> {
+        let mut extra = ExtraHeaders::default();
+        for (name,value) in headers {
+            let name = header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name {name:?}"))?;
+            if name == header::ETAG && value == "auto" {
+                extra.etag = true;
+                continue;
             }
-            true
-        });
-        debug!("cleaner ending");
+            let value = HeaderValue::from_str(value).map_err(|_| format!("invalid value {value:?} for header {name}"))?;
+            extra.headers.push((name, value));
+        }
+        Ok(extra)
     }
 }
 
-fn authenticate() -> impl IntoResponse {
-    (
-        [
-            (header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"game broker\"")),
-        ],
-        StatusCode::UNAUTHORIZED
-    )
+// ETag of a static file from the Last-Modified header set by ServeDir
+fn static_etag(response: &Response) -> Option<HeaderValue> {
+    let modified = response.headers().typed_get::<axum::headers::LastModified>()?;
+    let secs = SystemTime::from(modified).duration_since(UNIX_EPOCH).ok()?.as_secs();
+    HeaderValue::from_str(&format!("\"{secs:x}\"")).ok()
 }
 
-async fn auth_basic<B>(
-    auth: Option<TypedHeader<Authorization<Basic>>>,
-    Query(params): Query<RequestParams>,
+#[derive(Clone,Debug)]
+struct StaticFilter {
+    // None = all types are allowed
+    allowed_types: Option<Arc<Vec<String>>>,
+    // root of a single-page application (its index.html is served for missing files)
+    spa_root: Option<PathBuf>,
+}
+
+// rejects static files whose type is not in the allowed list
+async fn static_type_filter(
+    State(filter): State<StaticFilter>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> Response {
+    if let Some(allowed) = filter.allowed_types {
+        let path = request.uri().path();
+        let mime = match filter.spa_root.as_deref() {
+            Some(root) if !static_file_exists(root, path).await => String::from("text/html"),
+            _ => static_mime_type(path),
+        };
+        if !static_mime_type_allowed(&allowed, &mime) {
+            debug!("static file {} not served: type {mime} is not allowed",request.uri().path());
+            return (StatusCode::FORBIDDEN, format!("files of type {mime} are not served\n")).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+// logs the raw body of game_post and game_generate requests and puts it back for the extractors
+async fn log_request_body(
+    State(max_len): State<usize>,
+    request: Request<axum::body::Body>,
+    next: Next<axum::body::Body>,
+) -> impl IntoResponse {
+    if request.method() != axum::http::Method::POST {
+        return next.run(request).await;
+    }
+    let gameid = match request.uri().path().trim_end_matches('/').strip_prefix("/game") {
+        Some("") => String::from("(new game)"),
+        Some(rest) if rest.starts_with('/') && !rest[1..].contains('/') => rest[1..].to_string(),
+        _ => return next.run(request).await,
+    };
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(body, max_len).await {
+        Ok(bytes) => bytes,
+        Err(response) => return response,
+    };
+    let logged = &bytes[..bytes.len().min(MAX_LOGGED_BODY_LEN)];
+    debug!(target: logbuf::REQUEST_BODY_TARGET, "game {} request body at {} ({} bytes{}): {}",
+        gameid,
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0),
+        bytes.len(),
+        if bytes.len() > MAX_LOGGED_BODY_LEN { ", truncated" } else { "" },
+        String::from_utf8_lossy(logged));
+    next.run(Request::from_parts(parts, axum::body::Body::from(bytes))).await
+}
+
+// game ID of /game/ID, /game/ID/... and /admin/game/ID/... (borrowed from the path)
+fn extract_gameid<B>(request: &Request<B>) -> Option<&str> {
+    let path = request.uri().path();
+    let rest = path.strip_prefix("/admin").unwrap_or(path).strip_prefix("/game/")?;
+    rest.split('/').next().filter(|gameid| !gameid.is_empty())
+}
+
+// the gameid field is left out of the span when the path has no game ID
+fn request_span<B>(request: &Request<B>) -> tracing::Span {
+    tracing::info_span!("request", method = %request.method(), path = %request.uri().path(), gameid = extract_gameid(request).map(tracing::field::display))
+}
+
+// runs after routing (route_layer) so that the matched route is known
+async fn record_latency<B>(
     State(state): State<SharedState>, 
-    mut request: Request<B>,
+    request: Request<B>,
     next: Next<B>,
 ) -> impl IntoResponse {
-    let mut opt_username = None;
-    let mut opt_password = Some("");
-    if let Some(auth) = auth.as_deref() {
-        opt_username = Some(auth.username());
-        opt_password = Some(auth.password());
-    }
-    if let Some(username) = params.username.as_deref() {
-        opt_username = Some(username);
-    } 
-    if let Some(password) = params.password.as_deref() {
-        opt_password = Some(password);
-    } 
-    if let Some(username) = opt_username {
-        if let Some(password) = opt_password {
-            debug!("REQUEST username: {} password: {}",username,password);
-            if let Some(user) = state.users.iter().find(|u| u.name == username) {
-                debug!("CONFIG USER: {:?}",user);
-                if user.password == password {
-                    request.extensions_mut().insert(user.role);
-                    return next.run(request).await;
-                }
-            }
-        }        
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => format!("{} {}",request.method(),path.as_str()),
+        None => format!("{} (unmatched)",request.method()),
+    };
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+    if let Ok(mut latencies) = state.latencies.lock() {
+        latencies.entry(route).or_default().record(elapsed);
     }
-    request.extensions_mut().insert(state.unauthenticated_role);
-    next.run(request).await
+    response
 }
 
 async fn handle_timeout_error(err: BoxError) -> (StatusCode, String) {
@@ -368,43 +4795,331 @@ async fn handle_timeout_error(err: BoxError) -> (StatusCode, String) {
     }
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::fmt::init();
+// exit code of the broker when the config cannot be used (1 for the other errors)
+const CONFIG_ERROR_EXIT_CODE: i32 = 2;
+
+#[derive(Debug)]
+enum ConfigLoadError {
+    IoError(PathBuf, std::io::Error),
+    // position (1-based) of the invalid TOML or value in the file
+    ParseError { line: Option<u32>, column: Option<u32>, message: String },
+    // all the invalid values, not only the first one
+    ValidationError(Vec<String>),
+    // a key that another setting depends on
+    MissingRequired(String),
+}
 
-    info!("Loading config from {:?} or {:?}",get_config_file_name(true),get_config_file_name(false));
+impl std::fmt::Display for ConfigLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigLoadError::IoError(path, e) => write!(f, "cannot read config file {:?}: {e}",path),
+            ConfigLoadError::ParseError { line: Some(line), column, message } => {
+                let column = column.map(|column| format!(", column {column}")).unwrap_or_default();
+                write!(f, "config is not well-formatted at line {line}{column}: {message}")
+            },
+            ConfigLoadError::ParseError { message, .. } => write!(f, "config is not well-formatted: {message}"),
+            ConfigLoadError::ValidationError(errors) => {
+                write!(f, "config is invalid:")?;
+                errors.iter().try_for_each(|e| write!(f, "\n  - {e}"))
+            },
+            ConfigLoadError::MissingRequired(key) => write!(f, "config is missing {key}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigLoadError {}
+
+impl Config {
+    // checks that can be done without starting anything (the others are done by shared_state_from_config)
+    fn validate(&self) -> Result<(),ConfigLoadError> {
+        if self.general.enable_signing && self.general.signing_key.is_none() {
+            return Err(ConfigLoadError::MissingRequired(String::from("general.signing_key (required by general.enable_signing)")));
+        }
+        if self.general.game_id_alphabet == ConfigGameIdAlphabet::Custom && self.general.game_id_alphabet_chars.is_none() {
+            return Err(ConfigLoadError::MissingRequired(String::from("general.game_id_alphabet_chars (required by game_id_alphabet = \"custom\")")));
+        }
+        let mut errors: Vec<String> = [
+            self.access.validate(),
+            self.rate_limit.validate(),
+            self.latency.validate(),
+            self.general.game_id_alphabet().map(|_| ()),
+            self.general.auth_realm().map(|_| ()),
+            self.general.export_signer().map(|_| ()),
+            self.network.trusted_proxies().map(|_| ()),
+            self.game.bounds().map(|_| ()),
+            self.game.validator.clone().map_or(Ok(()), |validator| validator::Validator::new(validator, self.game.validator_timeout_secs).map(|_| ())),
+            audit::AuditFilter::new(&self.audit).map(|_| ()),
+            ExtraHeaders::parse(self.internal.headers.iter().map(|(name,value)| (name.as_str(), value.as_str())))
+                .map(|_| ()).map_err(|e| format!("internal.headers: {e}")),
+            sni::check_names(&self.tls.certs),
+        ].into_iter().filter_map(Result::err).collect();
+        errors.extend(self.statics.iter().filter_map(|static_dir| {
+            ExtraHeaders::parse(static_dir.extra_headers.iter().map(|h| (h.name.as_str(), h.value.as_str())))
+                .err().map(|e| format!("statics {:?}: {e}",static_dir.uri))
+        }));
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigLoadError::ValidationError(errors)),
+        }
+    }
+}
+
+// the config next to the current directory first, then next to the executable (None: defaults)
+fn config_file() -> Option<PathBuf> {
+    [get_config_file_name(true), get_config_file_name(false)].into_iter().find(|path| path.exists())
+}
 
-    let config: Config = toml::from_str(
-        &read_to_string(get_config_file_name(true))
-            .or(read_to_string(get_config_file_name(false)))
-            .unwrap_or(String::from(""))
-    ).expect("TOML was not well-formatted");
+fn load_config(path: &std::path::Path) -> Result<Config,ConfigLoadError> {
+    info!("Loading config from {:?}",path);
+    let content = read_to_string(path).map_err(|e| ConfigLoadError::IoError(path.to_path_buf(), e))?;
+    let config: Config = toml::from_str(&content).map_err(|e| {
+        let position = e.span().map(|span| {
+            let before = content.get(..span.start).unwrap_or_default();
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+            (line as u32, column as u32)
+        });
+        // the message of toml does not name the key, the line shows it
+        let message = match position.and_then(|(line, _)| content.lines().nth(line as usize - 1)) {
+            Some(source) if !source.trim().is_empty() => format!("{} (in `{}`)",e.message().trim(),source.trim()),
+            _ => e.message().trim().to_string(),
+        };
+        ConfigLoadError::ParseError { line: position.map(|(line, _)| line), column: position.map(|(_, column)| column), message }
+    })?;
+    config.validate()?;
     debug!("{:#?}",config);
+    Ok(config)
+}
 
-    let shared_state = Arc::new(SharedData { 
-        users: config.users,
-        unauthenticated_role: config.general.unauthenticated,
-        ..Default::default()
-    });
+// replaces $VAR and ${VAR} with the value of the environment variable
+fn expand_env_vars(value: &str) -> Result<String,String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos+1..];
+        let (name, remaining) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or_else(|| format!("unterminated ${{ in {value:?}"))?;
+            (&braced[..end], &braced[end+1..])
+        } else {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if name.is_empty() {
+            return Err(format!("empty variable name in {value:?}"));
+        }
+        expanded.push_str(&std::env::var(name).map_err(|_| format!("environment variable {name} is not set"))?);
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn read_users_file(users_file: &str) -> Result<Vec<ConfigUser>,String> {
+    let path = PathBuf::from(expand_env_vars(users_file)?);
+    info!("Loading users from {:?}",path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                warn!("users file {:?} is accessible by other users",path);
+            }
+        }
+    }
+    let content = read_to_string(&path).map_err(|e| format!("cannot read users file {:?}: {e}",path))?;
+    let users_file: ConfigUsersFile = toml::from_str(&content).map_err(|e| format!("users file {:?} is not well-formatted: {e}",path))?;
+    Ok(users_file.users)
+}
+
+// inline users from the main config merged with the users file (if any), with the passwords
+// stored in Vault
+async fn load_users(config: &Config) -> Result<Vec<ConfigUser>,String> {
+    let mut users = config.users.clone();
+    if let Some(users_file) = config.auth.users_file.as_deref() {
+        for user in read_users_file(users_file)? {
+            if users.iter().any(|u| u.name == user.name) {
+                return Err(format!("user {} is defined both in the config and in the users file",user.name));
+            }
+            users.push(user);
+        }
+    }
+    for user in users.iter() {
+        if let Some(limit) = user.rate_limit_override.as_ref() {
+            limit.validate().map_err(|e| format!("invalid rate_limit_override of user {}: {e}",user.name))?;
+        }
+        if user.vault_path.is_some() && !user.password.is_empty() {
+            return Err(format!("user {} has both a password and a vault_path",user.name));
+        }
+        if user.vault_path.is_none() && user.vault_key.is_some() {
+            return Err(format!("user {} has a vault_key but no vault_path",user.name));
+        }
+        if user.vault_path.is_none() && user.password.is_empty() && user.certificate_cn.is_none() {
+            return Err(format!("user {} has no password, vault_path or certificate_cn",user.name));
+        }
+    }
+    #[cfg(feature = "vault")]
+    vault::resolve_passwords(&mut users).await?;
+    #[cfg(not(feature = "vault"))]
+    if let Some(user) = users.iter().find(|user| user.vault_path.is_some()) {
+        return Err(format!("user {} has a vault_path but the broker was built without the vault feature",user.name));
+    }
+    Ok(users)
+}
+
+// re-reads the users (inline and from the users file) and keeps the current ones on error
+async fn reload_users(state: &SharedState) {
+    let Some(path) = config_file() else {
+        error!("cannot reload users, there is no config file");
+        return;
+    };
+    let config = match load_config(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("cannot reload users, config is invalid: {e}");
+            return;
+        }
+    };
+    match load_users(&config).await {
+        Ok(users) => {
+            warn!("reloaded {} users",users.len());
+            *state.users.write().await = users;
+        },
+        Err(e) => error!("cannot reload users: {e}"),
+    }
+}
+
+const DEFAULT_WATCH_CONFIG_DEBOUNCE_SECS: u64 = 2;
+
+fn config_file_modified() -> Option<SystemTime> {
+    std::fs::metadata(get_config_file_name(true))
+        .or(std::fs::metadata(get_config_file_name(false)))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
 
+// polls the modification time of the config file (works on bind mounts and with
+// the symlink swaps of mounted config maps) and reloads the users once it is stable
+async fn watch_config(debounce_secs: u64, state: SharedState) {
+    let debounce = Duration::from_secs(debounce_secs.max(1));
+    let mut loaded = config_file_modified();
+    loop {
+        sleep(Duration::from_secs(1)).await;
+        let mut modified = config_file_modified();
+        if modified == loaded {
+            continue;
+        }
+        // an editor or an atomic write-replace can touch the file several times
+        loop {
+            sleep(debounce).await;
+            let latest = config_file_modified();
+            if latest == modified { break; }
+            modified = latest;
+        }
+        loaded = modified;
+        info!("config file has changed");
+        reload_users(&state).await;
+    }
+}
+
+// literal routes under /game/ that would otherwise be taken for a game id (and case-converted)
+const GAME_LITERAL_ROUTES: &[&str] = &["batch"];
+
+fn build_app(config: &Config, shared_state: SharedState) -> Router {
     let mut app = Router::new()
-        .route("/game", get(game_generate))
-        .route("/game/:gameid", get(game_get).post(game_post))
+        .route(acme::CHALLENGE_PATH, get(acme::http01_challenge).with_state(shared_state.acme_challenges.clone()))
+        .route("/game", get(game_generate).post(game_generate))
+        .route("/game/batch", post(game_batch))
+        .route("/game/:gameid", get(game_get).post(game_post).delete(game_delete))
+        .route("/game/:gameid/meta", patch(game_meta_patch))
+        .route("/game/:gameid/history", get(game_history))
+        .route("/game/:gameid/diff", get(game_diff))
+        .route("/game/:gameid/moves", get(game_moves))
+        .route("/game/:gameid/result", post(game_result_post))
+        .route("/game/:gameid/turn_limit", post(game_turn_limit_post))
+        .route("/game/:gameid/annotations", get(game_annotations))
+        .route("/game/:gameid/turns/:turn_num/annotation", post(game_annotation_post))
+        .route("/game/:gameid/turns/:turn_num/annotation/:idx", delete(game_annotation_delete))
+        .route("/games", get(games))
+        .route("/leaderboard", get(leaderboard))
+        .route("/robots.txt", get(robots_txt))
+        .route("/version", get(protocol::version))
+        .route("/.well-known/security.txt", get(security_txt))
+        .route("/users/:name/rating", get(user_rating))
+        .route("/admin/users/:name/rating", post(admin_user_rating))
+        .route("/admin/users/:name/rate", get(admin_user_rate))
         .route("/admin/state", get(admin_state))
         .route("/admin/clear", delete(admin_clear))
+        .route("/admin/games", get(admin_games).delete(admin_games_delete))
+        .route("/admin/games/awaiting/:username", get(admin_games_awaiting))
+        .route("/admin/graph.dot", get(admin_graph_dot))
+        .route("/admin/graph.svg", get(admin_graph_svg))
+        .route("/admin/cleaner/status", get(admin_cleaner_status))
+        .route("/admin/cache/stats", get(admin_cache_stats))
+        .route("/admin/integrity", get(admin_integrity))
+        .route("/admin/reserve", post(admin_reserve))
+        .route("/admin/reserved", get(admin_reserved))
+        .route("/admin/games/archive", post(admin_games_archive))
+        .route("/admin/archives/:gameid", get(admin_archive))
+        .route("/admin/perf", get(admin_perf))
+        .route("/admin/perf/reset", delete(admin_perf_reset))
+        .route("/admin/activity", get(admin_activity))
+        .route("/admin/logs", get(admin_logs))
+        .route("/admin/logs/download", get(admin_logs_download))
+        .route("/admin/snapshot", post(admin_snapshot))
+        .route("/admin/export", get(admin_export))
+        .route("/admin/export/pubkey", get(admin_export_pubkey))
+        .route("/admin/tls/reload", post(admin_tls_reload))
+        .route("/admin/game/:gameid", get(admin_game_detail))
+        .route("/admin/game/:gameid/raw", get(admin_game_raw))
+        .route("/admin/game/:gameid/invite", post(admin_game_invite))
+        .route("/admin/game/:gameid/rewind", post(admin_game_rewind))
+        .route("/admin/game/:gameid/turn", patch(admin_game_turn_patch))
+        .route("/admin/game/:gameid/pin", post(admin_game_pin).delete(admin_game_unpin))
+        .route("/admin/game/:gameid/rename", post(admin_game_rename))
+        .route("/join/:token", get(join))
+        .route_layer(middleware::from_fn_with_state(shared_state.clone(), pretty_json))
+        .route_layer(middleware::from_fn_with_state(shared_state.clone(), record_latency))
+        // the game ID is added to every log line of the request
+        .route_layer(TraceLayer::new_for_http().make_span_with(request_span))
         .with_state(shared_state.clone());
 
-    for static_dir in config.statics {
+    for static_dir in config.statics.iter().cloned() {
         let trace_layer = TraceLayer::new_for_http()
             .make_span_with(trace::DefaultMakeSpan::new().level(tracing::Level::TRACE))
             .on_response(trace::DefaultOnResponse::new().level(tracing::Level::DEBUG));
+        let allowed_types = match static_dir.all_types {
+            true => None,
+            false => static_dir.allowed_types.or(config.general.default_allowed_types.clone()).map(Arc::new),
+        };
+        let filter = StaticFilter {
+            allowed_types,
+            spa_root: static_dir.spa_fallback.then(|| PathBuf::from(&static_dir.path)),
+        };
+        let serve_dir = ServiceBuilder::new().layer(middleware::from_fn_with_state(filter, static_type_filter));
+        let serve_dir = match static_dir.spa_fallback {
+            // like try_files $uri /index.html (with 200, the client side router shows the page)
+            true => serve_dir
+                .service(ServeDir::new(&static_dir.path).fallback(ServeFile::new(PathBuf::from(&static_dir.path).join("index.html"))))
+                .boxed_clone(),
+            false => serve_dir.service(ServeDir::new(&static_dir.path)).boxed_clone(),
+        };
+        let extra_headers = ExtraHeaders::parse(static_dir.extra_headers.iter().map(|h| (h.name.as_str(), h.value.as_str())))
+            .expect("statics extra_headers checked by Config::validate");
+        let mut serve_dir = serve_dir;
+        for (name,value) in extra_headers.headers {
+            serve_dir = ServiceBuilder::new().layer(SetResponseHeaderLayer::overriding(name, value)).service(serve_dir).boxed_clone();
+        }
+        if extra_headers.etag {
+            serve_dir = ServiceBuilder::new().layer(SetResponseHeaderLayer::if_not_present(header::ETAG, static_etag)).service(serve_dir).boxed_clone();
+        }
         if static_dir.uri.ends_with('/') {
-            app = app.nest_service(static_dir.uri.as_str(), ServeDir::new(static_dir.path))
+            app = app.nest_service(static_dir.uri.as_str(), serve_dir)
                 .layer(trace_layer);
         } else {
             // set up route for .../uri/ and redirect .../uri to .../uri/
             let with_slash = format!("{}/",static_dir.uri);
-            app = app.nest_service(&with_slash, ServeDir::new(static_dir.path))
+            app = app.nest_service(&with_slash, serve_dir)
                 .layer(trace_layer)
                 .route(static_dir.uri.as_str(), get(|| async { 
                     let target = with_slash; // take ownership
@@ -413,21 +5128,29 @@ async fn main() {
         }
     }
 
-    #[cfg(feature = "internal")]
+    #[cfg(feature = "qrcode")]
     {
-        macro_rules! get_bytes {
-            ($ctype:expr,$s:expr) => {
-                get(|| async {
-                    ([(header::CONTENT_TYPE, $ctype)], include_bytes!(concat!("../../ai_wargame_web/",$s)))
-                })
-            };
+        app = app.route("/game/:gameid/qr", get(game_qr).with_state(shared_state.clone()));
+    }
+
+    #[cfg(any(feature = "internal", feature = "internal-dynamic"))]
+    {
+        let extra_headers = ExtraHeaders::parse(config.internal.headers.iter().map(|(name,value)| (name.as_str(), value.as_str())))
+            .expect("internal.headers checked by Config::validate");
+        #[cfg(feature = "internal")]
+        let mut internal_router = {
+            app = app.route("/admin/asset-integrity", get(internal::admin_asset_integrity).with_state(shared_state.clone()));
+            // the preload links are only turned into pushes over TLS (HTTP/2)
+            internal::router(config.general.http2_push && config.tls.enabled != ConfigTLSType::Http, extra_headers.etag)
+        };
+        #[cfg(feature = "internal-dynamic")]
+        let mut internal_router = {
+            let root = config.general.internal_assets_path.as_deref().unwrap_or(internal_dynamic::DEFAULT_ASSETS_PATH);
+            internal_dynamic::router(PathBuf::from(root), extra_headers.etag)
+        };
+        for (name,value) in extra_headers.headers {
+            internal_router = internal_router.layer(SetResponseHeaderLayer::overriding(name, value));
         }
-        let internal_router = Router::new()
-            .route("/", get_bytes!("text/html; charset=utf-8","index.html"))
-            .route("/game.js", get_bytes!("text/javascript","game.js"))
-            .route("/game.css", get_bytes!("text/css","game.css"))
-            .route("/pkg/ai_wargame_web.js", get_bytes!("text/javascript","pkg/ai_wargame_web.js"))
-            .route("/pkg/ai_wargame_web_bg.wasm", get_bytes!("application/wasm","pkg/ai_wargame_web_bg.wasm"));
         if let Some(internal_uri) = config.general.internal.as_deref() {
             if internal_uri.ends_with('/') {
                 app = app.nest(internal_uri,internal_router)
@@ -443,50 +5166,472 @@ async fn main() {
         }
     }
 
+    if config.general.allow_reset {
+        app = app.route("/admin/reset", post(admin_reset).with_state(shared_state.clone()));
+    }
+
+    #[cfg(feature = "simulate")]
+    if let Some(latency) = latency_injection(config) {
+        app = app.route_layer(middleware::from_fn_with_state(Arc::new(simulate::Simulator::new(latency)), simulate::inject));
+    }
+
+    // limit of the (decompressed) request bodies read by the Json extractors and the middlewares
+    let max_request_body_bytes = config.general.max_request_body_bytes.unwrap_or(DEFAULT_MAX_REQUEST_BODY_BYTES);
+
+    if config.general.dedup_window_secs.unwrap_or(DEFAULT_DEDUP_WINDOW_SECS) > 0 {
+        // route_layer so that the matched route is known
+        app = app.route_layer(middleware::from_fn_with_state(max_request_body_bytes, body_hash));
+    }
+
+    if config.general.strict_json {
+        // route_layer so that the matched route is known
+        app = app.route_layer(middleware::from_fn_with_state(max_request_body_bytes, strict_json));
+    }
+
+    if config.general.strict_field_names {
+        // route_layer so that the matched route is known
+        app = app.route_layer(middleware::from_fn_with_state(max_request_body_bytes, strict_field_names));
+    }
+
+    if config.general.debug_log_request_bodies {
+        app = app.layer(middleware::from_fn_with_state(max_request_body_bytes, log_request_body));
+    }
+
+    app = app.layer(DefaultBodyLimit::max(max_request_body_bytes));
+    if config.general.decompress_requests {
+        // added after log_request_body so that the logged bodies are decompressed
+        app = app.layer(middleware::from_fn_with_state(max_request_body_bytes, decompress_request));
+    }
+
+    // rate limiting (after authentication so that users have their own limits)
+    app = app.layer(middleware::from_fn_with_state(shared_state.clone(), rate_limit));
+
+    // audit log (after authentication for the user name, requests over the rate limit included)
+    let audit_filter = audit::AuditFilter::new(&config.audit).expect("audit checked by Config::validate");
+    if audit_filter.is_enabled() {
+        app = app.layer(middleware::from_fn_with_state(Arc::new(audit_filter), audit::record));
+    }
+
     // authentication middleware
     app = app.layer(middleware::from_fn_with_state(shared_state.clone(), auth_basic));
 
+    // blocked clients are rejected before authentication
+    if config.access.is_enabled() {
+        app = app.layer(middleware::from_fn_with_state(shared_state, check_user_agent));
+    }
+
     // timeout handling
-    app = app.layer(
+    let app = app.layer(
         ServiceBuilder::new()
             .layer(HandleErrorLayer::new(handle_timeout_error))
             .layer(TimeoutLayer::new(Duration::from_secs(10)))
     );
 
-    if let Some(interval_secs) = config.general.cleanup {
-        if let Some(expires_secs) = config.general.expires {
-            tokio::spawn(cleaner(expires_secs, interval_secs, shared_state.clone()));
+    // the layers of a router run after routing: the game id is converted by an outer
+    // router so that every handler extracts the converted one
+    match config.general.game_id_case {
+        ConfigGameIdCase::AsIs => app,
+        case => Router::new().fallback_service(ServiceBuilder::new()
+            .layer(middleware::from_fn_with_state(case, normalize_game_id_case))
+            .service(app)),
+    }
+}
+
+fn shared_state_from_config(config: &Config) -> SharedState {
+    Arc::new(SharedData { 
+        users: RwLock::new(config.users.clone()),
+        unauthenticated_role: config.general.unauthenticated,
+        move_rules: config.game.move_rules(),
+        bounds: config.game.bounds().expect("game bounds checked by Config::validate"),
+        validator: config.game.validator.clone()
+            .map(|validator| Arc::new(validator::Validator::new(validator, config.game.validator_timeout_secs).expect("game.validator checked by Config::validate"))),
+        elo_k_factor: config.general.elo_k_factor.unwrap_or(rating::DEFAULT_K_FACTOR),
+        created_expires_secs: config.general.created_expires_secs,
+        finished_expires_secs: config.general.finished_expires_secs,
+        max_batch_ttl_secs: config.general.created_expires_secs.or(config.general.expires.map(|expires| expires.as_secs() / 10)),
+        hard_max_age_secs: config.general.hard_max_age_secs,
+        hard_max_games: config.general.hard_max_games,
+        archived_retention_secs: config.general.archived_retention_secs,
+        reserved_expires_secs: config.general.reserved_expires_secs.unwrap_or(DEFAULT_RESERVED_EXPIRES_SECS),
+        max_turns_per_game: config.general.max_turns_per_game,
+        max_annotations_per_game: config.general.max_annotations_per_game.unwrap_or(DEFAULT_MAX_ANNOTATIONS_PER_GAME),
+        max_history_per_game: config.general.max_history_per_game.unwrap_or(DEFAULT_MAX_HISTORY_PER_GAME),
+        game_id_alphabet: config.general.game_id_alphabet().expect("general.game_id_alphabet checked by Config::validate"),
+        game_id_case: config.general.game_id_case,
+        id_gen_max_attempts: config.general.id_gen_max_attempts.unwrap_or(DEFAULT_ID_GEN_MAX_ATTEMPTS).max(1),
+        max_batch_generate: config.general.max_batch_generate.unwrap_or(DEFAULT_MAX_BATCH_GENERATE),
+        id_gen_retry_delay_ms: config.general.id_gen_retry_delay_ms,
+        reserved_game_ids: config.general.reserved_game_ids(),
+        robots_txt: Arc::new(config.general.robots_txt()),
+        auth_realm: config.general.auth_realm().expect("general.auth_realm checked by Config::validate"),
+        pretty_json: config.general.pretty_json,
+        default_refresh_secs: config.general.default_refresh_secs,
+        omit_envelope: !config.general.default_envelope.unwrap_or(true),
+        warn_duplicate_moves: config.general.warn_duplicate_moves || config.general.reject_duplicate_moves,
+        reject_duplicate_moves: config.general.reject_duplicate_moves,
+        dedup_window_secs: config.general.dedup_window_secs.unwrap_or(DEFAULT_DEDUP_WINDOW_SECS),
+        cache_min_reads: config.general.cache_min_reads.unwrap_or(DEFAULT_CACHE_MIN_READS),
+        max_response_memory_bytes: match config.general.max_response_memory_mb.unwrap_or(memguard::DEFAULT_MAX_RESPONSE_MEMORY_MB) {
+            0 => None,
+            mb => Some(mb.saturating_mul(1 << 20)),
+        },
+        activity: activity::ActivityLog::new(config.general.activity_retention
+            .map(|retention| retention.0)
+            .unwrap_or(Duration::from_secs(activity::DEFAULT_RETENTION_SECS))),
+        security_txt: config.security.as_ref().map(|security| Arc::new(security.security_txt())),
+        token_secret: config.auth.token_secret.clone().unwrap_or_else(|| nanoid!(32)).into_bytes(),
+        token_ttl_secs: config.auth.token_ttl_secs.unwrap_or(token::DEFAULT_TTL_SECS),
+        tls_enabled: config.tls.enabled != ConfigTLSType::Http,
+        trusted_proxies: config.network.trusted_proxies().expect("network.trusted_proxies checked by Config::validate"),
+        // a snapshot of the (always empty) game data would overwrite the state file
+        state_file: match config.storage.backend {
+            ConfigStorageBackend::Memory => config.general.state_file.as_ref().map(PathBuf::from),
+            ConfigStorageBackend::Noop => None,
+        },
+        storage: config.storage.backend,
+        export_signer: config.general.export_signer().expect("general.signing_key checked by Config::validate"),
+        access: config.access.clone(),
+        rate_limit: config.rate_limit,
+        sni_hosts: config.tls.certs.iter().map(|cert| cert.sni.to_ascii_lowercase()).collect(),
+        ..Default::default()
+    })
+}
+
+// settings that should not be used in production (logged at startup and repeated in the banner)
+fn startup_warnings(config: &Config) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if config.general.allow_reset {
+        warnings.push(String::from("admin reset endpoint is enabled — do not use in production"));
+    }
+    if let Some(latency) = latency_injection(config) {
+        warnings.push(match cfg!(feature = "simulate") {
+            true => format!("latency injection is enabled — DISABLE IN PRODUCTION ({:?} {}-{}ms, failure rate {})",latency.distribution,latency.min_ms,latency.max_ms,latency.failure_rate),
+            false => String::from("latency injection is configured but ignored (the broker was built without the simulate feature)"),
+        });
+    }
+    if cfg!(feature = "internal-dynamic") {
+        warnings.push(String::from("the internal web UI is read from disk (internal-dynamic feature, for frontend development)"));
+    } else if config.general.internal_assets_path.is_some() {
+        warnings.push(String::from("internal_assets_path is set but ignored (the broker was built without the internal-dynamic feature)"));
+    }
+    if config.storage.backend == ConfigStorageBackend::Noop {
+        warnings.push(String::from("Storage backend is 'noop' — all game data will be discarded"));
+    }
+    if config.general.debug_log_request_bodies {
+        warnings.push(String::from("request bodies will be logged (debug level)"));
+    }
+    if config.tls.enabled != ConfigTLSType::Https && config.users.iter().any(|user| !user.password.is_empty()) {
+        warnings.push(String::from("passwords can be sent over plain http (basic auth is only encrypted with https)"));
+    }
+    warnings
+}
+
+// in-flight requests get this long to complete once the broker is asked to stop (longer than
+// MAX_WAIT_SECS so that the long-polling requests can return)
+const GRACEFUL_SHUTDOWN_SECS: u64 = 10;
+
+// ctrl-c, or SIGTERM on unix (service managers, the test fixture)
+async fn termination_requested() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// the listeners stop accepting connections once the broker is asked to stop, and the broker
+// exits when the requests in progress are done (every listener waits on its own clone)
+#[derive(Clone)]
+struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    fn install() -> Self {
+        let (requested, receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            termination_requested().await;
+            warn!("shutting down, waiting up to {GRACEFUL_SHUTDOWN_SECS}s for the requests in progress");
+            let _ = requested.send(true);
+            sleep(Duration::from_secs(GRACEFUL_SHUTDOWN_SECS)).await;
+            warn!("requests still in progress after {GRACEFUL_SHUTDOWN_SECS}s, exiting anyway");
+            std::process::exit(0);
+        });
+        Shutdown(receiver)
+    }
+    async fn requested(mut self) {
+        let _ = self.0.wait_for(|requested| *requested).await;
+    }
+    // for the axum_server listeners
+    fn handle(&self) -> axum_server::Handle {
+        let handle = axum_server::Handle::new();
+        let (shutdown, listener) = (self.clone(), handle.clone());
+        tokio::spawn(async move {
+            shutdown.requested().await;
+            listener.graceful_shutdown(None);
+        });
+        handle
+    }
+}
+
+fn startup_banner(config: &Config, warnings: &[String]) -> String {
+    let build_time = env!("BUILD_TIMESTAMP").parse().map(snapshot::iso8601).unwrap_or_default();
+    let addr = SocketAddr::from(config.network.clone());
+    let listeners = match (config.tls.enabled, config.tls.http_port) {
+        (ConfigTLSType::Http, _) => format!("http://{addr}"),
+        (ConfigTLSType::Https, _) => format!("https://{addr}"),
+        (ConfigTLSType::Both, Some(http_port)) => format!("https://{addr} and http port {http_port}"),
+        (ConfigTLSType::Both, None) => format!("http+https://{addr}"),
+    };
+    let admins = config.users.iter().filter(|user| user.role >= ConfigUserRole::Admin).count();
+    let expiry = match (config.general.expires, config.general.cleanup) {
+        (Some(expires), Some(cleanup)) => format!("after {expires}, cleanup every {cleanup}"),
+        (None, Some(cleanup)) if config.general.has_cleanup_limits() => format!("never, limits checked every {cleanup}"),
+        _ => String::from("never (no cleanup)"),
+    };
+    let features: Vec<&str> = [("internal", cfg!(feature = "internal")), ("internal-dynamic", cfg!(feature = "internal-dynamic")), ("simulate", cfg!(feature = "simulate")), ("qrcode", cfg!(feature = "qrcode")), ("vault", cfg!(feature = "vault"))]
+        .into_iter().filter_map(|(name, enabled)| enabled.then_some(name)).collect();
+    let mut lines = vec![
+        format!("{} {} (built {build_time})",env!("CARGO_PKG_NAME"),env!("CARGO_PKG_VERSION")),
+        format!("  listening:  {listeners}"),
+        format!("  tls:        {:?}",config.tls.enabled).to_lowercase(),
+        format!("  users:      {} ({admins} admin), unauthenticated role {}",config.users.len(),config.general.unauthenticated),
+        format!("  games:      expire {expiry}, storage {:?}",config.storage.backend).to_lowercase(),
+        format!("  features:   {}",if features.is_empty() { String::from("none") } else { features.join(", ") }),
+    ];
+    lines.extend(warnings.iter().map(|warning| format!("  warning:    {warning}")));
+    lines.join("\n")
+}
+
+// one multi-line event before the listeners are started (general.quiet_startup turns it off)
+fn print_startup_banner(config: &Config, warnings: &[String]) {
+    info!("{}",startup_banner(config, warnings));
+}
+
+#[tokio::main]
+async fn main() {
+    {
+        use tracing_subscriber::{prelude::*, fmt};
+        tracing_subscriber::registry()
+            .with(fmt::layer().with_filter(logbuf::console_filter()))
+            .with(fmt::layer()
+                .with_ansi(false)
+                .with_writer(logbuf::MakeLogBufferWriter)
+                .with_filter(logbuf::buffer_filter()))
+            .init();
+    }
+
+    let mut config = match config_file() {
+        Some(path) => load_config(&path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(CONFIG_ERROR_EXIT_CODE);
+        }),
+        None => {
+            info!("No config file at {:?} or {:?}, using the defaults",get_config_file_name(true),get_config_file_name(false));
+            Config::default()
+        },
+    };
+    logbuf::LOG_BUFFER.set_capacity(config.general.log_buffer_size.unwrap_or(logbuf::DEFAULT_CAPACITY));
+    config.users = load_users(&config).await.unwrap_or_else(|e| panic!("{e}"));
+    #[cfg(feature = "vault")]
+    if config.users.iter().any(|user| user.vault_path.is_some()) {
+        vault::start_token_renewal().await;
+    }
+
+    let selftest_requested = std::env::args().nth(1).as_deref() == Some("selftest");
+    if selftest_requested || config.general.selftest_on_startup {
+        let passed = selftest::run(&config).await;
+        if selftest_requested || !passed {
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+    }
+
+    #[cfg(feature = "internal")]
+    internal::verify_asset_integrity();
+
+    let shared_state = shared_state_from_config(&config);
+    let warnings = startup_warnings(&config);
+    for warning in warnings.iter() {
+        warn!("{warning}");
+    }
+    let alphabet_len = shared_state.game_id_alphabet.len();
+    info!("game ID entropy: {:.1} bits ({} chars, {}-char alphabet)",GAME_ID_LEN as f64 * (alphabet_len as f64).log2(),GAME_ID_LEN,alphabet_len);
+    if let Some(state_file) = shared_state.state_file.as_deref() {
+        if let Some(games) = snapshot::load(state_file) {
+            *shared_state.game_data.write().await = games;
+        }
+    }
+    let app = build_app(&config, shared_state.clone());
+
+    #[cfg(unix)]
+    {
+        let state = shared_state.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let Ok(mut hangup) = signal(SignalKind::hangup()) else { return };
+            while hangup.recv().await.is_some() {
+                reload_users(&state).await;
+            }
+        });
+    }
+
+    if config.general.watch_config {
+        let debounce_secs = config.general.watch_config_debounce_secs.unwrap_or(DEFAULT_WATCH_CONFIG_DEBOUNCE_SECS);
+        info!("watching the config file for changes");
+        tokio::spawn(watch_config(debounce_secs, shared_state.clone()));
+    }
+
+    if let Some(interval) = config.general.cleanup {
+        if let Some(expires) = config.general.expires {
+            info!("games expire after {expires}, cleanup every {interval}");
+            tokio::spawn(cleaner(expires.as_secs(), interval.as_secs(), shared_state.clone()));
+        } else if config.general.has_cleanup_limits() {
+            // only the hard limits and the retention of the archives apply
+            info!("games never expire, cleanup every {interval} for the limits");
+            tokio::spawn(cleaner(u64::MAX, interval.as_secs(), shared_state.clone()));
+        }
+    }
+
+    if let Some(interval_secs) = config.general.snapshot_interval_secs {
+        match shared_state.state_file.clone() {
+            Some(state_file) => { tokio::spawn(snapshot::snapshotter(state_file, interval_secs, shared_state.clone())); },
+            None => warn!("snapshot_interval_secs is set but there is no state_file"),
+        }
+    }
+
+    let (mut tls_cert, mut tls_key) = (PathBuf::from(&config.tls.cert), PathBuf::from(&config.tls.key));
+    if config.tls.acme_domain.is_some() {
+        let challenge_port = config.tls.acme_challenge_port(config.network.port)
+            .expect("tls.acme_domain requires tls.enabled = \"https\" with http_redirect_port, or tls.enabled = \"both\"");
+        let acme = Arc::new(acme::AcmeClient::new(&config.tls, shared_state.acme_challenges.clone()).unwrap_or_else(|e| panic!("{e}")));
+        if acme.needs_renewal() {
+            let challenge_addr = SocketAddr::new(SocketAddr::from(config.network.clone()).ip(), challenge_port as u16);
+            acme.obtain_serving_challenges(challenge_addr).await
+                .unwrap_or_else(|e| panic!("cannot obtain the certificate of {}: {e}",acme.domain()));
+        }
+        (tls_cert, tls_key) = (acme.cert_path(), acme.key_path());
+        // renewed by the cleaner if it runs
+        if config.general.cleanup.is_none() || (config.general.expires.is_none() && !config.general.has_cleanup_limits()) {
+            let (acme, state) = (acme.clone(), shared_state.clone());
+            tokio::spawn(async move {
+                loop {
+                    sleep(Duration::from_secs(acme::RENEWAL_CHECK_SECS)).await;
+                    acme::renew_if_needed(acme.clone(), state.tls_certs.get().cloned());
+                }
+            });
         }
+        shared_state.acme.set(acme).expect("the ACME client is only created once");
     }
 
+    // every certificate is checked against its key before anything is bound
+    let tls_certs = (config.tls.enabled != ConfigTLSType::Http).then(|| {
+        let resolver = sni::SniResolver::new(&tls_cert, &tls_key, &config.tls.certs)
+            .unwrap_or_else(|e| panic!("{e}"));
+        let resolver = Arc::new(resolver);
+        shared_state.tls_certs.set(resolver.clone()).expect("TLS certificates are only loaded once");
+        resolver
+    });
+
+    assert!(config.network.accept_timeout_secs > 0, "network.accept_timeout_secs must be positive");
+    assert!(config.tls.handshake_timeout_secs != Some(0), "tls.handshake_timeout_secs must be positive");
+    assert!(!config.tls.enforce_https || config.tls.enabled != ConfigTLSType::Http, "tls.enforce_https cannot be used when tls.enabled = \"http\"");
+    let accept_timeout = Duration::from_secs(config.network.accept_timeout_secs);
+    let handshake_timeout = config.tls.handshake_timeout();
+    if !config.general.quiet_startup {
+        print_startup_banner(&config, &warnings);
+    }
     let addr = SocketAddr::from(config.network);
+    let shutdown = Shutdown::install();
+    // the plain http listener of both mode keeps serving the app without the header
+    let https_app = {
+        let hsts = HeaderValue::from_str(&config.tls.hsts()).expect("the HSTS header is ASCII");
+        let redirect_to_port = (config.tls.enforce_https && config.tls.enabled == ConfigTLSType::Both)
+            .then(|| config.tls.https_port.unwrap_or(addr.port() as u32));
+        app.clone().layer(middleware::from_fn_with_state(Arc::new(HttpsPolicy { hsts, redirect_to_port }), https_policy))
+    };
     match config.tls.enabled {
         ConfigTLSType::Http => {
             warn!("listening on http://{addr}");
             axum::Server::bind(&addr)
+                .http1_header_read_timeout(accept_timeout)
                 .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(shutdown.requested())
                 .await
                 .unwrap();
         },
         ConfigTLSType::Https => {
-            let tls_config = RustlsConfig::from_pem_file(
-                PathBuf::from(config.tls.cert),
-                PathBuf::from(config.tls.key),
-            ).await.unwrap();
+            if let Some(redirect_port) = config.tls.http_redirect_port {
+                let redirect_addr = SocketAddr::new(addr.ip(), redirect_port as u16);
+                let https_port = config.tls.https_port.unwrap_or(addr.port() as u32);
+                tokio::spawn(serve_https_redirect(redirect_addr, https_port, accept_timeout, shared_state.acme_challenges.clone()));
+            }
+            let tls_certs = tls_certs.expect("TLS certificates are loaded in https mode");
+            if config.tls.require_client_cert {
+                let client_ca_cert = config.tls.client_ca_cert.expect("require_client_cert needs client_ca_cert");
+                let tls_config = mtls::rustls_config(tls_certs, &PathBuf::from(client_ca_cert)).unwrap();
+                warn!("listening on https://{addr} (client certificate required)");
+                axum_server::bind(addr)
+                    .handle(shutdown.handle())
+                    .acceptor(handshake::TimeoutAcceptor::new(mtls::ClientCertAcceptor::new(tls_config, handshake_timeout), handshake_timeout))
+                    .serve(https_app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .unwrap();
+                return;
+            }
+            let tls_config = sni::rustls_config(tls_certs);
             warn!("listening on https://{addr}");
-            axum_server::bind_rustls(addr, tls_config)
-                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            let acceptor = RustlsAcceptor::new(tls_config).handshake_timeout(handshake_timeout);
+            axum_server::bind(addr)
+                .handle(shutdown.handle())
+                .acceptor(handshake::TimeoutAcceptor::new(acceptor, handshake_timeout))
+                .serve(https_app.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .unwrap();
         },
         ConfigTLSType::Both => {
-            let tls_config = RustlsConfig::from_pem_file(
-                PathBuf::from(config.tls.cert),
-                PathBuf::from(config.tls.key),
-            ).await.unwrap();
+            // plain http connections would bypass the client certificate check
+            assert!(!config.tls.require_client_cert, "require_client_cert is only supported when tls.enabled = \"https\"");
+            let tls_config = sni::rustls_config(tls_certs.expect("TLS certificates are loaded in both mode"));
+            if let Some(http_port) = config.tls.http_port {
+                // separate listeners (ex: http on 80 and https on 443)
+                let http_addr = SocketAddr::new(addr.ip(), http_port as u16);
+                if config.tls.http_redirect_only || config.tls.enforce_https {
+                    let https_port = config.tls.https_port.unwrap_or(addr.port() as u32);
+                    tokio::spawn(serve_https_redirect(http_addr, https_port, accept_timeout, shared_state.acme_challenges.clone()));
+                } else {
+                    warn!("listening on http://{http_addr}");
+                    let http_server = axum::Server::bind(&http_addr)
+                        .http1_header_read_timeout(accept_timeout)
+                        .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>())
+                        .with_graceful_shutdown(shutdown.clone().requested());
+                    tokio::spawn(async move {
+                        if let Err(e) = http_server.await {
+                            error!("http listener failed: {e}");
+                        }
+                    });
+                }
+                warn!("listening on https://{addr}");
+                let acceptor = RustlsAcceptor::new(tls_config).handshake_timeout(handshake_timeout);
+                axum_server::bind(addr)
+                    .handle(shutdown.handle())
+                    .acceptor(handshake::TimeoutAcceptor::new(acceptor, handshake_timeout))
+                    .serve(https_app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .unwrap();
+                return;
+            }
             warn!("listening on http+https://{addr}");
+            // the rustls acceptor inside has a fixed timeout of 10s that can expire first
             axum_server_dual_protocol::bind_dual_protocol(addr, tls_config)
-                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .handle(shutdown.handle())
+                .map(|acceptor| handshake::TimeoutAcceptor::new(acceptor, handshake_timeout))
+                .http_config(HttpConfig::new().http1_header_read_timeout(accept_timeout).build())
+                .serve(https_app.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .unwrap();
         },
@@ -0,0 +1,23 @@
+// per-request memory guard of the replies that render every game (the admin page and the
+// export): their size is estimated from the game data before rendering, and a reply larger
+// than general.max_response_memory_mb is answered with 507 without being built
+
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use tracing::warn;
+use crate::GameReply;
+
+pub const DEFAULT_MAX_RESPONSE_MEMORY_MB: usize = 256;
+
+// upper bounds of a rendered game (row of the admin page, export without its turns) and of
+// each turn or annotation it holds, for the usual sizes of the metadata and annotations
+pub const BYTES_PER_GAME: usize = 4096;
+pub const BYTES_PER_TURN: usize = 512;
+
+// the 507 reply if the estimated size of the reply is over max_bytes (None: no limit)
+pub fn over_limit(route: &str, estimated_bytes: usize, max_bytes: Option<usize>) -> Option<Response> {
+    let max_bytes = max_bytes.filter(|max_bytes| estimated_bytes > *max_bytes)?;
+    warn!("{route} would need about {} MB to build its reply, over general.max_response_memory_mb",estimated_bytes >> 20);
+    Some(GameReply::error(format!("the reply needs more than {} MB of memory",max_bytes >> 20))
+        .with_status(StatusCode::INSUFFICIENT_STORAGE).into_response())
+}
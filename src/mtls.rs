@@ -0,0 +1,130 @@
+// TLS client certificate authentication (mutual TLS)
+// the CN of the verified client certificate is passed to the auth middleware
+// as a request extension so that it can be mapped to a user
+
+use axum::Extension;
+use axum_server::{accept::{Accept, DefaultAcceptor}, tls_rustls::{RustlsAcceptor, RustlsConfig}};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig, server::{AllowAnyAuthenticatedClient, ResolvesServerCert}};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use std::{future::Future, io, path::Path, pin::Pin, sync::Arc, time::Duration};
+
+// common name of the verified client certificate (None if the client did not send one)
+#[derive(Default,Debug,Clone)]
+pub struct ClientCertName(pub Option<String>);
+
+pub fn read_certs(path: &Path) -> io::Result<Vec<Certificate>> {
+    let pem = std::fs::read(path).map_err(|e| io::Error::other(format!("cannot read {:?}: {e}",path)))?;
+    Ok(rustls_pemfile::certs(&mut pem.as_slice())?.into_iter().map(Certificate).collect())
+}
+
+pub fn read_key(path: &Path) -> io::Result<PrivateKey> {
+    use rustls_pemfile::Item;
+    let pem = std::fs::read(path).map_err(|e| io::Error::other(format!("cannot read {:?}: {e}",path)))?;
+    match rustls_pemfile::read_one(&mut pem.as_slice())? {
+        Some(Item::RSAKey(key)) | Some(Item::PKCS8Key(key)) | Some(Item::ECKey(key)) => Ok(PrivateKey(key)),
+        _ => Err(io::Error::other(format!("private key format of {:?} not supported",path))),
+    }
+}
+
+// same as sni::rustls_config but clients must present a certificate signed by client_ca
+pub fn rustls_config(resolver: Arc<dyn ResolvesServerCert>, client_ca: &Path) -> io::Result<RustlsConfig> {
+    let mut roots = RootCertStore::empty();
+    for ca in read_certs(client_ca)? {
+        roots.add(&ca).map_err(|e| io::Error::other(format!("invalid client CA in {:?}: {e}",client_ca)))?;
+    }
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(RustlsConfig::from_config(Arc::new(config)))
+}
+
+// rustls acceptor that adds the ClientCertName extension to every request of the connection
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(config: RustlsConfig, handshake_timeout: Duration) -> Self {
+        ClientCertAcceptor { inner: RustlsAcceptor::new(config).handshake_timeout(handshake_timeout) }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = <Extension<ClientCertName> as Layer<S>>::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = <RustlsAcceptor<DefaultAcceptor> as Accept<I, S>>::accept(&self.inner, stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            let name = stream.get_ref().1.peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| common_name(&cert.0));
+            Ok((stream, Extension(ClientCertName(name)).layer(service)))
+        })
+    }
+}
+
+// minimal DER reader: returns (tag, content, rest)
+pub fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&len, mut input) = input.split_first()?;
+    let len = if len < 0x80 {
+        len as usize
+    } else {
+        let count = (len & 0x7f) as usize;
+        if count == 0 || count > 4 || input.len() < count { return None; }
+        let len = input[..count].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        input = &input[count..];
+        len
+    };
+    if input.len() < len { return None; }
+    Some((tag, &input[..len], &input[len..]))
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_SET: u8 = 0x31;
+const DER_OID: u8 = 0x06;
+// 2.5.4.3 (commonName)
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+// common name of the subject of a DER encoded X.509 certificate
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    let (DER_SEQUENCE, cert, _) = der_next(cert)? else { return None };
+    let (DER_SEQUENCE, mut fields, _) = der_next(cert)? else { return None };
+    // skip the optional version ([0]) then serial, signature algorithm, issuer and validity
+    if let Some((0xa0, _, rest)) = der_next(fields) {
+        fields = rest;
+    }
+    for _ in 0..4 {
+        fields = der_next(fields)?.2;
+    }
+    let (DER_SEQUENCE, subject, _) = der_next(fields)? else { return None };
+    find_common_name(subject)
+}
+
+fn find_common_name(mut name: &[u8]) -> Option<String> {
+    while let Some((DER_SET, rdn, rest)) = der_next(name) {
+        name = rest;
+        let mut attributes = rdn;
+        while let Some((DER_SEQUENCE, attribute, rest)) = der_next(attributes) {
+            attributes = rest;
+            let Some((DER_OID, oid, value)) = der_next(attribute) else { continue };
+            if oid == OID_COMMON_NAME {
+                let (_, value, _) = der_next(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,69 @@
+// latency histograms per route (log-scale buckets, about 19% precision)
+
+use serde::Serialize;
+use std::time::Duration;
+
+// 4 buckets per power of 2 up to 2^36 microseconds (about 19 hours)
+const BUCKETS_PER_OCTAVE: f64 = 4.0;
+const BUCKET_COUNT: usize = 36 * 4 + 1;
+
+#[derive(Debug,Clone)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max_us: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram { buckets: vec![0; BUCKET_COUNT], count: 0, max_us: 0 }
+    }
+}
+
+#[derive(Serialize,Debug,Clone)]
+pub struct LatencySummary {
+    count: u64,
+    // milliseconds
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl LatencyHistogram {
+    fn bucket(us: u64) -> usize {
+        if us <= 1 { return 0; }
+        ((us as f64).log2() * BUCKETS_PER_OCTAVE).ceil().min((BUCKET_COUNT - 1) as f64) as usize
+    }
+    fn bucket_upper_bound_us(bucket: usize) -> f64 {
+        2f64.powf(bucket as f64 / BUCKETS_PER_OCTAVE)
+    }
+    pub fn record(&mut self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[Self::bucket(us)] += 1;
+        self.count += 1;
+        self.max_us = self.max_us.max(us);
+    }
+    // upper bound of the bucket containing the given percentile (in milliseconds)
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        if self.count == 0 { return 0.0; }
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket,count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_upper_bound_us(bucket).min(self.max_us as f64).round() / 1000.0;
+            }
+        }
+        self.max_us as f64 / 1000.0
+    }
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            p50: self.percentile(50.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+            max: self.max_us as f64 / 1000.0,
+        }
+    }
+}
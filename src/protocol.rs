@@ -0,0 +1,21 @@
+// version of the JSON API spoken by the broker: a turn (GameTurn) is posted to
+// /game/:gameid and every read or write of a game answers with a GameReply envelope
+// (or the turn alone with envelope=false), see the README for the fields of both;
+// clients (ex: the web UI under general.internal) read GET /version to check that
+// they were built for the same protocol
+
+use axum::Json;
+use serde::Serialize;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize,Debug)]
+pub struct VersionReply {
+    version: &'static str,
+    protocol_version: &'static str,
+}
+
+// no auth required (clients check it before logging in)
+pub async fn version() -> Json<VersionReply> {
+    Json(VersionReply { version: env!("CARGO_PKG_VERSION"), protocol_version: VERSION })
+}
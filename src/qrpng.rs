@@ -0,0 +1,53 @@
+// QR codes (qrcode crate, error correction level M) rendered as PNG images: without its
+// image feature the crate has no PNG output, the image is written with miniz_oxide
+
+use crate::gzip::crc32;
+use qrcode::{Color, QrCode};
+
+// light modules around the symbol
+const QUIET_ZONE: usize = 4;
+
+// smallest image with one pixel per module
+pub fn min_pixels(code: &QrCode) -> usize {
+    code.width() + 2 * QUIET_ZONE
+}
+
+// 8-bit grayscale PNG of width and height `pixels` (None if smaller than one pixel per module)
+pub fn to_png(code: &QrCode, pixels: usize) -> Option<Vec<u8>> {
+    let width = code.width();
+    let scale = pixels / min_pixels(code);
+    if scale == 0 {
+        return None;
+    }
+    // the pixels left over are added to the quiet zone
+    let offset = (pixels - scale * min_pixels(code)) / 2 + QUIET_ZONE * scale;
+    let module = |pixel: usize| pixel.checked_sub(offset).map(|pixel| pixel / scale).filter(|&module| module < width);
+    let mut raw = Vec::with_capacity(pixels * (pixels + 1));
+    for y in 0..pixels {
+        // filter type None
+        raw.push(0);
+        raw.extend((0..pixels).map(|x| match (module(x), module(y)) {
+            (Some(col), Some(row)) if code[(col, row)] == Color::Dark => 0x00,
+            _ => 0xff,
+        }));
+    }
+    let mut header = Vec::with_capacity(13);
+    header.extend((pixels as u32).to_be_bytes());
+    header.extend((pixels as u32).to_be_bytes());
+    // bit depth 8, grayscale, deflate, no filter, no interlace
+    header.extend([8, 0, 0, 0, 0]);
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &miniz_oxide::deflate::compress_to_vec_zlib(&raw, 6));
+    png_chunk(&mut png, b"IEND", &[]);
+    Some(png)
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
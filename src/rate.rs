@@ -0,0 +1,55 @@
+// token bucket shared by the rate limiting middlewares
+// (included by the integration tests with #[path], so it must not depend on
+// anything else in the crate)
+
+use std::{sync::Mutex, time::Instant};
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: u32,
+    // tokens added per second
+    refill_rate: f64,
+    // the bucket is shared by reference (ex: stored in a map behind a read lock)
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    // starts full
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        TokenBucket {
+            capacity,
+            refill_rate,
+            state: Mutex::new(BucketState { tokens: capacity as f64, last_refill: Instant::now() }),
+        }
+    }
+
+    fn refill(&self, state: &mut BucketState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.refill_rate).min(self.capacity as f64);
+        state.last_refill = now;
+    }
+
+    // false (and nothing is consumed) if there are not enough tokens
+    pub fn consume(&self, n: u32) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut state);
+        if state.tokens < n as f64 {
+            return false;
+        }
+        state.tokens -= n as f64;
+        true
+    }
+
+    pub fn tokens_available(&self) -> f64 {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut state);
+        state.tokens
+    }
+}
@@ -0,0 +1,65 @@
+// rate limiting of the API: one token bucket per user (or per address without
+// authentication) and per kind of request (reads or writes)
+
+use std::{collections::HashMap, sync::Mutex};
+use crate::rate::TokenBucket;
+
+// hard cap on the number of buckets: past it, the full ones (same as new) are dropped,
+// then the least recently used ones down to 9/10 of the cap
+pub const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Limit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Default,Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default,Debug)]
+struct Buckets {
+    // keyed by (user or address, write), with the use count at the last check
+    map: HashMap<(String,bool),(Limit,TokenBucket,u64)>,
+    uses: u64,
+}
+
+impl Buckets {
+    fn evict(&mut self) {
+        self.map.retain(|_,(limit,bucket,_)| bucket.tokens_available() < limit.burst as f64);
+        if self.map.len() >= MAX_BUCKETS {
+            let mut used: Vec<u64> = self.map.values().map(|(_,_,used)| *used).collect();
+            let evicted = self.map.len() - MAX_BUCKETS * 9 / 10;
+            let (_, &mut oldest_kept, _) = used.select_nth_unstable(evicted);
+            self.map.retain(|_,(_,_,used)| *used >= oldest_kept);
+        }
+    }
+}
+
+impl RateLimiter {
+    // false if the request is over the limit
+    pub fn check(&self, key: &str, write: bool, limit: Limit) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (key.to_string(), write);
+        if buckets.map.len() >= MAX_BUCKETS && !buckets.map.contains_key(&key) {
+            buckets.evict();
+        }
+        buckets.uses += 1;
+        let uses = buckets.uses;
+        let new_bucket = || (limit, TokenBucket::new(limit.burst, limit.per_second), uses);
+        let entry = buckets.map.entry(key).or_insert_with(new_bucket);
+        // the limit of the user changed (users reloaded)
+        if entry.0 != limit {
+            *entry = new_bucket();
+        }
+        entry.2 = uses;
+        entry.1.consume(1)
+    }
+
+    // number of buckets dropped
+    pub fn clear(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner()).map.drain().count()
+    }
+}
@@ -0,0 +1,66 @@
+// ELO ratings of the players, updated when a game result is recorded
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub const INITIAL_ELO: f64 = 1500.0;
+pub const DEFAULT_K_FACTOR: f64 = 32.0;
+
+#[derive(Serialize,Debug,Clone)]
+pub struct UserRating {
+    pub username: String,
+    pub elo: f64,
+    pub games_played: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<RatingChange>,
+}
+
+#[derive(Serialize,Debug,Clone)]
+pub struct RatingChange {
+    // None for manual corrections by an admin
+    pub gameid: Option<String>,
+    pub elo_before: f64,
+    pub elo_after: f64,
+    pub at: u64,
+}
+
+impl UserRating {
+    pub fn new(username: &str) -> Self {
+        UserRating { username: username.to_string(), elo: INITIAL_ELO, games_played: 0, history: vec![] }
+    }
+    fn record(&mut self, gameid: Option<&str>, elo: f64) {
+        self.history.push(RatingChange {
+            gameid: gameid.map(String::from),
+            elo_before: self.elo,
+            elo_after: elo,
+            at: crate::unix_time_secs(),
+        });
+        self.elo = elo;
+    }
+    // leaderboard entry without the history
+    pub fn summary(&self) -> Self {
+        UserRating { history: vec![], ..self.clone() }
+    }
+    pub fn set_elo(&mut self, elo: f64) {
+        self.record(None, elo);
+    }
+}
+
+// probability that a player rated `rating` beats a player rated `opponent`
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// score is 1.0 if white won, 0.0 if black won and 0.5 for a draw
+pub fn update_ratings(ratings: &mut HashMap<String,UserRating>, gameid: &str, white: &str, black: &str, score: f64, k_factor: f64) {
+    let white_elo = ratings.entry(white.to_string()).or_insert_with(|| UserRating::new(white)).elo;
+    let black_elo = ratings.entry(black.to_string()).or_insert_with(|| UserRating::new(black)).elo;
+    let white_new = white_elo + k_factor * (score - expected_score(white_elo, black_elo));
+    let black_new = black_elo + k_factor * ((1.0 - score) - expected_score(black_elo, white_elo));
+    for (player,elo) in [(white,white_new),(black,black_new)] {
+        if let Some(rating) = ratings.get_mut(player) {
+            rating.record(Some(gameid), elo);
+            rating.games_played += 1;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// end-to-end smoke test: runs the broker on a random local port and
+// exercises the whole request lifecycle through real HTTP calls
+
+use hyper::{StatusCode, header};
+use nanoid::nanoid;
+use serde_json::json;
+use tokio::sync::oneshot;
+use tracing::{info, error};
+use std::net::{SocketAddr, TcpListener};
+use crate::{Config, ConfigUser, ConfigUserRole, GameTurn, build_app, compute_fingerprint, shared_state_from_config};
+use crate::testutil::{TestClient, TestReport};
+
+pub async fn run(config: &Config) -> bool {
+    let mut config = config.clone();
+    let admin = ConfigUser { name: format!("selftest-admin-{}",nanoid!(6)), role: ConfigUserRole::Admin, password: nanoid!(16), ..Default::default() };
+    let user = ConfigUser { name: format!("selftest-user-{}",nanoid!(6)), role: ConfigUserRole::User, password: nanoid!(16), ..Default::default() };
+    config.users.push(admin.clone());
+    config.users.push(user.clone());
+    config.general.unauthenticated = ConfigUserRole::Guest;
+    // the scripted moves must not depend on the configured game rules
+    config.game = Default::default();
+
+    let listener = match TcpListener::bind("127.0.0.1:0") {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("selftest could not bind a local port: {e}");
+            return false;
+        }
+    };
+    let addr = listener.local_addr().expect("bound listener has an address");
+    let app = build_app(&config, shared_state_from_config(&config));
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let server = match axum::Server::from_tcp(listener) {
+        Ok(builder) => builder
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async { shutdown_rx.await.ok(); }),
+        Err(e) => {
+            error!("selftest could not start the server: {e}");
+            return false;
+        }
+    };
+    let server_task = tokio::spawn(server);
+    info!("selftest running against http://{addr}");
+
+    let client = TestClient::new(&format!("http://{addr}"));
+    let report = run_checks(&client, &admin, &user).await;
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+
+    report.print();
+    report.success()
+}
+
+async fn run_checks(client: &TestClient, admin: &ConfigUser, user: &ConfigUser) -> TestReport {
+    let mut report = TestReport::default();
+    let anonymous = client.without_auth();
+    let wrong_password = client.with_auth(&user.name, "wrong password");
+    let as_user = client.with_auth(&user.name, &user.password);
+    let as_admin = client.with_auth(&admin.name, &admin.password);
+
+    report.check_status("generate game without auth is rejected", &anonymous.get("/game").await, StatusCode::UNAUTHORIZED);
+    report.check_status("generate game with wrong password is rejected", &wrong_password.get("/game").await, StatusCode::UNAUTHORIZED);
+
+    let generated = as_user.get("/game").await;
+    if !report.check_status("generate game", &generated, StatusCode::OK) {
+        return report;
+    }
+    let gameid = generated.map(|r| r.body.trim().to_string()).unwrap_or_default();
+    report.check("generated game id is not empty", !gameid.is_empty(), "empty game id");
+
+    let turn = json!({ "from": { "row": 1, "col": 2 }, "to": { "row": 3, "col": 4 }, "turn": 1 });
+    // the broker adds the fingerprint of the move (computed here as a client would)
+    let mut stored = turn.clone();
+    stored["fingerprint"] = json!(serde_json::from_value(turn.clone()).map(|turn: GameTurn| compute_fingerprint(&turn)).unwrap_or_default());
+    let path = format!("/game/{gameid}");
+    report.check_status("post turn with wrong password is rejected", &wrong_password.post(&path, turn.clone()).await, StatusCode::UNAUTHORIZED);
+    let posted = as_user.post(&path, turn.clone()).await;
+    report.check_status("post turn", &posted, StatusCode::OK);
+    if let Ok(posted) = posted {
+        let reply = posted.json();
+        let location = posted.headers.get(header::LOCATION).and_then(|location| location.to_str().ok()).unwrap_or_default().to_string();
+        report.check("post turn returns the game location", location == path, format!("Location: {location}"));
+        report.check("post turn echoes the turn", reply["success"] == true && reply["data"] == stored, posted.body);
+    }
+
+    let fetched = as_user.get(&path).await;
+    report.check_status("get turn", &fetched, StatusCode::OK);
+    if let Ok(fetched) = fetched {
+        let reply = fetched.json();
+        report.check("get turn returns the posted turn", reply["success"] == true && reply["data"] == stored, fetched.body);
+    }
+
+    let meta = json!({ "description": "selftest", "notes": "created by the broker selftest" });
+    report.check_status("update game metadata", &as_user.patch(&format!("{path}/meta"), meta.clone()).await, StatusCode::OK);
+    if let Ok(fetched) = as_user.get(&path).await {
+        let reply = fetched.json();
+        report.check("get turn returns the metadata", reply["meta"] == meta && reply["data"] == stored, fetched.body);
+    }
+
+    let missing = as_user.get(&format!("/game/{}",nanoid!(12))).await;
+    report.check_status("get nonexistent game", &missing, StatusCode::OK);
+    if let Ok(missing) = missing {
+        report.check("nonexistent game has no data", missing.json()["data"].is_null(), missing.body);
+    }
+
+    report.check_status("admin state is protected", &as_user.get("/admin/state").await, StatusCode::UNAUTHORIZED);
+    report.check_status("admin clear is protected", &as_user.delete("/admin/clear").await, StatusCode::UNAUTHORIZED);
+    report.check_status("admin state", &as_admin.get("/admin/state").await, StatusCode::OK);
+
+    report.check_status("delete game", &as_user.delete(&path).await, StatusCode::OK);
+    if let Ok(deleted) = as_user.get(&path).await {
+        report.check("deleted game has no data", deleted.json()["data"].is_null(), deleted.body);
+    }
+
+    report.check_status("post turn after delete", &as_user.post(&path, turn).await, StatusCode::OK);
+    report.check_status("admin clear", &as_admin.delete("/admin/clear").await, StatusCode::OK);
+    if let Ok(cleared) = as_user.get(&path).await {
+        report.check("cleared game has no data", cleared.json()["data"].is_null(), cleared.body);
+    }
+
+    report
+}
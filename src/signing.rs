@@ -0,0 +1,82 @@
+// signature of the game exports (general.signing_key): the signed message is the SHA-256
+// digest of the exported JSON, signed with Ed25519 or RSA PKCS#1 v1.5 (SHA-256) depending
+// on the PKCS#8 private key (checked by broker-cli verify, see verify.rs)
+
+use ring::{digest, rand::SystemRandom, signature::{self, Ed25519KeyPair, KeyPair, RsaKeyPair}};
+use std::path::Path;
+use crate::der::{der, pem, DER_BIT_STRING, DER_NULL, DER_OID, DER_SEQUENCE};
+
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+enum SigningKey {
+    Ed25519(Ed25519KeyPair),
+    Rsa(RsaKeyPair),
+}
+
+pub struct ExportSigner {
+    key: SigningKey,
+    public_key_pem: String,
+}
+
+impl std::fmt::Debug for ExportSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExportSigner").field("algorithm", &self.algorithm()).finish_non_exhaustive()
+    }
+}
+
+impl ExportSigner {
+    // PEM file with a PKCS#8 (PRIVATE KEY) or PKCS#1 (RSA PRIVATE KEY) key
+    pub fn load(path: &Path) -> Result<Self,String> {
+        let pem = std::fs::read(path).map_err(|e| format!("cannot read signing key {path:?}: {e}"))?;
+        let item = rustls_pemfile::read_one(&mut pem.as_slice())
+            .map_err(|e| format!("invalid signing key {path:?}: {e}"))?;
+        let key = match item {
+            Some(rustls_pemfile::Item::PKCS8Key(der)) => Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der).map(SigningKey::Ed25519)
+                .or_else(|_| RsaKeyPair::from_pkcs8(&der).map(SigningKey::Rsa))
+                .map_err(|e| format!("signing key {path:?} is neither Ed25519 nor RSA: {e}"))?,
+            Some(rustls_pemfile::Item::RSAKey(der)) => RsaKeyPair::from_der(&der).map(SigningKey::Rsa)
+                .map_err(|e| format!("invalid RSA signing key {path:?}: {e}"))?,
+            _ => return Err(format!("no PKCS#8 or RSA private key in {path:?}")),
+        };
+        Ok(Self::from_key(key))
+    }
+
+    fn from_key(key: SigningKey) -> Self {
+        let spki = match &key {
+            SigningKey::Ed25519(key) => der(DER_SEQUENCE, &[
+                der(DER_SEQUENCE, &der(DER_OID, OID_ED25519)),
+                der(DER_BIT_STRING, &[&[0], key.public_key().as_ref()].concat()),
+            ].concat()),
+            SigningKey::Rsa(key) => der(DER_SEQUENCE, &[
+                der(DER_SEQUENCE, &[der(DER_OID, OID_RSA_ENCRYPTION), der(DER_NULL, &[])].concat()),
+                der(DER_BIT_STRING, &[&[0], key.public_key().as_ref()].concat()),
+            ].concat()),
+        };
+        ExportSigner { key, public_key_pem: pem("PUBLIC KEY", &spki) }
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        match self.key {
+            SigningKey::Ed25519(_) => "ed25519",
+            SigningKey::Rsa(_) => "rsa-pkcs1-sha256",
+        }
+    }
+
+    pub fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    pub fn sign(&self, export: &[u8]) -> Result<Vec<u8>,String> {
+        let hash = digest::digest(&digest::SHA256, export);
+        match &self.key {
+            SigningKey::Ed25519(key) => Ok(key.sign(hash.as_ref()).as_ref().to_vec()),
+            SigningKey::Rsa(key) => {
+                let mut sig = vec![0; key.public_modulus_len()];
+                key.sign(&signature::RSA_PKCS1_SHA256, &SystemRandom::new(), hash.as_ref(), &mut sig)
+                    .map_err(|_| String::from("RSA signature failed"))?;
+                Ok(sig)
+            },
+        }
+    }
+}
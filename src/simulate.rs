@@ -0,0 +1,64 @@
+// latency and failure injection in the API requests so that AI client developers
+// can test their timeouts and retries (only built with the simulate feature)
+
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response}};
+use ring::rand::{self, SystemRandom};
+use std::{sync::Arc, time::Duration};
+use crate::{ConfigLatency, ConfigLatencyDistribution};
+
+pub struct Simulator {
+    latency: ConfigLatency,
+    rng: SystemRandom,
+}
+
+impl Simulator {
+    pub fn new(latency: ConfigLatency) -> Self {
+        Simulator { latency, rng: SystemRandom::new() }
+    }
+
+    // uniform in [0,1)
+    fn random(&self) -> f64 {
+        let bytes: [u8; 8] = rand::generate(&self.rng).map(|r| r.expose()).unwrap_or_default();
+        (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn delay(&self) -> Duration {
+        let (min, max) = (self.latency.min_ms as f64, self.latency.max_ms as f64);
+        let ms = match self.latency.distribution {
+            ConfigLatencyDistribution::Uniform => min + (max - min) * self.random(),
+            ConfigLatencyDistribution::Normal => {
+                // Box-Muller transform
+                let z = (-2.0 * (1.0 - self.random()).ln()).sqrt() * (2.0 * std::f64::consts::PI * self.random()).cos();
+                ((min + max) / 2.0 + z * (max - min) / 6.0).clamp(min, max)
+            },
+        };
+        Duration::from_millis(ms.round() as u64)
+    }
+
+    pub fn fails(&self) -> bool {
+        self.random() < self.latency.failure_rate
+    }
+}
+
+// delays (and sometimes fails) every request except the admin routes
+pub async fn inject<B>(
+    State(simulator): State<Arc<Simulator>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if request.uri().path().starts_with("/admin") {
+        return next.run(request).await;
+    }
+    let delay = simulator.delay();
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    if simulator.fails() {
+        return (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, "1")], "simulated failure\n").into_response();
+    }
+    next.run(request).await
+}
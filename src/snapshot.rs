@@ -0,0 +1,216 @@
+// periodic snapshot of the game data to disk (crash recovery)
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn, error};
+use tokio::sync::watch;
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+use crate::{Annotation, GameData, GameEntry, GameMeta, GameResult, GameTurn, TurnLimit, SharedState, unix_time_secs};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize,Deserialize,Debug)]
+struct Snapshot {
+    version: u32,
+    #[serde(default)]
+    snapshot_at: String,
+    #[serde(default)]
+    games: HashMap<String,SnapshotGame>,
+}
+
+// only the version is read first so that a future format does not fail to parse
+#[derive(Deserialize,Debug)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+struct SnapshotGame {
+    turn: Option<GameTurn>,
+    history: Vec<GameTurn>,
+    meta: GameMeta,
+    creator: Option<String>,
+    result: Option<GameResult>,
+    // unix timestamps (seconds)
+    created_at: u64,
+    updated_at: Option<u64>,
+    reserved: bool,
+    max_turns: Option<u16>,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    // the timer of a pending turn is not restored
+    #[serde(default)]
+    turn_limit: Option<TurnLimit>,
+    #[serde(default)]
+    forfeit_at: Option<u16>,
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    posted_by: Option<String>,
+    #[serde(default)]
+    players: Vec<String>,
+    #[serde(default)]
+    created_ttl_secs: Option<u64>,
+}
+
+fn to_unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn from_unix_secs(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+impl From<&GameEntry> for SnapshotGame {
+    fn from(entry: &GameEntry) -> Self {
+        SnapshotGame {
+            turn: entry.turn,
+            history: entry.history.clone(),
+            meta: entry.meta.clone(),
+            creator: entry.creator.clone(),
+            result: entry.result.clone(),
+            created_at: to_unix_secs(entry.created_at),
+            updated_at: entry.turn.and_then(|turn| turn.updated).map(to_unix_secs),
+            reserved: entry.reserved,
+            max_turns: entry.max_turns,
+            annotations: entry.annotations.clone(),
+            turn_limit: entry.turn_limit,
+            forfeit_at: entry.forfeit_at,
+            version: entry.version,
+            pinned: entry.pinned,
+            host: entry.host.clone(),
+            posted_by: entry.posted_by.clone(),
+            players: entry.players.clone(),
+            created_ttl_secs: entry.created_ttl_secs,
+        }
+    }
+}
+
+impl From<SnapshotGame> for GameEntry {
+    fn from(game: SnapshotGame) -> Self {
+        let updated = game.updated_at.map(from_unix_secs);
+        let mut turn = game.turn;
+        if let Some(turn) = turn.as_mut() {
+            turn.updated = updated;
+        }
+        let last_write = updated.unwrap_or(from_unix_secs(game.created_at));
+        GameEntry {
+            turn,
+            history: game.history,
+            meta: game.meta,
+            creator: game.creator,
+            result: game.result,
+            created_at: from_unix_secs(game.created_at),
+            reserved: game.reserved,
+            max_turns: game.max_turns,
+            annotations: game.annotations,
+            turn_limit: game.turn_limit,
+            forfeit_at: game.forfeit_at,
+            turn_timer: None,
+            version: game.version,
+            last_write,
+            version_tx: Arc::new(watch::channel(game.version).0),
+            pinned: game.pinned,
+            host: game.host,
+            posted_by: game.posted_by,
+            players: game.players,
+            last_body: None,
+            reply_cache: Default::default(),
+            created_ttl_secs: game.created_ttl_secs,
+        }
+    }
+}
+
+// UTC date and time (YYYY-MM-DDTHH:MM:SSZ) from a unix timestamp
+pub fn iso8601(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",year,month,day,time / 3600,time % 3600 / 60,time % 60)
+}
+
+// JSON of all the games in the format of the state file (also served by /admin/export) and number of games
+pub async fn to_json(state: &SharedState) -> Result<(Vec<u8>,usize),String> {
+    let snapshot = {
+        let dict = state.snapshot().await;
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            snapshot_at: iso8601(unix_time_secs()),
+            games: dict.iter().map(|(gameid,entry)| (gameid.clone(), SnapshotGame::from(entry))).collect(),
+        }
+    };
+    let json = serde_json::to_vec(&snapshot).map_err(|e| format!("cannot serialize snapshot: {e}"))?;
+    Ok((json, snapshot.games.len()))
+}
+
+// writes to a temporary file first and renames it so that the state file is never partially written
+pub async fn write(state: &SharedState, path: &Path) -> Result<usize,String> {
+    let (json, count) = to_json(state).await?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    tokio::fs::write(&tmp_path, json).await.map_err(|e| format!("cannot write {:?}: {e}",tmp_path))?;
+    tokio::fs::rename(&tmp_path, path).await.map_err(|e| format!("cannot rename {:?} to {:?}: {e}",tmp_path,path))?;
+    Ok(count)
+}
+
+// None if there is no usable snapshot (a broken or incompatible snapshot is not fatal)
+pub fn load(path: &Path) -> Option<GameData> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("cannot read snapshot {:?}: {e}",path);
+            return None;
+        }
+    };
+    match serde_json::from_str::<SnapshotVersion>(&content) {
+        Ok(SnapshotVersion { version: SNAPSHOT_VERSION }) => (),
+        Ok(SnapshotVersion { version }) => {
+            warn!("snapshot {:?} has version {version} (expected {SNAPSHOT_VERSION}), not loading it",path);
+            return None;
+        },
+        Err(e) => {
+            warn!("snapshot {:?} is not valid: {e}",path);
+            return None;
+        }
+    }
+    match serde_json::from_str::<Snapshot>(&content) {
+        Ok(snapshot) => {
+            info!("loaded {} games from snapshot {:?} taken at {}",snapshot.games.len(),path,snapshot.snapshot_at);
+            Some(snapshot.games.into_iter().map(|(gameid,game)| (gameid, GameEntry::from(game))).collect())
+        },
+        Err(e) => {
+            warn!("snapshot {:?} is not valid: {e}",path);
+            None
+        }
+    }
+}
+
+pub async fn snapshotter(path: PathBuf, interval_secs: u64, state: SharedState) {
+    let interval = Duration::from_secs(interval_secs.max(1));
+    let mut ticker = tokio::time::interval(interval);
+    // a slow write must not cause a burst of snapshots
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match tokio::time::timeout(interval, write(&state, &path)).await {
+            Ok(Ok(count)) => info!("snapshot of {count} games written to {:?}",path),
+            Ok(Err(e)) => warn!("snapshot failed: {e}"),
+            Err(_) => error!("snapshot to {:?} took longer than {interval_secs}s, skipped",path),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+// TLS certificate selection by SNI hostname so that several tournament domains
+// can point to the same broker, with a fallback certificate (tls.cert and tls.key)
+// for other names and for clients that do not send SNI
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{ServerConfig, server::{ClientHello, ResolvesServerCert}, sign::{self, CertifiedKey}};
+use std::{collections::{HashMap, HashSet}, fmt, path::{Path, PathBuf}, sync::{Arc, RwLock}};
+use crate::{ConfigTLSCert, certcheck, mtls::{read_certs, read_key}};
+
+// name of the fallback certificate in reload replies
+pub const FALLBACK_NAME: &str = "default";
+
+struct SniEntry {
+    cert: PathBuf,
+    key: PathBuf,
+    certified_key: Arc<CertifiedKey>,
+}
+
+impl SniEntry {
+    fn load(cert: &Path, key: &Path) -> Result<Self,String> {
+        certcheck::check_cert_and_key(cert, key)?;
+        let certs = read_certs(cert).map_err(|e| e.to_string())?;
+        let private_key = read_key(key).map_err(|e| e.to_string())?;
+        let signing_key = sign::any_supported_type(&private_key)
+            .map_err(|_| format!("private key format of {:?} not supported",key))?;
+        Ok(SniEntry {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            certified_key: Arc::new(CertifiedKey::new(certs, signing_key)),
+        })
+    }
+
+    // the files are read again (the paths cannot be changed without a restart)
+    fn reloaded(&self) -> Result<Self,String> {
+        SniEntry::load(&self.cert, &self.key)
+    }
+}
+
+pub struct SniResolver {
+    fallback: RwLock<SniEntry>,
+    // keyed by lowercase hostname
+    hosts: RwLock<HashMap<String,SniEntry>>,
+}
+
+// sni names of the [[tls.certs]] entries: distinct, not empty and not FALLBACK_NAME (which
+// names the fallback certificate in /admin/tls/reload)
+pub fn check_names(certs: &[ConfigTLSCert]) -> Result<(),String> {
+    let mut names = HashSet::new();
+    for cert in certs {
+        let sni = cert.sni.to_ascii_lowercase();
+        let problem = match sni.as_str() {
+            "" => "empty",
+            FALLBACK_NAME => "reserved for the fallback certificate",
+            _ if !names.insert(sni.clone()) => "repeated",
+            _ => continue,
+        };
+        return Err(format!("tls.certs entries need distinct sni names ({:?} is {problem})",cert.sni));
+    }
+    Ok(())
+}
+
+impl SniResolver {
+    pub fn new(fallback_cert: &Path, fallback_key: &Path, certs: &[ConfigTLSCert]) -> Result<Self,String> {
+        check_names(certs)?;
+        let fallback = SniEntry::load(fallback_cert, fallback_key)?;
+        let mut hosts = HashMap::new();
+        for cert in certs {
+            hosts.insert(cert.sni.to_ascii_lowercase(), SniEntry::load(&PathBuf::from(&cert.cert), &PathBuf::from(&cert.key))?);
+        }
+        Ok(SniResolver { fallback: RwLock::new(fallback), hosts: RwLock::new(hosts) })
+    }
+
+    pub fn has_host(&self, sni: &str) -> bool {
+        self.hosts.read().is_ok_and(|hosts| hosts.contains_key(&sni.to_ascii_lowercase()))
+    }
+
+    // reloads one SNI entry (or the fallback with FALLBACK_NAME), or everything when sni is None
+    // nothing is replaced if one of the certificates cannot be loaded
+    pub fn reload(&self, sni: Option<&str>) -> Result<Vec<String>,String> {
+        let poisoned = || String::from("certificate store is unavailable");
+        match sni.map(|sni| sni.to_ascii_lowercase()) {
+            Some(sni) if sni == FALLBACK_NAME => {
+                let entry = self.fallback.read().map_err(|_| poisoned())?.reloaded()?;
+                *self.fallback.write().map_err(|_| poisoned())? = entry;
+                Ok(vec![sni])
+            },
+            Some(sni) => {
+                let entry = self.hosts.read().map_err(|_| poisoned())?
+                    .get(&sni).ok_or_else(|| format!("no certificate for {sni:?}"))?
+                    .reloaded()?;
+                self.hosts.write().map_err(|_| poisoned())?.insert(sni.clone(), entry);
+                Ok(vec![sni])
+            },
+            None => {
+                let fallback = self.fallback.read().map_err(|_| poisoned())?.reloaded()?;
+                let hosts = self.hosts.read().map_err(|_| poisoned())?.iter()
+                    .map(|(sni,entry)| entry.reloaded().map(|entry| (sni.clone(), entry)))
+                    .collect::<Result<HashMap<_,_>,_>>()?;
+                let mut names: Vec<String> = hosts.keys().cloned().collect();
+                names.sort();
+                names.insert(0, String::from(FALLBACK_NAME));
+                *self.fallback.write().map_err(|_| poisoned())? = fallback;
+                *self.hosts.write().map_err(|_| poisoned())? = hosts;
+                Ok(names)
+            },
+        }
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        if let Some(sni) = client_hello.server_name() {
+            if let Some(entry) = self.hosts.read().ok()?.get(&sni.to_ascii_lowercase()) {
+                return Some(entry.certified_key.clone());
+            }
+        }
+        Some(self.fallback.read().ok()?.certified_key.clone())
+    }
+}
+
+impl fmt::Debug for SniResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hosts: Vec<String> = self.hosts.read().map(|hosts| hosts.keys().cloned().collect()).unwrap_or_default();
+        f.debug_struct("SniResolver").field("hosts", &hosts).finish()
+    }
+}
+
+// server config without client certificates (see mtls::rustls_config otherwise)
+pub fn rustls_config(resolver: Arc<SniResolver>) -> RustlsConfig {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    RustlsConfig::from_config(Arc::new(config))
+}
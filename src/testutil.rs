@@ -0,0 +1,128 @@
+// HTTP helpers shared by the selftest subcommand, the integration tests and
+// broker-cli (which include this file directly with #[path]), so it must not
+// depend on anything else in the crate.
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hyper::{Body, Client, HeaderMap, Method, Request, StatusCode, client::HttpConnector, header};
+use std::time::Duration;
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+#[derive(Clone)]
+pub struct TestClient {
+    client: Client<HttpConnector>,
+    base_url: String,
+    auth: Option<(String,String)>,
+}
+
+impl TestClient {
+    pub fn new(base_url: &str) -> Self {
+        TestClient {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth: None,
+        }
+    }
+
+    pub fn with_auth(&self, username: &str, password: &str) -> Self {
+        TestClient {
+            auth: Some((username.to_string(), password.to_string())),
+            ..self.clone()
+        }
+    }
+
+    pub fn without_auth(&self) -> Self {
+        TestClient { auth: None, ..self.clone() }
+    }
+
+    pub async fn request(&self, method: Method, path: &str, body: Option<serde_json::Value>) -> Result<TestResponse,String> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(format!("{}{}",self.base_url,path));
+        if let Some((username,password)) = self.auth.as_ref() {
+            let credentials = BASE64.encode(format!("{}:{}",username,password));
+            builder = builder.header(header::AUTHORIZATION, format!("Basic {}",credentials));
+        }
+        let request = match body {
+            Some(value) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(value.to_string())),
+            None => builder.body(Body::empty()),
+        }.map_err(|e| e.to_string())?;
+        let response = tokio::time::timeout(Duration::from_secs(10), self.client.request(request)).await
+            .map_err(|_| String::from("request timed out"))?
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.map_err(|e| e.to_string())?;
+        Ok(TestResponse { status, headers, body: String::from_utf8_lossy(&bytes).into_owned() })
+    }
+
+    pub async fn get(&self, path: &str) -> Result<TestResponse,String> {
+        self.request(Method::GET, path, None).await
+    }
+
+    pub async fn post(&self, path: &str, body: serde_json::Value) -> Result<TestResponse,String> {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    pub async fn patch(&self, path: &str, body: serde_json::Value) -> Result<TestResponse,String> {
+        self.request(Method::PATCH, path, Some(body)).await
+    }
+
+    pub async fn delete(&self, path: &str) -> Result<TestResponse,String> {
+        self.request(Method::DELETE, path, None).await
+    }
+}
+
+#[derive(Default)]
+pub struct TestReport {
+    results: Vec<(String,bool,String)>,
+}
+
+impl TestReport {
+    pub fn check(&mut self, name: &str, passed: bool, detail: impl Into<String>) -> bool {
+        self.results.push((name.to_string(), passed, detail.into()));
+        passed
+    }
+
+    pub fn check_status(&mut self, name: &str, response: &Result<TestResponse,String>, expected: StatusCode) -> bool {
+        match response {
+            Ok(r) => self.check(name, r.status == expected, format!("expected {expected}, got {}",r.status)),
+            Err(e) => self.check(name, false, format!("request failed: {e}")),
+        }
+    }
+
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|(_,passed,_)| *passed).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn success(&self) -> bool {
+        self.failed() == 0
+    }
+
+    pub fn print(&self) {
+        for (name,passed,detail) in self.results.iter() {
+            if *passed {
+                println!("[PASS] {name}");
+            } else {
+                println!("[FAIL] {name}: {detail}");
+            }
+        }
+        println!("{} passed, {} failed", self.passed(), self.failed());
+    }
+}
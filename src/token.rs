@@ -0,0 +1,79 @@
+// short-lived signed tokens (HS256 JWT): game tokens returned by game_generate
+// so that stateless clients can access a single game without sending
+// credentials, and single-use invites for players without an account
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL};
+use ring::hmac;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+pub const DEFAULT_TTL_SECS: u64 = 60 * 60;
+
+// only alg = HS256 is ever issued or accepted
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+pub trait Claims: Serialize + DeserializeOwned {
+    // unix timestamp (seconds)
+    fn exp(&self) -> u64;
+}
+
+// unknown fields are denied so that an invite cannot be used as a game token
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GameClaims {
+    pub sub: String,
+    pub game: String,
+    pub exp: u64,
+}
+
+impl GameClaims {
+    pub fn new(username: &str, gameid: &str, ttl_secs: u64) -> Self {
+        GameClaims {
+            sub: username.to_string(),
+            game: gameid.to_string(),
+            exp: crate::unix_time_secs().saturating_add(ttl_secs),
+        }
+    }
+}
+
+impl Claims for GameClaims {
+    fn exp(&self) -> u64 { self.exp }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct InviteClaims {
+    // admin who created the invite (the game token obtained by joining acts on their behalf)
+    pub sub: String,
+    pub gameid: String,
+    pub role: String,
+    pub exp: u64,
+}
+
+impl Claims for InviteClaims {
+    fn exp(&self) -> u64 { self.exp }
+}
+
+pub fn issue<C: Claims>(secret: &[u8], claims: &C) -> String {
+    let payload = serde_json::to_vec(claims).expect("claims are serializable");
+    let signed = format!("{}.{}",BASE64URL.encode(HEADER),BASE64URL.encode(payload));
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret), signed.as_bytes());
+    format!("{}.{}",signed,BASE64URL.encode(tag.as_ref()))
+}
+
+// checks the signature and the expiry (the scope is checked by the caller)
+pub fn verify<C: Claims>(secret: &[u8], token: &str) -> Result<C,String> {
+    let (signed, signature) = token.rsplit_once('.').ok_or("malformed token")?;
+    let (header, payload) = signed.split_once('.').ok_or("malformed token")?;
+    if BASE64URL.decode(header).map_err(|_| "malformed token header")? != HEADER.as_bytes() {
+        return Err(String::from("unsupported token header"));
+    }
+    let signature = BASE64URL.decode(signature).map_err(|_| "malformed token signature")?;
+    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, secret), signed.as_bytes(), &signature)
+        .map_err(|_| "invalid token signature")?;
+    let payload = BASE64URL.decode(payload).map_err(|_| "malformed token payload")?;
+    let claims: C = serde_json::from_slice(&payload).map_err(|e| format!("invalid token claims: {e}"))?;
+    if claims.exp() <= crate::unix_time_secs() {
+        return Err(String::from("token has expired"));
+    }
+    Ok(claims)
+}
@@ -0,0 +1,116 @@
+// external validation of the posted moves ([game] validator): the turn is sent as JSON
+// to an HTTP endpoint (which answers {"valid":true} or {"valid":false,"reason":"..."})
+// or to the stdin of a command (exit code 0 when the move is valid) before it is stored
+
+use hyper::{Body, Client, Method, Request, client::HttpConnector, header};
+use serde::Deserialize;
+use tokio::{io::AsyncWriteExt, process::Command};
+use std::{process::Stdio, time::Duration};
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 2;
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ConfigValidator {
+    Http { url: String },
+    // run directly (not through a shell)
+    Process {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug,PartialEq)]
+pub enum Rejection {
+    // the validator refused the move
+    Invalid(String),
+    // the validator could not be reached or did not answer in time
+    Unavailable(String),
+}
+
+#[derive(Deserialize,Debug)]
+struct HttpVerdict {
+    valid: bool,
+    reason: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Validator {
+    config: ConfigValidator,
+    timeout: Duration,
+    client: Client<HttpConnector>,
+}
+
+impl Validator {
+    pub fn new(config: ConfigValidator, timeout_secs: Option<u64>) -> Result<Self,String> {
+        if let ConfigValidator::Http { url } = &config {
+            if !url.starts_with("http://") {
+                return Err(format!("game.validator: {url:?} is not an http:// URL"));
+            }
+        }
+        let timeout = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        if timeout.is_zero() {
+            return Err(String::from("game.validator_timeout_secs must be positive"));
+        }
+        Ok(Validator { config, timeout, client: Client::new() })
+    }
+
+    // turn is the JSON of the posted GameTurn
+    pub async fn validate(&self, gameid: &str, turn: &str) -> Result<(),Rejection> {
+        let verdict = match &self.config {
+            ConfigValidator::Http { url } => tokio::time::timeout(self.timeout, self.call_http(url, gameid, turn)).await,
+            ConfigValidator::Process { command, args } => tokio::time::timeout(self.timeout, run_process(command, args, gameid, turn)).await,
+        };
+        verdict.unwrap_or_else(|_| Err(Rejection::Unavailable(format!("no answer within {}s",self.timeout.as_secs()))))
+    }
+
+    async fn call_http(&self, url: &str, gameid: &str, turn: &str) -> Result<(),Rejection> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-game-id", gameid)
+            .body(Body::from(turn.to_string()))
+            .map_err(|e| Rejection::Unavailable(e.to_string()))?;
+        let response = self.client.request(request).await.map_err(|e| Rejection::Unavailable(e.to_string()))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.map_err(|e| Rejection::Unavailable(e.to_string()))?;
+        if !status.is_success() {
+            return Err(Rejection::Unavailable(format!("status {status}")));
+        }
+        match serde_json::from_slice::<HttpVerdict>(&body) {
+            Ok(HttpVerdict { valid: true, .. }) => Ok(()),
+            Ok(HttpVerdict { valid: false, reason }) => Err(Rejection::Invalid(reason.unwrap_or_else(|| String::from("rejected by the validator")))),
+            Err(e) => Err(Rejection::Unavailable(format!("invalid reply: {e}"))),
+        }
+    }
+}
+
+// the first line of the output (stdout, or stderr if empty) is the reason of a rejection
+async fn run_process(command: &str, args: &[String], gameid: &str, turn: &str) -> Result<(),Rejection> {
+    let mut child = Command::new(command).args(args)
+        .env("GAME_ID", gameid)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Rejection::Unavailable(format!("cannot run {command}: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a validator that exits without reading its input closes the pipe
+        let _ = stdin.write_all(turn.as_bytes()).await;
+    }
+    let output = child.wait_with_output().await.map_err(|e| Rejection::Unavailable(e.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let Some(code) = output.status.code() else {
+        return Err(Rejection::Unavailable(format!("{command} was killed")));
+    };
+    let reason = [&output.stdout, &output.stderr].into_iter()
+        .filter_map(|text| String::from_utf8_lossy(text).lines().map(str::trim).find(|line| !line.is_empty()).map(String::from))
+        .next()
+        .unwrap_or_else(|| format!("rejected by the validator (exit code {code})"));
+    Err(Rejection::Invalid(reason))
+}
@@ -0,0 +1,196 @@
+// passwords of [[users]] read from HashiCorp Vault (vault feature): vault_path names a KV
+// secret (read as version 2 through <mount>/data/ first, then as version 1) and vault_key the
+// field holding the password; VAULT_ADDR and VAULT_TOKEN (and VAULT_CACERT for https) come
+// from the environment, and a token with a TTL is renewed in the background so that the
+// users can still be reloaded later
+
+use hyper::{Body, Method, Request, StatusCode, Uri, header};
+use serde_json::{json, Value};
+use tokio::{net::TcpStream, time::sleep};
+use tokio_rustls::{TlsConnector, rustls::{ClientConfig, RootCertStore, ServerName}};
+use tracing::{info, warn, error};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use crate::{ConfigUser, acme::{DEFAULT_CA_FILE, send_request}, mtls::read_certs};
+
+pub const DEFAULT_KEY: &str = "password";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MIN_RENEW_SECS: u64 = 5;
+const RENEW_RETRY_SECS: u64 = 30;
+
+pub struct VaultClient {
+    addr: String,
+    token: String,
+    // None when VAULT_ADDR is plain http (dev servers)
+    tls: Option<TlsConnector>,
+}
+
+impl VaultClient {
+    pub fn from_env() -> Result<Self,String> {
+        let addr = std::env::var("VAULT_ADDR").map_err(|_| String::from("VAULT_ADDR is not set"))?;
+        let token = std::env::var("VAULT_TOKEN").map_err(|_| String::from("VAULT_TOKEN is not set"))?;
+        Self::new(&addr, token)
+    }
+
+    pub fn new(addr: &str, token: String) -> Result<Self,String> {
+        let addr = addr.trim_end_matches('/').to_string();
+        let uri: Uri = addr.parse().map_err(|_| format!("VAULT_ADDR {addr:?} is not a valid URL"))?;
+        let tls = match uri.scheme_str() {
+            Some("https") => {
+                let ca_file = PathBuf::from(std::env::var("VAULT_CACERT").unwrap_or_else(|_| String::from(DEFAULT_CA_FILE)));
+                let certs: Vec<Vec<u8>> = read_certs(&ca_file).map_err(|e| e.to_string())?.into_iter().map(|cert| cert.0).collect();
+                let mut roots = RootCertStore::empty();
+                if roots.add_parsable_certificates(&certs).0 == 0 {
+                    return Err(format!("no CA certificate found in {:?} (VAULT_CACERT)",ca_file));
+                }
+                let config = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
+                Some(TlsConnector::from(Arc::new(config)))
+            },
+            Some("http") => None,
+            _ => return Err(format!("VAULT_ADDR {addr:?} is not an http or https URL")),
+        };
+        Ok(VaultClient { addr, token, tls })
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<Value>) -> Result<(StatusCode,Value),String> {
+        let url = format!("{}/v1/{}",self.addr,path);
+        let uri: Uri = url.parse().map_err(|_| format!("invalid URL {url:?}"))?;
+        let (Some(host), Some(authority)) = (uri.host(), uri.authority()) else {
+            return Err(format!("invalid URL {url:?}"));
+        };
+        let port = uri.port_u16().unwrap_or(if self.tls.is_some() { 443 } else { 80 });
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri.path_and_query().map_or("/", |path| path.as_str()))
+            .header(header::HOST, authority.as_str())
+            .header(header::USER_AGENT, concat!("ai_wargame_broker/", env!("CARGO_PKG_VERSION")))
+            .header("X-Vault-Token", &self.token);
+        let request = match body {
+            Some(body) => builder.header(header::CONTENT_TYPE, "application/json").body(Body::from(body.to_string())),
+            None => builder.body(Body::empty()),
+        }.map_err(|e| e.to_string())?;
+        let exchange = async {
+            let tcp = TcpStream::connect((host, port)).await.map_err(|e| e.to_string())?;
+            match self.tls.as_ref() {
+                None => send_request(tcp, request).await,
+                Some(tls) => {
+                    let server_name = ServerName::try_from(host).map_err(|e| e.to_string())?;
+                    send_request(tls.connect(server_name, tcp).await.map_err(|e| e.to_string())?, request).await
+                },
+            }
+        };
+        let (status, _, body) = tokio::time::timeout(REQUEST_TIMEOUT, exchange).await
+            .map_err(|_| format!("{url}: request timed out"))?
+            .map_err(|e| format!("{url}: {e}"))?;
+        Ok((status, serde_json::from_slice(&body).unwrap_or(Value::Null)))
+    }
+
+    // value of the field key in the secret at path
+    pub async fn read_secret(&self, path: &str, key: &str) -> Result<String,String> {
+        let path = path.trim_matches('/');
+        let (mut status, mut body) = match path.split_once('/') {
+            Some((mount, rest)) => self.send(Method::GET, &format!("{mount}/data/{rest}"), None).await?,
+            None => (StatusCode::NOT_FOUND, Value::Null),
+        };
+        if status == StatusCode::NOT_FOUND {
+            (status, body) = self.send(Method::GET, path, None).await?;
+        }
+        if !status.is_success() {
+            return Err(format!("Vault returned {status}{}",vault_errors(&body)));
+        }
+        let data = &body["data"];
+        match data["data"].get(key).or_else(|| data.get(key)) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(_) => Err(format!("field {key:?} of the secret is not a string")),
+            None => Err(format!("no field {key:?} in the secret")),
+        }
+    }
+
+    // Some(ttl) when the token expires and can be renewed
+    async fn renewable_ttl(&self) -> Result<Option<u64>,String> {
+        let (status, body) = self.send(Method::GET, "auth/token/lookup-self", None).await?;
+        if !status.is_success() {
+            return Err(format!("token lookup failed with {status}{}",vault_errors(&body)));
+        }
+        let ttl = body["data"]["ttl"].as_u64().unwrap_or(0);
+        let renewable = body["data"]["renewable"].as_bool().unwrap_or(false);
+        Ok((ttl > 0 && renewable).then_some(ttl))
+    }
+
+    // new TTL of the token
+    async fn renew(&self) -> Result<u64,String> {
+        let (status, body) = self.send(Method::POST, "auth/token/renew-self", Some(json!({}))).await?;
+        if !status.is_success() {
+            return Err(format!("token renewal failed with {status}{}",vault_errors(&body)));
+        }
+        Ok(body["auth"]["lease_duration"].as_u64().unwrap_or(0))
+    }
+}
+
+fn vault_errors(body: &Value) -> String {
+    match body["errors"].as_array() {
+        Some(errors) if !errors.is_empty() => {
+            let errors: Vec<&str> = errors.iter().filter_map(|e| e.as_str()).collect();
+            format!(" ({})",errors.join(", "))
+        },
+        _ => String::new(),
+    }
+}
+
+// fills the password of the users having a vault_path (no connection to Vault otherwise)
+pub async fn resolve_passwords(users: &mut [ConfigUser]) -> Result<(),String> {
+    if users.iter().all(|user| user.vault_path.is_none()) {
+        return Ok(());
+    }
+    let client = VaultClient::from_env().map_err(|e| format!("cannot read passwords from Vault: {e}"))?;
+    for user in users.iter_mut() {
+        let Some(path) = user.vault_path.as_deref() else { continue };
+        let key = user.vault_key.as_deref().unwrap_or(DEFAULT_KEY);
+        user.password = client.read_secret(path, key).await
+            .map_err(|e| format!("cannot read the password of user {} from Vault path {path:?}: {e}",user.name))?;
+        info!("password of user {} read from Vault path {path:?}",user.name);
+    }
+    Ok(())
+}
+
+// renews the token at 2/3 of its TTL for as long as the broker runs (nothing to do if the
+// token does not expire)
+pub async fn start_token_renewal() {
+    let client = match VaultClient::from_env() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("cannot renew the Vault token: {e}");
+            return;
+        },
+    };
+    let mut ttl = match client.renewable_ttl().await {
+        Ok(Some(ttl)) => ttl,
+        Ok(None) => {
+            info!("Vault token does not need to be renewed");
+            return;
+        },
+        Err(e) => {
+            warn!("cannot look up the Vault token, it will not be renewed: {e}");
+            return;
+        },
+    };
+    info!("Vault token will be renewed before its TTL of {ttl}s runs out");
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs((ttl * 2 / 3).max(MIN_RENEW_SECS))).await;
+            match client.renew().await {
+                Ok(0) => {
+                    info!("Vault token no longer expires");
+                    return;
+                },
+                Ok(new_ttl) => {
+                    info!("Vault token renewed for {new_ttl}s");
+                    ttl = new_ttl;
+                },
+                Err(e) => {
+                    error!("cannot renew the Vault token: {e}");
+                    ttl = RENEW_RETRY_SECS * 3 / 2;
+                },
+            }
+        }
+    });
+}
@@ -0,0 +1,59 @@
+// verification of the export signatures made by signing::ExportSigner, used by broker-cli
+// verify (the broker itself only signs, so it only includes this module for its tests)
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{digest, signature::{self, UnparsedPublicKey}};
+
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+// checks a signature of an export against the PEM public key served by /admin/export/pubkey
+pub fn verify(public_key_pem: &str, export: &[u8], sig: &[u8]) -> Result<(),String> {
+    let spki = pem_decode(public_key_pem, "PUBLIC KEY")?;
+    let invalid = || String::from("invalid public key");
+    let (algorithm, key) = der_read(DER_SEQUENCE, &spki).and_then(|(info, _)| {
+        let (algorithm, rest) = der_read(DER_SEQUENCE, info)?;
+        let (oid, _) = der_read(DER_OID, algorithm)?;
+        let (key, _) = der_read(DER_BIT_STRING, rest)?;
+        Some((oid, key.strip_prefix(&[0])?))
+    }).ok_or_else(invalid)?;
+    let algorithm: &dyn signature::VerificationAlgorithm = match algorithm {
+        OID_ED25519 => &signature::ED25519,
+        OID_RSA_ENCRYPTION => &signature::RSA_PKCS1_2048_8192_SHA256,
+        _ => return Err(String::from("unsupported public key algorithm")),
+    };
+    let hash = digest::digest(&digest::SHA256, export);
+    UnparsedPublicKey::new(algorithm, key).verify(hash.as_ref(), sig)
+        .map_err(|_| String::from("signature does not match"))
+}
+
+// (content, rest) of the element with this tag at the start of input
+fn der_read(tag: u8, input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&first, input) = input.split_first()?;
+    let (&len, mut input) = input.split_first()?;
+    if first != tag {
+        return None;
+    }
+    let len = match len {
+        0..=0x7f => len as usize,
+        0x81..=0x84 => {
+            let (bytes, rest) = input.split_at_checked((len & 0x7f) as usize)?;
+            input = rest;
+            bytes.iter().fold(0, |len, b| len << 8 | *b as usize)
+        },
+        _ => return None,
+    };
+    input.split_at_checked(len)
+}
+
+fn pem_decode(pem: &str, label: &str) -> Result<Vec<u8>,String> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let body = pem.split_once(&begin).and_then(|(_, rest)| rest.split_once(&end)).map(|(body, _)| body)
+        .ok_or_else(|| format!("no {label} in PEM"))?;
+    let body: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    STANDARD.decode(body).map_err(|e| format!("invalid PEM: {e}"))
+}
@@ -0,0 +1,89 @@
+// every route of the API must answer unauthenticated requests with 401 unless
+// it is public on purpose: the routes are read from build_app so that a new
+// route is checked without changing this test (and fails it until it checks
+// the role of the client or is added to PUBLIC_ROUTES)
+
+mod common;
+
+use common::BrokerFixture;
+use hyper::{Method, StatusCode};
+use serde_json::{json, Value};
+
+// (method, route, status for an unauthenticated client)
+const PUBLIC_ROUTES: &[(&str, &str, StatusCode)] = &[
+    ("GET", "/leaderboard", StatusCode::OK),
+    ("GET", "/robots.txt", StatusCode::OK),
+    ("GET", "/version", StatusCode::OK),
+    // 404 without a [security] section
+    ("GET", "/.well-known/security.txt", StatusCode::NOT_FOUND),
+    // 404 without general.enable_signing
+    ("GET", "/admin/export/pubkey", StatusCode::NOT_FOUND),
+];
+
+// routes of optional features, only checked when the feature is enabled
+const FEATURE_ROUTES: &[(&str, bool)] = &[
+    ("/game/:gameid/qr", cfg!(feature = "qrcode")),
+    ("/admin/asset-integrity", cfg!(feature = "internal")),
+];
+
+// (method, route) of every .route("...") in build_app (routes with a computed path are skipped)
+fn routes() -> Vec<(Method, String)> {
+    let source = include_str!("../src/main.rs");
+    let start = source.find("fn build_app(").expect("build_app not found in src/main.rs");
+    let end = start + source[start..].find("\n}\n").expect("end of build_app not found");
+    let mut routes = Vec::new();
+    for line in source[start..end].lines() {
+        let Some(position) = line.find(".route(\"") else { continue };
+        let Some((path, handlers)) = line[position + 8..].split_once('"') else { continue };
+        if FEATURE_ROUTES.iter().any(|(route, enabled)| *route == path && !enabled) {
+            continue;
+        }
+        for (name, method) in [("get(", Method::GET), ("post(", Method::POST), ("patch(", Method::PATCH), ("delete(", Method::DELETE)] {
+            if handlers.contains(name) {
+                routes.push((method, path.to_string()));
+            }
+        }
+    }
+    routes
+}
+
+// bodies accepted by the Json extractors (which run before the handlers check the role)
+fn body(method: &Method, route: &str) -> Option<Value> {
+    let coord = json!({ "row": 0, "col": 0 });
+    let body = match route {
+        "/game/:gameid" | "/admin/game/:gameid/turn" => json!({ "from": coord, "to": coord, "turn": 1 }),
+        "/game/:gameid/turn_limit" => json!({ "secs_per_turn": 30, "forfeit_move": { "from": coord, "to": coord } }),
+        "/game/:gameid/turns/:turn_num/annotation" => json!({ "text": "note" }),
+        "/game/:gameid/result" => json!({ "winner": "draw" }),
+        "/game/batch" => json!({ "count": 1 }),
+        "/admin/users/:name/rating" => json!({ "elo": 1500.0 }),
+        "/admin/reserve" => json!({ "ids": ["reserved"] }),
+        "/admin/games/archive" => json!({ "ids": ["archived"], "reason": "test" }),
+        "/admin/game/:gameid/rename" => json!({ "new_gameid": "renamed" }),
+        _ => json!({}),
+    };
+    (*method == Method::POST || *method == Method::PATCH).then_some(body)
+}
+
+#[tokio::test]
+async fn every_route_requires_authentication_unless_public() {
+    let broker = BrokerFixture::builder().with_reset().build().await;
+    let client = broker.client();
+    let routes = routes();
+    assert!(routes.len() > 40, "only {} routes found in build_app",routes.len());
+    let mut unprotected = Vec::new();
+    for (method, route) in routes {
+        let path = route.split('/')
+            .map(|part| if part.starts_with(':') { "1" } else { part })
+            .collect::<Vec<_>>()
+            .join("/");
+        let response = client.request(method.clone(), &path, body(&method, &route)).await.unwrap();
+        let expected = PUBLIC_ROUTES.iter()
+            .find(|(public_method, public_route, _)| *public_method == method.as_str() && *public_route == route)
+            .map_or(StatusCode::UNAUTHORIZED, |(_, _, status)| *status);
+        if response.status != expected {
+            unprotected.push(format!("{method} {route}: {} instead of {expected}",response.status));
+        }
+    }
+    assert!(unprotected.is_empty(), "routes without an auth check:\n{}",unprotected.join("\n"));
+}
@@ -0,0 +1,424 @@
+mod common;
+
+use common::{BrokerFixture, Role};
+use hyper::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn users_play_a_game_that_guests_cannot_create() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+
+    assert_eq!(broker.client().get("/game").await.unwrap().status, StatusCode::UNAUTHORIZED);
+    let generated = alice.get("/game").await.unwrap();
+    assert_eq!(generated.status, StatusCode::OK);
+    let gameid = generated.body.trim().to_string();
+
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    assert_eq!(alice.post(&format!("/game/{gameid}"), turn.clone()).await.unwrap().status, StatusCode::OK);
+    let mut stored = alice.get(&format!("/game/{gameid}")).await.unwrap().json()["data"].take();
+    let fingerprint = stored.as_object_mut().unwrap().remove("fingerprint").unwrap();
+    // set by the broker (see submission_time_is_set_by_the_broker)
+    stored.as_object_mut().unwrap().retain(|field, _| !field.starts_with("submitted_at_"));
+    assert_eq!(stored, turn);
+    // computed by the client from the turn it posted
+    let expected = ring::digest::digest(&ring::digest::SHA256, b"turn:1,from:(0,0),to:(1,0)");
+    assert_eq!(fingerprint, expected.as_ref().iter().map(|b| format!("{:02x}",b)).collect::<String>());
+}
+
+#[tokio::test]
+async fn admin_routes_require_the_admin_role() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .with_user("bob", "bob password", Role::User)
+        .with_expires(60)
+        .build().await;
+
+    assert_eq!(broker.client_as("bob").get("/admin/cleaner/status").await.unwrap().status, StatusCode::UNAUTHORIZED);
+    let status = broker.client_as("alice").get("/admin/cleaner/status").await.unwrap();
+    assert_eq!(status.status, StatusCode::OK);
+    assert_eq!(status.json()["interval_secs"], 6);
+}
+
+#[tokio::test]
+async fn max_turns_per_game_is_enforced() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .with_max_turns_per_game(2)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let path = format!("/game/{gameid}");
+
+    let turn = |turn: u16| json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": turn });
+    assert_eq!(alice.post(&path, turn(1)).await.unwrap().status, StatusCode::OK);
+    let version = alice.get(&path).await.unwrap().headers["x-game-version"].clone();
+    let rejected = alice.post(&path, turn(2)).await.unwrap();
+    assert_eq!(rejected.status, StatusCode::CONFLICT);
+    assert_eq!(rejected.json()["success"], false);
+    // the game is finished in a new version
+    let finished = alice.get(&path).await.unwrap();
+    assert_ne!(finished.headers["x-game-version"], version);
+    assert_eq!(finished.json()["result"]["reason"], "max_turns");
+}
+
+#[tokio::test]
+async fn broker_shuts_down_gracefully_on_sigterm() {
+    let mut broker = BrokerFixture::builder().build().await;
+    assert_eq!(broker.client().get("/version").await.unwrap().status, StatusCode::OK);
+    let status = broker.shutdown().expect("the broker did not exit");
+    // killed brokers have no exit code
+    assert_eq!(status.code(), Some(0), "{status}");
+    assert!(broker.client().get("/version").await.is_err());
+}
+
+#[tokio::test]
+async fn version_is_public_and_includes_the_protocol_version() {
+    let broker = BrokerFixture::builder().build().await;
+    let version = broker.client().get("/version").await.unwrap();
+    assert_eq!(version.status, StatusCode::OK);
+    assert_eq!(version.json()["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version.json()["protocol_version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn game_ids_in_the_wrong_case_find_the_game() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .with_game_id_case("lower")
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    assert_eq!(gameid, gameid.to_lowercase());
+
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    assert_eq!(alice.post(&format!("/game/{}", gameid.to_uppercase()), turn).await.unwrap().status, StatusCode::OK);
+    let game = alice.get(&format!("/game/{gameid}")).await.unwrap();
+    assert_eq!(game.json()["data"]["turn"], 1);
+    assert_eq!(alice.post("/game/MixedCase", json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 })).await.unwrap().status, StatusCode::OK);
+    assert_eq!(alice.get("/game/mixedcase").await.unwrap().json()["data"]["turn"], 1);
+}
+
+#[tokio::test]
+async fn json_is_indented_on_request_except_for_posted_turns() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    let posted = alice.post(&format!("/game/{gameid}?pretty=true"), turn.clone()).await.unwrap();
+    assert!(!posted.body.contains('\n'));
+    let compact = alice.get(&format!("/game/{gameid}")).await.unwrap();
+    assert!(!compact.body.contains('\n'));
+    let pretty = alice.get(&format!("/game/{gameid}?pretty=true")).await.unwrap();
+    assert!(pretty.body.starts_with("{\n  \"success\": true,"));
+    assert_eq!(pretty.json(), compact.json());
+}
+
+#[tokio::test]
+async fn pinned_games_are_listed_and_kept_by_age_based_deletions() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let pinned = alice.get("/game").await.unwrap().body.trim().to_string();
+    let unpinned = alice.get("/game").await.unwrap().body.trim().to_string();
+
+    assert_eq!(alice.post(&format!("/admin/game/{pinned}/pin"), json!({})).await.unwrap().json(), json!({ "gameid": pinned, "pinned": true }));
+    assert_eq!(alice.get("/admin/games?pinned=true").await.unwrap().json(), json!({ "ids": [pinned] }));
+
+    let deleted = alice.delete("/admin/games?older_than_secs=0&dry_run=true").await.unwrap().json();
+    assert_eq!(deleted["deleted_count"], 0);
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let deleted = alice.delete("/admin/games?older_than_secs=0").await.unwrap().json();
+    assert_eq!(deleted["deleted_count"], 1);
+    assert_eq!(alice.get("/admin/games").await.unwrap().json(), json!({ "ids": [pinned] }));
+    assert_eq!(alice.get(&format!("/admin/game/{unpinned}/raw")).await.unwrap().status, StatusCode::NOT_FOUND);
+
+    assert_eq!(alice.delete(&format!("/admin/game/{pinned}/pin")).await.unwrap().json()["pinned"], false);
+    assert_eq!(alice.get("/admin/games?pinned=true").await.unwrap().json(), json!({ "ids": [] }));
+}
+
+#[tokio::test]
+async fn posted_turns_return_the_game_location_and_links_on_request() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    let posted = alice.post(&format!("/game/{gameid}"), turn.clone()).await.unwrap();
+    assert_eq!(posted.headers["location"], format!("/game/{gameid}").as_str());
+    assert!(posted.json().get("_links").is_none());
+
+    let posted = alice.post(&format!("/game/{gameid}?links=true"), turn).await.unwrap();
+    assert_eq!(posted.json()["_links"], json!({
+        "self": format!("/game/{gameid}"),
+        "history": format!("/game/{gameid}/history"),
+        "diff": format!("/game/{gameid}/diff"),
+        "annotations": format!("/game/{gameid}/annotations"),
+    }));
+    assert_eq!(alice.get(&format!("/game/{gameid}?links=true")).await.unwrap().json()["_links"]["self"], format!("/game/{gameid}"));
+}
+
+#[tokio::test]
+async fn turns_are_returned_without_envelope_on_request() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let path = format!("/game/{gameid}");
+
+    let empty = alice.get(&format!("{path}?envelope=false")).await.unwrap();
+    assert_eq!(empty.status, StatusCode::NOT_FOUND);
+    assert_eq!(empty.headers["x-broker-success"], "false");
+
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    let posted = alice.post(&format!("{path}?envelope=false"), turn.clone()).await.unwrap();
+    assert_eq!(posted.headers["x-broker-success"], "true");
+    assert_eq!(posted.json()["turn"], 1);
+    assert!(posted.json().get("success").is_none());
+
+    let fetched = alice.get(&format!("{path}?envelope=false")).await.unwrap();
+    assert_eq!(fetched.json()["to"], turn["to"]);
+    let wrapped = alice.get(&path).await.unwrap();
+    assert_eq!(wrapped.headers["x-broker-success"], "true");
+    assert_eq!(wrapped.json()["data"], fetched.json());
+
+    let missing = alice.get("/game/missing?envelope=false").await.unwrap();
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+    assert_eq!(missing.json(), json!({ "error": "game not found" }));
+}
+
+#[tokio::test]
+async fn reset_clears_the_games_only_when_allowed() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    assert_eq!(broker.client_as("alice").post("/admin/reset", json!({})).await.unwrap().status, StatusCode::NOT_FOUND);
+
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .with_user("bob", "bob password", Role::User)
+        .with_reset()
+        .build().await;
+    let alice = broker.client_as("alice");
+    assert_eq!(broker.client_as("bob").post("/admin/reset", json!({})).await.unwrap().status, StatusCode::UNAUTHORIZED);
+    let mut ids = vec![];
+    for _ in 0..2 {
+        ids.push(alice.get("/game").await.unwrap().body.trim().to_string());
+    }
+    assert_eq!(alice.post("/admin/games/archive", json!({ "ids": [ids[0]], "reason": "round_1_complete" })).await.unwrap().status, StatusCode::OK);
+    let reset = alice.post("/admin/reset", json!({})).await.unwrap().json();
+    assert_eq!(reset["games"], 1);
+    assert_eq!(reset["archived_games"], 1);
+    assert_eq!(reset["activity_events"], 2);
+    assert_eq!(alice.get("/admin/games").await.unwrap().json(), json!({ "ids": [] }));
+    assert_eq!(alice.get(&format!("/admin/archives/{}",ids[0])).await.unwrap().status, StatusCode::NOT_FOUND);
+    assert_eq!(alice.post("/admin/reset", json!({})).await.unwrap().json()["games"], 0);
+}
+
+#[tokio::test]
+async fn games_are_filtered_by_the_type_of_their_last_move() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let attacking = alice.get("/game").await.unwrap().body.trim().to_string();
+    let moving = alice.get("/game").await.unwrap().body.trim().to_string();
+    let turn = |turn: u16, move_type: &str| json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": turn, "move_type": move_type });
+    alice.post(&format!("/game/{attacking}"), turn(1, "move")).await.unwrap();
+    alice.post(&format!("/game/{attacking}"), turn(2, "attack")).await.unwrap();
+    alice.post(&format!("/game/{moving}"), turn(1, "attack")).await.unwrap();
+    alice.post(&format!("/game/{moving}"), turn(2, "move")).await.unwrap();
+
+    let attacks = alice.get("/games?move_type=attack").await.unwrap().json();
+    assert_eq!(attacks.as_object().unwrap().keys().collect::<Vec<_>>(), vec![&attacking]);
+    assert_eq!(attacks[&attacking]["turn"], 2);
+    assert_eq!(alice.get("/games").await.unwrap().json().as_object().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn games_are_renamed_by_admins() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let other = alice.get("/game").await.unwrap().body.trim().to_string();
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    alice.post(&format!("/game/{gameid}"), turn).await.unwrap();
+
+    let conflict = alice.post(&format!("/admin/game/{gameid}/rename"), json!({ "new_gameid": other })).await.unwrap();
+    assert_eq!(conflict.status, StatusCode::CONFLICT);
+    let invalid = alice.post(&format!("/admin/game/{gameid}/rename"), json!({ "new_gameid": "bracket r1" })).await.unwrap();
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let missing = alice.post("/admin/game/missing/rename", json!({ "new_gameid": "bracket_r1_g2" })).await.unwrap();
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+    let renamed = alice.post(&format!("/admin/game/{gameid}/rename"), json!({ "new_gameid": "bracket_r1_g2" })).await.unwrap();
+    assert_eq!(renamed.json(), json!({ "gameid": "bracket_r1_g2", "old_gameid": gameid }));
+    assert_eq!(alice.get("/game/bracket_r1_g2").await.unwrap().json()["data"]["turn"], 1);
+    assert_eq!(alice.get(&format!("/admin/game/{gameid}/raw")).await.unwrap().status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reserved_words_cannot_be_used_as_game_ids() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+
+    let posted = alice.post("/game/Admin", turn.clone()).await.unwrap();
+    assert_eq!(posted.status, StatusCode::CONFLICT);
+    assert_eq!(posted.json()["error"], "game ID 'Admin' is reserved");
+    let reserved = alice.post("/admin/reserve", json!({ "ids": ["round1", "HISTORY"] })).await.unwrap();
+    assert_eq!(reserved.status, StatusCode::CONFLICT);
+    assert_eq!(reserved.json()["error"], "game ID 'HISTORY' is reserved");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let renamed = alice.post(&format!("/admin/game/{gameid}/rename"), json!({ "new_gameid": "health" })).await.unwrap();
+    assert_eq!(renamed.status, StatusCode::CONFLICT);
+    assert_eq!(alice.get("/admin/games").await.unwrap().json(), json!({ "ids": [gameid] }));
+}
+
+#[cfg(feature = "qrcode")]
+#[tokio::test]
+async fn game_qr_codes_are_png_images() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+
+    let qr = alice.get(&format!("/game/{gameid}/qr")).await.unwrap();
+    assert_eq!(qr.status, StatusCode::OK);
+    assert_eq!(qr.headers[hyper::header::CONTENT_TYPE], "image/png");
+    assert!(qr.body.contains("PNG"));
+    assert_eq!(alice.get(&format!("/game/{gameid}/qr?size=2000")).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(alice.get(&format!("/game/{gameid}/qr?size=10")).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(alice.get("/game/missing/qr").await.unwrap().status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn submission_time_is_set_by_the_broker() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+    // a forged submission time is ignored
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1, "submitted_at_ms": 0, "submitted_at_iso": "1970-01-01T00:00:00.000Z" });
+    let posted = alice.post(&format!("/game/{gameid}"), turn).await.unwrap().json();
+    let submitted_at_ms = posted["data"]["submitted_at_ms"].as_u64().unwrap();
+    assert!(submitted_at_ms >= now_ms);
+    assert!(posted["data"]["submitted_at_iso"].as_str().unwrap().ends_with(&format!(".{:03}Z",submitted_at_ms % 1000)));
+    let game = alice.get(&format!("/game/{gameid}")).await.unwrap().json();
+    assert_eq!(game["data"]["submitted_at_ms"], submitted_at_ms);
+}
+
+#[tokio::test]
+async fn game_ids_are_generated_in_batches() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let batch = alice.post("/game/batch", json!({ "count": 3, "ttl_secs": 3600, "meta": { "description": "round 1" } })).await.unwrap();
+    assert_eq!(batch.status, StatusCode::OK);
+    let mut ids: Vec<String> = serde_json::from_value(batch.json()["ids"].clone()).unwrap();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 3);
+    let mut listed: Vec<String> = serde_json::from_value(alice.get("/admin/games").await.unwrap().json()["ids"].clone()).unwrap();
+    listed.sort();
+    assert_eq!(listed, ids);
+    assert_eq!(alice.get(&format!("/game/{}",ids[0])).await.unwrap().json()["meta"]["description"], "round 1");
+
+    assert_eq!(alice.post("/game/batch", json!({ "count": 0 })).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(alice.post("/game/batch", json!({ "count": 51 })).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(alice.post("/game/batch", json!({ "count": 1.5 })).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(broker.client().post("/game/batch", json!({ "count": 1 })).await.unwrap().status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn game_batch_ttl_is_limited_to_the_expiry_of_unplayed_games() {
+    // unplayed games expire after expires / 10
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .with_expires(36000)
+        .build().await;
+    let alice = broker.client_as("alice");
+    assert_eq!(alice.post("/game/batch", json!({ "count": 1, "ttl_secs": 3600 })).await.unwrap().status, StatusCode::OK);
+    assert_eq!(alice.post("/game/batch", json!({ "count": 1, "ttl_secs": 3601 })).await.unwrap().status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(alice.get("/admin/games").await.unwrap().json()["ids"].as_array().map(Vec::len), Some(1));
+}
+
+#[tokio::test]
+async fn game_batch_is_not_taken_for_a_game_id_in_upper_case_mode() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .with_game_id_case("upper")
+        .build().await;
+    let alice = broker.client_as("alice");
+    let batch = alice.post("/game/batch", json!({ "count": 2 })).await.unwrap();
+    assert_eq!(batch.status, StatusCode::OK);
+    let ids: Vec<String> = serde_json::from_value(batch.json()["ids"].clone()).unwrap();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.iter().all(|id| *id == id.to_uppercase()));
+}
+
+#[tokio::test]
+async fn identical_successive_posts_are_written_once() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::User)
+        .with_user("bob", "bob password", Role::User)
+        .build().await;
+    let (alice, bob) = (broker.client_as("alice"), broker.client_as("bob"));
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let path = format!("/game/{gameid}");
+    let turn = json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": 1 });
+    let first = alice.post(&path, turn.clone()).await.unwrap().json();
+    let retried = alice.post(&path, turn.clone()).await.unwrap();
+    assert_eq!(retried.status, StatusCode::OK);
+    assert_eq!(retried.json(), first);
+    let history = format!("/game/{gameid}/history");
+    assert_eq!(alice.get(&history).await.unwrap().json().as_array().unwrap().len(), 1);
+    // the same body from another user is written
+    assert_eq!(bob.post(&path, turn).await.unwrap().status, StatusCode::OK);
+    assert_eq!(alice.get(&history).await.unwrap().json().as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn cached_game_replies_are_dropped_when_a_turn_is_posted() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let alice = broker.client_as("alice");
+    let gameid = alice.get("/game").await.unwrap().body.trim().to_string();
+    let path = format!("/game/{gameid}");
+    let turn = |turn: u16| json!({ "from": { "row": 0, "col": 0 }, "to": { "row": 1, "col": 0 }, "turn": turn });
+    assert_eq!(alice.post(&path, turn(1)).await.unwrap().status, StatusCode::OK);
+
+    // stored by the 4th read (more than general.cache_min_reads), returned from the cache by the 5th
+    let mut replies = vec![];
+    for _ in 0..5 {
+        replies.push(alice.get(&path).await.unwrap().json());
+    }
+    assert!(replies.windows(2).all(|pair| pair[0] == pair[1]));
+    assert_eq!(replies[0]["data"]["turn"], 1);
+    assert_eq!(alice.post(&path, turn(2)).await.unwrap().status, StatusCode::OK);
+    assert_eq!(alice.get(&path).await.unwrap().json()["data"]["turn"], 2);
+
+    let stats = alice.get("/admin/cache/stats").await.unwrap().json();
+    assert_eq!(stats["enabled"], true);
+    assert_eq!(stats["hits"], 1);
+    assert_eq!(stats["misses"], 5);
+    assert_eq!(stats["cached_games"], 1);
+}
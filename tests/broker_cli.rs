@@ -0,0 +1,42 @@
+mod common;
+
+use common::{BrokerFixture, Role};
+use std::process::{Command, Output};
+
+fn broker_cli(broker: &BrokerFixture, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_broker-cli"))
+        .args(args)
+        .env("BROKER_URL", broker.base_url())
+        .env("BROKER_USERNAME", "alice")
+        .env("BROKER_PASSWORD", "alice password")
+        .output()
+        .expect("cannot run broker-cli")
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "broker-cli failed: {}",String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[tokio::test]
+async fn cli_plays_and_lists_games() {
+    let broker = BrokerFixture::builder()
+        .with_user("alice", "alice password", Role::Admin)
+        .build().await;
+    let gameid = stdout(&broker_cli(&broker, &["game", "new"])).trim().to_string();
+    assert!(!gameid.is_empty());
+
+    stdout(&broker_cli(&broker, &["game", "post", &gameid, "--from", "A0", "--to", "B1", "--turn", "1"]));
+    let table = stdout(&broker_cli(&broker, &["game", "get", &gameid]));
+    assert_eq!(table.lines().nth(2).unwrap().split_whitespace().collect::<Vec<_>>(), vec!["1", "A0", "B1"]);
+    let reply: serde_json::Value = serde_json::from_str(&stdout(&broker_cli(&broker, &["--json", "game", "get", &gameid]))).unwrap();
+    assert_eq!(reply["data"]["to"], serde_json::json!({ "row": 1, "col": 1 }));
+
+    let state = stdout(&broker_cli(&broker, &["admin", "state"]));
+    assert!(state.lines().any(|line| line.starts_with(&gameid)));
+    let rejected = broker_cli(&broker, &["game", "post", &gameid, "--from", "A0", "--to", "9Z", "--turn", "2"]);
+    assert!(!rejected.status.success());
+    stdout(&broker_cli(&broker, &["admin", "clear"]));
+    let state: serde_json::Value = serde_json::from_str(&stdout(&broker_cli(&broker, &["--json", "admin", "state"]))).unwrap();
+    assert_eq!(state, serde_json::json!({}));
+}
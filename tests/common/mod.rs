@@ -0,0 +1,208 @@
+// broker fixture for the integration tests: runs the broker binary on a free
+// local port with a generated config (in its own temporary directory) and
+// shuts it down when dropped, so each test gets a fresh broker without shared state
+// (not every test file uses every helper)
+#![allow(dead_code)]
+
+#[path = "../../src/testutil.rs"]
+pub mod testutil;
+
+use std::{net::TcpListener, path::PathBuf, process::{Child, Command, ExitStatus, Stdio}, time::{Duration, Instant}};
+use testutil::TestClient;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+// the broker is killed if it is not done by then
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// ports tried before giving up when another process takes the port first
+const BIND_ATTEMPTS: usize = 5;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Role {
+    Guest,
+    User,
+    Admin,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BrokerFixtureBuilder {
+    users: Vec<(String,String,Role)>,
+    expires_secs: Option<u64>,
+    max_turns_per_game: Option<u16>,
+    game_id_case: Option<String>,
+    allow_reset: bool,
+}
+
+impl BrokerFixtureBuilder {
+    pub fn with_user(mut self, name: &str, password: &str, role: Role) -> Self {
+        self.users.push((name.to_string(), password.to_string(), role));
+        self
+    }
+
+    // also starts the cleaner (every expires / 10 seconds, at least every second)
+    pub fn with_expires(mut self, expires_secs: u64) -> Self {
+        self.expires_secs = Some(expires_secs);
+        self
+    }
+
+    pub fn with_max_turns_per_game(mut self, max_turns: u16) -> Self {
+        self.max_turns_per_game = Some(max_turns);
+        self
+    }
+
+    // "lower", "upper" or "as-is"
+    pub fn with_game_id_case(mut self, case: &str) -> Self {
+        self.game_id_case = Some(case.to_string());
+        self
+    }
+
+    // enables POST /admin/reset
+    pub fn with_reset(mut self) -> Self {
+        self.allow_reset = true;
+        self
+    }
+
+    fn config(&self, port: u16) -> String {
+        let mut config = format!("[network]\nip = \"127.0.0.1\"\nport = {port}\n\n[general]\nunauthenticated = \"guest\"\n");
+        if let Some(expires_secs) = self.expires_secs {
+            config.push_str(&format!("expires = {expires_secs}\ncleanup = {}\n",(expires_secs / 10).max(1)));
+        }
+        if let Some(max_turns) = self.max_turns_per_game {
+            config.push_str(&format!("max_turns_per_game = {max_turns}\n"));
+        }
+        if let Some(case) = self.game_id_case.as_ref() {
+            config.push_str(&format!("game_id_case = {case:?}\n"));
+        }
+        if self.allow_reset {
+            config.push_str("allow_reset = true\n");
+        }
+        for (name,password,role) in self.users.iter() {
+            config.push_str(&format!("\n[[users]]\nname = {name:?}\npassword = {password:?}\nrole = \"{}\"\n",role.as_str()));
+        }
+        config
+    }
+
+    pub async fn build(self) -> BrokerFixture {
+        for _ in 0..BIND_ATTEMPTS {
+            // the port is released before the broker binds it (another process can take it in between)
+            let port = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("no free local port")
+                .port();
+            let mut fixture = self.start(port);
+            match fixture.wait_until_ready().await {
+                Ok(()) => return fixture,
+                // the broker could not bind the port: try another one
+                Err(_) if TcpListener::bind(("127.0.0.1", port)).is_err() => continue,
+                Err(status) => panic!("the broker exited during startup ({status})"),
+            }
+        }
+        panic!("the broker could not bind a free port in {BIND_ATTEMPTS} attempts");
+    }
+
+    fn start(&self, port: u16) -> BrokerFixture {
+        let dir = std::env::temp_dir().join(format!("ai_wargame_broker-test-{}-{port}",std::process::id()));
+        std::fs::create_dir_all(&dir).expect("cannot create the fixture directory");
+        // the broker reads <binary name>.toml from its working directory
+        std::fs::write(dir.join("ai_wargame_broker.toml"), self.config(port)).expect("cannot write the fixture config");
+        let process = Command::new(env!("CARGO_BIN_EXE_ai_wargame_broker"))
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("cannot start the broker");
+        BrokerFixture {
+            base_url: format!("http://127.0.0.1:{port}"),
+            users: self.users.clone(),
+            process,
+            dir,
+        }
+    }
+}
+
+pub struct BrokerFixture {
+    base_url: String,
+    users: Vec<(String,String,Role)>,
+    process: Child,
+    dir: PathBuf,
+}
+
+impl BrokerFixture {
+    pub fn builder() -> BrokerFixtureBuilder {
+        BrokerFixtureBuilder::default()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // unauthenticated client
+    pub fn client(&self) -> TestClient {
+        TestClient::new(&self.base_url)
+    }
+
+    // client authenticated as one of the users given to the builder
+    pub fn client_as(&self, name: &str) -> TestClient {
+        let (_,password,_) = self.users.iter().find(|(user,_,_)| user == name)
+            .unwrap_or_else(|| panic!("the fixture has no user {name:?}"));
+        self.client().with_auth(name, password)
+    }
+
+    // the exit status if the broker stopped during startup
+    async fn wait_until_ready(&mut self) -> Result<(),ExitStatus> {
+        let client = self.client();
+        let started = Instant::now();
+        loop {
+            if let Ok(Some(status)) = self.process.try_wait() {
+                return Err(status);
+            }
+            if client.get("/robots.txt").await.is_ok() {
+                return Ok(());
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!("the broker did not start listening on {} within {:?}",self.base_url,STARTUP_TIMEOUT);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    // SIGTERM (graceful shutdown of the broker), then SIGKILL if it is still running after
+    // SHUTDOWN_TIMEOUT; the exit status of the broker (also done when the fixture is dropped)
+    pub fn shutdown(&mut self) -> Option<ExitStatus> {
+        if let Ok(Some(status)) = self.process.try_wait() {
+            return Some(status);
+        }
+        #[cfg(unix)]
+        {
+            let terminated = Command::new("kill")
+                .args(["-TERM", &self.process.id().to_string()])
+                .status()
+                .is_ok_and(|status| status.success());
+            let started = Instant::now();
+            while terminated && started.elapsed() < SHUTDOWN_TIMEOUT {
+                if let Ok(Some(status)) = self.process.try_wait() {
+                    return Some(status);
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+        let _ = self.process.kill();
+        self.process.wait().ok()
+    }
+}
+
+impl Drop for BrokerFixture {
+    fn drop(&mut self) {
+        self.shutdown();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,44 @@
+#[path = "../src/rate.rs"]
+mod rate;
+
+use rate::TokenBucket;
+use std::{sync::Arc, thread, time::Duration};
+
+#[test]
+fn bucket_starts_full() {
+    let bucket = TokenBucket::new(10, 1.0);
+    assert!(bucket.tokens_available() >= 10.0);
+    assert!(bucket.consume(10));
+}
+
+#[test]
+fn tokens_refill_at_the_configured_rate() {
+    let bucket = TokenBucket::new(10, 100.0);
+    assert!(bucket.consume(10));
+    assert!(!bucket.consume(1));
+    thread::sleep(Duration::from_millis(50));
+    let available = bucket.tokens_available();
+    assert!((4.0..=10.0).contains(&available), "{available} tokens after 50ms at 100/s");
+    assert!(bucket.consume(4));
+}
+
+#[test]
+fn burst_capacity_is_honored() {
+    let bucket = TokenBucket::new(5, 1000.0);
+    thread::sleep(Duration::from_millis(20));
+    assert!(bucket.tokens_available() <= 5.0);
+    assert!(!bucket.consume(6));
+    assert!(bucket.consume(5));
+}
+
+#[test]
+fn concurrent_access_does_not_lose_tokens() {
+    let bucket = Arc::new(TokenBucket::new(1000, 0.0));
+    let workers: Vec<_> = (0..8).map(|_| {
+        let bucket = bucket.clone();
+        thread::spawn(move || (0..200).filter(|_| bucket.consume(1)).count())
+    }).collect();
+    let consumed: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+    assert_eq!(consumed, 1000);
+    assert_eq!(bucket.tokens_available(), 0.0);
+}